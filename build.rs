@@ -0,0 +1,13 @@
+extern crate rustc_serialize as serialize;
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+mod codegen;
+
+fn main() {
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    if let Err(err) = codegen::generate(Path::new("models"), &out) {
+        panic!("couldn't generate the service clients: {}", err);
+    }
+}
@@ -0,0 +1,937 @@
+use std::collections::BTreeSet;
+
+use super::model::{Member, Model, Operation, Paginator, Shape, XmlNamespace};
+
+const KEYWORDS: [&'static str; 38] = [
+    "abstract", "alignof", "as", "become", "box", "break", "const", "continue", "crate", "do",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "offsetof", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "type", "where"];
+
+// Names the generated code uses itself, shapes called this get a suffix.
+const RESERVED_TYPES: [&'static str; 14] = [
+    "ApiClient", "AwsError", "BTreeMap", "Client", "Config", "ConfigError", "Items", "Method",
+    "Option", "Paginator", "Param", "Result", "String", "Vec"];
+
+#[derive(Clone,Copy,PartialEq)]
+enum Protocol {
+    Query,
+    Ec2,
+    RestXml,
+}
+
+struct Generator<'a> {
+    model: &'a Model,
+    protocol: Protocol,
+    out: String,
+}
+
+// The structure a REST-XML operation sends as its body, and the root element
+// it goes in.
+struct XmlBody<'a> {
+    shape: &'a String,
+    element: String,
+    namespace: Option<String>,
+    /// The payload member holding it, or none when it's the whole input.
+    payload: Option<&'a String>,
+}
+
+/// The Rust source of the client for `service`.
+pub fn generate(service: &str, model: &Model) -> String {
+    let protocol = match &model.protocol[..] {
+        "query" => Protocol::Query,
+        "ec2" => Protocol::Ec2,
+        "rest-xml" => Protocol::RestXml,
+        other => panic!("{}: the {} protocol isn't supported yet", service, other),
+    };
+    let mut gen = Generator { model: model, protocol: protocol, out: String::new() };
+
+    let mut inputs = BTreeSet::new();
+    let mut outputs = BTreeSet::new();
+    let mut xml_inputs = BTreeSet::new();
+    for op in model.operations.values() {
+        if let Some(input) = gen.input(op) {
+            gen.reachable(input, &mut inputs);
+        }
+        if let Some(output) = gen.output(op) {
+            gen.reachable(output, &mut outputs);
+        }
+        if let Some(body) = gen.xml_body(op) {
+            gen.reachable(body.shape, &mut xml_inputs);
+        }
+    }
+
+    gen.line(&format!("pub const API_VERSION: &'static str = {:?};", model.api_version));
+    gen.line("");
+    gen.client();
+    for op in model.operations.values() {
+        gen.error(op);
+    }
+    let structures: BTreeSet<&String> = inputs.union(&outputs).cloned().collect();
+    for name in structures {
+        gen.structure(name, inputs.contains(&name), outputs.contains(&name), xml_inputs.contains(&name));
+    }
+
+    let mut source = format!("// Generated by build.rs from models/{}, don't edit.\n\n", service);
+    for import in gen.imports() {
+        source.push_str(&format!("use {};\n", import));
+    }
+    source.push('\n');
+    source.push_str(&gen.out);
+    source
+}
+
+impl<'a> Generator<'a> {
+    fn line(&mut self, line: &str) {
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    // Only what the generated code uses, so it builds without warnings.
+    fn imports(&self) -> Vec<&'static str> {
+        let uses = |s: &str| self.out.contains(s);
+        let mut imports = vec!["std::error::Error as StdError", "std::fmt"];
+        if uses("BTreeMap<") {
+            imports.insert(0, "std::collections::BTreeMap");
+        }
+        if uses("Method::") {
+            imports.push("hyper::method::Method");
+        }
+        if uses("Decodable") {
+            imports.push("serialize::{Decodable, Decoder}");
+        }
+        if uses(".to_base64(") {
+            imports.push("serialize::base64::{ToBase64, STANDARD}");
+        }
+        imports.extend(vec!["config::{Config, ConfigError}", "error::AwsError"]);
+        match (uses("impl ToParam"), uses("QueryRequest::")) {
+            (true, true) => imports.push("query::{Param, QueryRequest, ToParam}"),
+            (true, false) => imports.push("query::{Param, ToParam}"),
+            (false, true) => imports.push("query::QueryRequest"),
+            (false, false) => (),
+        }
+        match (uses("Paginator<"), uses("Items<")) {
+            (true, true) => imports.push("paginate::{Items, Paginator}"),
+            (true, false) => imports.push("paginate::Paginator"),
+            _ => (),
+        }
+        imports.push("request::ApiClient");
+        if uses("RestRequest::") {
+            imports.push("rest::RestRequest");
+        }
+        if uses("xml::") {
+            imports.push("xml");
+        }
+        if uses("impl ToXml") {
+            imports.push("xml::ToXml");
+        }
+        imports
+    }
+
+    // Shapes without members are treated as no input or output at all.
+    fn input(&self, op: &'a Operation) -> Option<&'a String> {
+        self.with_members(op.input.as_ref())
+    }
+
+    fn output(&self, op: &'a Operation) -> Option<&'a String> {
+        self.with_members(op.output.as_ref())
+    }
+
+    fn with_members(&self, name: Option<&'a String>) -> Option<&'a String> {
+        name.and_then(|n| if self.model.shape(n).members.is_empty() { None } else { Some(n) })
+    }
+
+    fn reachable(&self, name: &'a String, seen: &mut BTreeSet<&'a String>) {
+        let model = self.model;
+        let shape = model.shape(name);
+        match &shape.kind[..] {
+            "structure" if !shape.exception => {
+                if seen.insert(name) {
+                    for &(_, ref member) in shape.members.iter() {
+                        self.reachable(&member.shape, seen);
+                    }
+                }
+            },
+            "list" => self.reachable(&shape.member.as_ref().unwrap().shape, seen),
+            "map" => self.reachable(&shape.value.as_ref().unwrap().shape, seen),
+            _ => (),
+        }
+    }
+
+    // The structure sent as the XML body of `op`: its payload member when the
+    // payload is a structure, or the input itself when members go in the body
+    // without a payload, as with Route 53.
+    fn xml_body(&self, op: &'a Operation) -> Option<XmlBody<'a>> {
+        if self.protocol != Protocol::RestXml {
+            return None
+        }
+        let input = match self.input(op) {
+            Some(input) => input,
+            None => return None,
+        };
+        let shape = self.model.shape(input);
+        match shape.payload {
+            Some(ref payload) => shape.member(payload).and_then(|member| {
+                if self.model.shape(&member.shape).kind != "structure" {
+                    return None
+                }
+                Some(XmlBody {
+                    shape: &member.shape,
+                    element: member.location_name.clone().unwrap_or(payload.clone()),
+                    namespace: member.xml_namespace.as_ref().map(|ns| ns.uri.clone()),
+                    payload: Some(payload),
+                })
+            }),
+            None if shape.members.iter().any(|&(_, ref m)| m.location.is_none()) => Some(XmlBody {
+                shape: input,
+                element: op.input_location_name.clone().unwrap_or(input.clone()),
+                namespace: op.input_namespace.as_ref().map(|ns| ns.uri.clone()),
+                payload: None,
+            }),
+            None => None,
+        }
+    }
+
+    fn rust_type(&self, name: &str) -> String {
+        let shape = self.model.shape(name);
+        match &shape.kind[..] {
+            "string" | "timestamp" => "String".to_string(),
+            "blob" => "Vec<u8>".to_string(),
+            "boolean" => "bool".to_string(),
+            "integer" => "i32".to_string(),
+            "long" => "i64".to_string(),
+            "double" | "float" => "f64".to_string(),
+            "list" | "map" if self.is_blob(shape.member.as_ref().or(shape.value.as_ref()).unwrap()) => {
+                panic!("{}: lists and maps of blobs aren't supported yet", name)
+            },
+            "list" => format!("Vec<{}>", self.rust_type(&shape.member.as_ref().unwrap().shape)),
+            "map" => format!("BTreeMap<String, {}>", self.rust_type(&shape.value.as_ref().unwrap().shape)),
+            "structure" => type_name(name),
+            other => panic!("{} has unsupported type {}", name, other),
+        }
+    }
+
+    fn is_container(&self, member: &Member) -> bool {
+        let kind = &self.model.shape(&member.shape).kind;
+        kind == "list" || kind == "map"
+    }
+
+    fn is_string(&self, member: &Member) -> bool {
+        self.rust_type(&member.shape) == "String"
+    }
+
+    fn is_blob(&self, member: &Member) -> bool {
+        self.model.shape(&member.shape).kind == "blob"
+    }
+
+    // Lists and maps are empty rather than missing, and so are required
+    // members of inputs, anything else may not be there.
+    fn field_type(&self, member: &Member, required: bool) -> String {
+        if required || self.is_container(member) {
+            self.rust_type(&member.shape)
+        } else {
+            format!("Option<{}>", self.rust_type(&member.shape))
+        }
+    }
+
+    fn client(&mut self) {
+        let model = self.model;
+        self.doc(&format!("A client for {}, generated from the {} model.", model.service_name, model.api_version), "");
+        self.line("#[derive(Clone,Debug)]");
+        self.line("pub struct Client {");
+        self.line("    client: ApiClient,");
+        self.line("}");
+        self.line("");
+        self.line("impl Client {");
+        self.line("    pub fn new(client: ApiClient) -> Client {");
+        self.line("        Client { client: client }");
+        self.line("    }");
+        self.line("");
+        self.line("    pub fn from_config(config: &Config) -> Result<Client, ConfigError> {");
+        self.line(&format!("        ApiClient::from_config(config, {:?}).map(Client::new)", model.endpoint_prefix));
+        self.line("    }");
+        for op in model.operations.values() {
+            self.line("");
+            self.operation(op);
+            if self.paginated(op).is_some() {
+                self.line("");
+                self.pages_method(op);
+            }
+        }
+        self.line("}");
+        self.line("");
+    }
+
+    fn signature(&self, op: &'a Operation, name: &str, returns: &str) -> String {
+        match self.input(op) {
+            Some(input) => format!("    pub fn {}(&self, input: &{}) -> {} {{", name, type_name(input), returns),
+            None => format!("    pub fn {}(&self) -> {} {{", name, returns),
+        }
+    }
+
+    fn operation(&mut self, op: &'a Operation) {
+        if let Some(ref doc) = op.documentation {
+            self.doc(doc, "    ");
+        }
+        let output = self.output(op).map_or("()".to_string(), |o| type_name(o));
+        let returns = format!("Result<{}, {}Error>", output, op.name);
+        let signature = self.signature(op, &snake_case(&op.name), &returns);
+        self.line(&signature);
+        match self.protocol {
+            Protocol::Query | Protocol::Ec2 => self.query_operation(op),
+            Protocol::RestXml => self.rest_operation(op),
+        }
+        self.line("    }");
+    }
+
+    fn query_operation(&mut self, op: &'a Operation) {
+        let input = if self.input(op).is_some() { ".input(input)" } else { "" };
+        self.line(&format!("        let request = QueryRequest::new({:?}, API_VERSION){};", op.name, input));
+        if self.output(op).is_some() {
+            self.line("        Ok(try!(self.client.call_decoded(&request)).result)");
+        } else {
+            self.line("        try!(self.client.call(&request));");
+            self.line("        Ok(())");
+        }
+    }
+
+    fn rest_operation(&mut self, op: &'a Operation) {
+        let model = self.model;
+        let method = match &op.method[..] {
+            "GET" => "Get",
+            "PUT" => "Put",
+            "POST" => "Post",
+            "DELETE" => "Delete",
+            "HEAD" => "Head",
+            "PATCH" => "Patch",
+            other => panic!("{} uses unsupported method {}", op.name, other),
+        };
+        let mut chained = vec![format!("RestRequest::new(Method::{}, {:?})", method, op.request_uri)];
+        let mut optional = Vec::new();
+
+        if let Some(input) = self.input(op) {
+            let shape = model.shape(input);
+            let payload = shape.payload.as_ref().and_then(|p| shape.member(p).map(|m| (p, m)));
+            let blob_payload = payload.map_or(false, |(_, m)| {
+                let kind = &model.shape(&m.shape).kind;
+                kind == "blob" || kind == "string"
+            });
+            let xml_body = self.xml_body(op);
+            let mut content_type = None;
+
+            for &(ref name, ref member) in shape.members.iter() {
+                let field = format!("input.{}", snake_case(name));
+                let wire = member.location_name.clone().unwrap_or(name.clone());
+                let required = shape.required.contains(name);
+                match member.location.as_ref().map(|l| &l[..]) {
+                    Some("uri") => {
+                        let value = if !required {
+                            format!("{}.as_ref().map_or(\"\", |v| &v[..])", field)
+                        } else if self.is_string(member) {
+                            format!("&{}", field)
+                        } else {
+                            format!("&{}.to_string()", field)
+                        };
+                        chained.push(format!(".label({:?}, {})", wire, value));
+                    },
+                    Some("header") if blob_payload && wire.to_lowercase() == "content-type" => {
+                        content_type = Some(field);
+                    },
+                    Some(location @ "querystring") | Some(location @ "header") => {
+                        let add = if location == "header" { "header" } else { "query" };
+                        if self.is_container(member) {
+                            optional.push(format!("for v in {}.iter() {{", field));
+                            optional.push(format!("    request = request.{}({:?}, &v.to_string());", add, wire));
+                            optional.push("}".to_string());
+                        } else if required {
+                            chained.push(format!(".{}({:?}, &{}.to_string())", add, wire, field));
+                        } else {
+                            optional.push(format!("if let Some(ref v) = {} {{", field));
+                            optional.push(format!("    request = request.{}({:?}, &v.to_string());", add, wire));
+                            optional.push("}".to_string());
+                        }
+                    },
+                    Some("headers") => {
+                        optional.push(format!("for (k, v) in {}.iter() {{", field));
+                        optional.push(format!("    request = request.header(&format!(\"{}{{}}\", k), v);", wire));
+                        optional.push("}".to_string());
+                    },
+                    Some(other) => panic!("{} has {} in unsupported location {}", op.name, name, other),
+                    // the payload and body members are sent below
+                    None => (),
+                }
+            }
+
+            match (payload, xml_body) {
+                (Some((name, member)), None) => {
+                    let field = format!("input.{}", snake_case(name));
+                    let content_type = content_type
+                        .map(|c| format!("{}.as_ref().map_or(\"application/octet-stream\", |t| &t[..])", c))
+                        .unwrap_or("\"application/octet-stream\"".to_string());
+                    let bytes = if self.is_blob(member) { "" } else { ".as_bytes()" };
+                    if shape.required.contains(name) {
+                        chained.push(format!(".body(&{}{}, {})", field, bytes, content_type));
+                    } else {
+                        optional.push(format!("if let Some(ref body) = {} {{", field));
+                        optional.push(format!("    request = request.body(body{}, {});", bytes, content_type));
+                        optional.push("}".to_string());
+                    }
+                },
+                (_, Some(body)) => {
+                    let document = |value: &str| {
+                        format!("xml_body(&xml::document({}, {:?}, {:?}))", value, body.element, body.namespace)
+                    };
+                    match body.payload {
+                        Some(name) if shape.required.contains(name) => {
+                            chained.push(format!(".{}", document(&format!("&input.{}", snake_case(name)))));
+                        },
+                        Some(name) => {
+                            optional.push(format!("if let Some(ref body) = input.{} {{", snake_case(name)));
+                            optional.push(format!("    request = request.{};", document("body")));
+                            optional.push("}".to_string());
+                        },
+                        None => chained.push(format!(".{}", document("input"))),
+                    }
+                },
+                (None, None) => (),
+            }
+        }
+
+        let binding = if optional.is_empty() { "let request" } else { "let mut request" };
+        if chained.len() == 1 {
+            self.line(&format!("        {} = {};", binding, chained[0]));
+        } else {
+            self.line(&format!("        {} = {}", binding, chained[0]));
+            let last = chained.len() - 1;
+            for (i, call) in chained.iter().enumerate().skip(1) {
+                self.line(&format!("            {}{}", call, if i == last { ";" } else { "" }));
+            }
+        }
+        for line in optional {
+            self.line(&format!("        {}", line));
+        }
+
+        let output = match self.output(op) {
+            Some(output) => output,
+            None => {
+                self.line("        try!(self.client.call_rest(&request));");
+                self.line("        Ok(())");
+                return
+            },
+        };
+        let shape = model.shape(output);
+        let mut assignments = Vec::new();
+        let mut in_body = false;
+        for &(ref name, ref member) in shape.members.iter() {
+            let field = format!("output.{}", snake_case(name));
+            let wire = member.location_name.clone().unwrap_or(name.clone());
+            match member.location.as_ref().map(|l| &l[..]) {
+                Some("header") if self.is_string(member) => {
+                    assignments.push(format!("{} = response.header({:?});", field, wire));
+                },
+                Some("header") => {
+                    assignments.push(format!("{} = response.header({:?}).and_then(|v| v.parse().ok());", field, wire));
+                },
+                Some("headers") => {
+                    assignments.push(format!("{} = response.prefixed_headers({:?});", field, wire));
+                },
+                Some("statusCode") => {
+                    assignments.push(format!("{} = Some(response.status as i32);", field));
+                },
+                None if shape.payload.as_ref() == Some(name) => {
+                    let value = match &model.shape(&member.shape).kind[..] {
+                        "blob" => "response.body.clone()",
+                        "string" => "response.body_str()",
+                        "structure" => "try!(xml::parse(&response.body_str()).and_then(|root| xml::decode(&root)).map_err(AwsError::from))",
+                        other => panic!("{}: {} payloads aren't supported", op.name, other),
+                    };
+                    assignments.push(format!("{} = Some({});", field, value));
+                },
+                _ => in_body = true,
+            }
+        }
+
+        let name = type_name(output);
+        let decoded = if in_body {
+            "try!(xml::parse(&response.body_str()).and_then(|root| xml::decode(&root)).map_err(AwsError::from))".to_string()
+        } else {
+            format!("{}::default()", name)
+        };
+        self.line("        let response = try!(self.client.call_rest(&request));");
+        if assignments.is_empty() {
+            self.line(&format!("        let output: {} = {};", name, decoded));
+        } else {
+            self.line(&format!("        let mut output: {} = {};", name, decoded));
+            for assignment in assignments {
+                self.line(&format!("        {}", assignment));
+            }
+        }
+        self.line("        Ok(output)");
+    }
+
+    // The paginator for `op`, when it can be followed with plain fields.
+    fn paginated(&self, op: &'a Operation) -> Option<&'a Paginator> {
+        let paginator = match self.model.paginators.get(&op.name) {
+            Some(paginator) => paginator,
+            None => return None,
+        };
+        let (input, output) = match (self.input(op), self.output(op)) {
+            (Some(input), Some(output)) => (self.model.shape(input), self.model.shape(output)),
+            _ => return None,
+        };
+        let usable = input.member(&paginator.input_token).map_or(false, |m| self.is_string(m)) &&
+            !input.required.contains(&paginator.input_token) &&
+            output.member(&paginator.output_token).map_or(false, |m| self.is_string(m));
+        if usable { Some(paginator) } else { None }
+    }
+
+    fn pages_method(&mut self, op: &'a Operation) {
+        let paginator = self.paginated(op).unwrap();
+        let output = type_name(self.output(op).unwrap());
+        let name = snake_case(&op.name);
+        let pages = format!("Paginator<{}, {}Error>", output, op.name);
+
+        self.doc(&format!("Every page of `{}`, following `{}`.", op.name, paginator.output_token), "    ");
+        let signature = self.signature(op, &format!("{}_pages", name), &pages);
+        self.line(&signature);
+        self.line("        let (client, mut input) = (self.clone(), input.clone());");
+        self.line(&format!("        Paginator::new(move |token: Option<&str>| -> Result<({}, Option<String>), {}Error> {{", output, op.name));
+        self.line("            if let Some(token) = token {");
+        self.line(&format!("                input.{} = Some(token.to_string());", snake_case(&paginator.input_token)));
+        self.line("            }");
+        self.line(&format!("            let page = try!(client.{}(&input));", name));
+        let next = format!("page.{}.clone()", snake_case(&paginator.output_token));
+        match paginator.more_results {
+            Some(ref more) => self.line(&format!("            let next = if page.{}.unwrap_or(false) {{ {} }} else {{ None }};", snake_case(more), next)),
+            None => self.line(&format!("            let next = {};", next)),
+        }
+        self.line("            Ok((page, next))");
+        self.line("        })");
+        self.line("    }");
+
+        // A single list of results can be iterated over item by item.
+        let model = self.model;
+        let output_shape = model.shape(self.output(op).unwrap());
+        if paginator.result_keys.len() != 1 {
+            return
+        }
+        let key = &paginator.result_keys[0];
+        let item = match output_shape.member(key).map(|m| model.shape(&m.shape)) {
+            Some(shape) if shape.kind == "list" => self.rust_type(&shape.member.as_ref().unwrap().shape),
+            _ => return,
+        };
+        self.line("");
+        self.doc(&format!("Every item in the `{}` of each page of `{}`.", key, op.name), "    ");
+        let signature = self.signature(op, &format!("{}_items", name), &format!("Items<{}, {}Error, {}>", output, op.name, item));
+        self.line(&signature);
+        self.line(&format!("        self.{}_pages(input).items(|page| page.{})", name, snake_case(key)));
+        self.line("    }");
+    }
+
+    fn error(&mut self, op: &'a Operation) {
+        let model = self.model;
+        let name = format!("{}Error", op.name);
+        let errors: Vec<(String, String)> = op.errors.iter().map(|e| {
+            let code = model.shape(e).error_code.clone().unwrap_or(e.clone());
+            (type_name(e), code)
+        }).collect();
+
+        self.doc(&format!("An error returned by `{}`, the ones in the model get their own variant.", op.name), "");
+        self.line("#[derive(Debug)]");
+        self.line(&format!("pub enum {} {{", name));
+        for &(ref variant, _) in errors.iter() {
+            self.line(&format!("    {}(AwsError),", variant));
+        }
+        self.line("    Other(AwsError),");
+        self.line("}");
+        self.line("");
+        self.line(&format!("impl {} {{", name));
+        self.line("    pub fn aws_error(&self) -> &AwsError {");
+        self.line("        match *self {");
+        for &(ref variant, _) in errors.iter() {
+            self.line(&format!("            {}::{}(ref err) => err,", name, variant));
+        }
+        self.line(&format!("            {}::Other(ref err) => err,", name));
+        self.line("        }");
+        self.line("    }");
+        self.line("}");
+        self.line("");
+        self.line(&format!("impl From<AwsError> for {} {{", name));
+        self.line(&format!("    fn from(err: AwsError) -> {} {{", name));
+        if errors.is_empty() {
+            self.line(&format!("        {}::Other(err)", name));
+        } else {
+            self.line("        match &err.code[..] {");
+            for &(ref variant, ref code) in errors.iter() {
+                self.line(&format!("            {:?} => {}::{}(err),", code, name, variant));
+            }
+            self.line(&format!("            _ => {}::Other(err),", name));
+            self.line("        }");
+        }
+        self.line("    }");
+        self.line("}");
+        self.line("");
+        self.line(&format!("impl fmt::Display for {} {{", name));
+        self.line("    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {");
+        self.line("        fmt::Display::fmt(self.aws_error(), f)");
+        self.line("    }");
+        self.line("}");
+        self.line("");
+        self.line(&format!("impl StdError for {} {{", name));
+        self.line("    fn description(&self) -> &str {");
+        self.line("        self.aws_error().description()");
+        self.line("    }");
+        self.line("}");
+        self.line("");
+    }
+
+    fn structure(&mut self, name: &str, input: bool, output: bool, xml: bool) {
+        let model = self.model;
+        let shape = model.shape(name);
+        let rust_name = type_name(name);
+        if let Some(ref doc) = shape.documentation {
+            self.doc(doc, "");
+        }
+        self.line("#[derive(Clone,Debug,Default,PartialEq)]");
+        if shape.members.is_empty() {
+            self.line(&format!("pub struct {};", rust_name));
+        } else {
+            self.line(&format!("pub struct {} {{", rust_name));
+            for &(ref member_name, ref member) in shape.members.iter() {
+                let required = input && !output && shape.required.contains(member_name);
+                let field_type = self.field_type(member, required);
+                self.line(&format!("    pub {}: {},", snake_case(member_name), field_type));
+            }
+            self.line("}");
+        }
+        self.line("");
+
+        if input && self.protocol != Protocol::RestXml {
+            self.to_param(name, shape, output);
+        }
+        if xml {
+            self.to_xml(name, shape, input && !output);
+        }
+        // outputs made only of headers and payloads are never decoded
+        let in_body = shape.members.iter().any(|&(ref n, ref m)| m.location.is_none() && shape.payload.as_ref() != Some(n));
+        if output && (in_body || shape.members.is_empty()) {
+            self.decodable(name, shape);
+        }
+    }
+
+    fn to_param(&mut self, name: &str, shape: &Shape, output: bool) {
+        self.line(&format!("impl ToParam for {} {{", type_name(name)));
+        self.line("    fn to_param(&self) -> Param {");
+        if shape.members.is_empty() {
+            self.line("        Param::Struct(Vec::new())");
+        } else {
+            self.line("        let mut fields = Vec::new();");
+            for &(ref member_name, ref member) in shape.members.iter() {
+                let key = self.query_name(member_name, member);
+                let field = format!("self.{}", snake_case(member_name));
+                if self.is_container(member) {
+                    self.line(&format!("        if !{}.is_empty() {{", field));
+                    let value = self.param_expr(&member.shape, &field, 0);
+                    self.line(&format!("            fields.push(({:?}.to_string(), {}));", key, value));
+                    self.line("        }");
+                } else if !output && shape.required.contains(member_name) {
+                    let value = self.param_expr(&member.shape, &field, 0);
+                    self.line(&format!("        fields.push(({:?}.to_string(), {}));", key, value));
+                } else {
+                    self.line(&format!("        if let Some(ref v) = {} {{", field));
+                    let value = self.param_expr(&member.shape, "v", 0);
+                    self.line(&format!("            fields.push(({:?}.to_string(), {}));", key, value));
+                    self.line("        }");
+                }
+            }
+            self.line("        Param::Struct(fields)");
+        }
+        self.line("    }");
+        self.line("}");
+        self.line("");
+    }
+
+    fn param_expr(&self, shape_name: &str, value: &str, depth: usize) -> String {
+        let shape = self.model.shape(shape_name);
+        match &shape.kind[..] {
+            "list" => {
+                let item = format!("v{}", depth);
+                let kind = if self.protocol == Protocol::Ec2 || shape.flattened { "List" } else { "Members" };
+                let member = self.param_expr(&shape.member.as_ref().unwrap().shape, &item, depth + 1);
+                format!("Param::{}({}.iter().map(|{}| {}).collect())", kind, value, item, member)
+            },
+            "map" => {
+                let (key, val) = (shape.key.as_ref().unwrap(), shape.value.as_ref().unwrap());
+                let (k, v) = (format!("k{}", depth), format!("v{}", depth));
+                let entry = format!("Param::Struct(vec![({:?}.to_string(), {}), ({:?}.to_string(), {})])",
+                                    key.location_name.as_ref().map_or("key", |n| &n[..]),
+                                    self.param_expr(&key.shape, &k, depth + 1),
+                                    val.location_name.as_ref().map_or("value", |n| &n[..]),
+                                    self.param_expr(&val.shape, &v, depth + 1));
+                let entries = format!("Param::List({}.iter().map(|({}, {})| {}).collect())", value, k, v, entry);
+                if shape.flattened {
+                    entries
+                } else {
+                    format!("Param::Struct(vec![(\"entry\".to_string(), {})])", entries)
+                }
+            },
+            "blob" => format!("Param::Value({}.to_base64(STANDARD))", value),
+            _ => format!("{}.to_param()", value),
+        }
+    }
+
+    // Structures write their own tags, so namespaces and attributes can go in
+    // the opening one. Members sent in the URI or headers are left out.
+    fn to_xml(&mut self, name: &str, shape: &Shape, required_fields: bool) {
+        self.line(&format!("impl ToXml for {} {{", type_name(name)));
+        self.line("    fn to_xml(&self, name: &str, out: &mut String) {");
+        let attributes: Vec<&(String, Member)> = shape.members.iter()
+            .filter(|&&(_, ref m)| m.location.is_none() && m.xml_attribute)
+            .collect();
+        if shape.xml_namespace.is_none() && attributes.is_empty() {
+            self.line("        out.push_str(&format!(\"<{}>\", name));");
+        } else {
+            self.line("        out.push_str(&format!(\"<{}\", name));");
+            if let Some(ref ns) = shape.xml_namespace {
+                self.line(&format!("        out.push_str({:?});", namespace_attribute(ns)));
+            }
+            for &&(ref member_name, ref member) in attributes.iter() {
+                let wire = format!(" {}=\"{{}}\"", member.location_name.clone().unwrap_or(member_name.clone()));
+                let value = if self.is_string(member) { "xml::escape(v)" } else { "v" };
+                let field = format!("self.{}", snake_case(member_name));
+                if required_fields && shape.required.contains(member_name) {
+                    self.line(&format!("        let v = &{};", field));
+                    self.line(&format!("        out.push_str(&format!({:?}, {}));", wire, value));
+                } else {
+                    self.line(&format!("        if let Some(ref v) = {} {{", field));
+                    self.line(&format!("            out.push_str(&format!({:?}, {}));", wire, value));
+                    self.line("        }");
+                }
+            }
+            self.line("        out.push('>');");
+        }
+        for &(ref member_name, ref member) in shape.members.iter() {
+            if member.location.is_some() || member.xml_attribute {
+                continue
+            }
+            let wire = member.location_name.clone().unwrap_or(member_name.clone());
+            let field = format!("self.{}", snake_case(member_name));
+            let member_shape = self.model.shape(&member.shape);
+            match &member_shape.kind[..] {
+                "list" => {
+                    let element = member_shape.member.as_ref().unwrap();
+                    self.nested_xml(element);
+                    let item = element.location_name.clone().unwrap_or("member".to_string());
+                    if member_shape.flattened {
+                        self.line(&format!("        for v in {}.iter() {{", field));
+                        self.line(&format!("            v.to_xml({:?}, out);", wire));
+                        self.line("        }");
+                    } else {
+                        self.line(&format!("        if !{}.is_empty() {{", field));
+                        self.line(&format!("            out.push_str(\"<{}>\");", wire));
+                        self.line(&format!("            for v in {}.iter() {{", field));
+                        self.line(&format!("                v.to_xml({:?}, out);", item));
+                        self.line("            }");
+                        self.line(&format!("            out.push_str(\"</{}>\");", wire));
+                        self.line("        }");
+                    }
+                },
+                "map" => {
+                    let (key, value) = (member_shape.key.as_ref().unwrap(), member_shape.value.as_ref().unwrap());
+                    self.nested_xml(value);
+                    let entry = if member_shape.flattened { wire.clone() } else { "entry".to_string() };
+                    self.line(&format!("        if !{}.is_empty() {{", field));
+                    if !member_shape.flattened {
+                        self.line(&format!("            out.push_str(\"<{}>\");", wire));
+                    }
+                    self.line(&format!("            for (k, v) in {}.iter() {{", field));
+                    self.line(&format!("                out.push_str(\"<{}>\");", entry));
+                    self.line(&format!("                k.to_xml({:?}, out);", key.location_name.as_ref().map_or("key", |n| &n[..])));
+                    self.line(&format!("                v.to_xml({:?}, out);", value.location_name.as_ref().map_or("value", |n| &n[..])));
+                    self.line(&format!("                out.push_str(\"</{}>\");", entry));
+                    self.line("            }");
+                    if !member_shape.flattened {
+                        self.line(&format!("            out.push_str(\"</{}>\");", wire));
+                    }
+                    self.line("        }");
+                },
+                _ if required_fields && shape.required.contains(member_name) => {
+                    self.line(&format!("        {}.to_xml({:?}, out);", field, wire));
+                },
+                _ => {
+                    self.line(&format!("        if let Some(ref v) = {} {{", field));
+                    self.line(&format!("            v.to_xml({:?}, out);", wire));
+                    self.line("        }");
+                },
+            }
+        }
+        self.line("        out.push_str(&format!(\"</{}>\", name));");
+        self.line("    }");
+        self.line("}");
+        self.line("");
+    }
+
+    // Lists and maps are written by the structure holding them, so they can't
+    // hold other lists or maps directly.
+    fn nested_xml(&self, element: &Member) {
+        let kind = &self.model.shape(&element.shape).kind;
+        if kind == "list" || kind == "map" {
+            panic!("{}: lists and maps of lists or maps can't be sent as XML yet", element.shape)
+        }
+    }
+
+    // EC2 capitalizes the XML name, the other Query services use it as is,
+    // except flattened lists which are named after their elements.
+    fn query_name(&self, name: &str, member: &Member) -> String {
+        if self.protocol == Protocol::Ec2 {
+            return member.query_name.clone()
+                .or(member.location_name.as_ref().map(|n| capitalize(n)))
+                .unwrap_or(name.to_string())
+        }
+        self.xml_name(name, member)
+    }
+
+    // Lists wrapped in anything but `item` or `member` elements, like S3's
+    // `<Buckets><Bucket>`, are read through a path to the elements.
+    fn xml_name(&self, name: &str, member: &Member) -> String {
+        let shape = self.model.shape(&member.shape);
+        let wire = member.location_name.clone().unwrap_or(name.to_string());
+        let element = match shape.member {
+            Some(ref element) if shape.kind == "list" => element.location_name.clone(),
+            _ => None,
+        };
+        match element {
+            Some(element) if shape.flattened => element,
+            Some(element) if element != "item" && element != "member" => format!("{}.{}", wire, element),
+            _ => wire,
+        }
+    }
+
+    // Fields are read by their XML names rather than derived from the field
+    // names, members that don't come from the body are filled in afterwards.
+    fn decodable(&mut self, name: &str, shape: &Shape) {
+        let rust_name = type_name(name);
+        self.line(&format!("impl Decodable for {} {{", rust_name));
+        self.line(&format!("    fn decode<D: Decoder>(d: &mut D) -> Result<{}, D::Error> {{", rust_name));
+        if shape.members.is_empty() {
+            self.line(&format!("        d.read_struct({:?}, 0, |_| Ok({}))", name, rust_name));
+        } else {
+            self.line(&format!("        d.read_struct({:?}, {}, |d| {{", name, shape.members.len()));
+            self.line(&format!("            Ok({} {{", rust_name));
+            for (i, &(ref member_name, ref member)) in shape.members.iter().enumerate() {
+                let field = snake_case(member_name);
+                let outside_body = member.location.is_some() || shape.payload.as_ref() == Some(member_name);
+                if outside_body {
+                    self.line(&format!("                {}: Default::default(),", field));
+                } else if self.is_blob(member) {
+                    let wire = self.xml_name(member_name, member);
+                    self.line(&format!("                {}: try!(d.read_struct_field({:?}, {}, |d| {{", field, wire, i));
+                    self.line("                    d.read_option(|d, present| if present { xml::read_blob(d).map(Some) } else { Ok(None) })");
+                    self.line("                })),");
+                } else {
+                    let wire = self.xml_name(member_name, member);
+                    self.line(&format!("                {}: try!(d.read_struct_field({:?}, {}, |d| Decodable::decode(d))),",
+                                       field, wire, i));
+                }
+            }
+            self.line("            })");
+            self.line("        })");
+        }
+        self.line("    }");
+        self.line("}");
+        self.line("");
+    }
+
+    fn doc(&mut self, text: &str, indent: &str) {
+        let mut line = String::new();
+        for word in strip_html(text).split_whitespace() {
+            if !line.is_empty() && indent.len() + line.len() + word.len() > 92 {
+                self.line(&format!("{}/// {}", indent, line));
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        if !line.is_empty() {
+            self.line(&format!("{}/// {}", indent, line));
+        }
+    }
+}
+
+// `xmlns` for a default namespace, `xmlns:prefix` otherwise.
+fn namespace_attribute(ns: &XmlNamespace) -> String {
+    match ns.prefix {
+        Some(ref prefix) => format!(" xmlns:{}=\"{}\"", prefix, ns.uri),
+        None => format!(" xmlns=\"{}\"", ns.uri),
+    }
+}
+
+fn type_name(name: &str) -> String {
+    let mut rust_name = capitalize(name);
+    if RESERVED_TYPES.contains(&&rust_name[..]) {
+        rust_name.push_str("Shape");
+    }
+    rust_name
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// `DescribeInstances` to `describe_instances`, keeping acronyms together:
+/// `MD5OfBody` is `md5_of_body` and `QueueOwnerAWSAccountId` is
+/// `queue_owner_aws_account_id`.
+pub fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let next_lower = chars.get(i + 1).map_or(false, |n| n.is_lowercase());
+            let boundary = i > 0 && match chars[i - 1] {
+                p if p.is_lowercase() || p.is_numeric() => true,
+                p if p.is_uppercase() => next_lower,
+                _ => false,
+            };
+            if boundary {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else if c.is_alphanumeric() {
+            out.push(c);
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    if KEYWORDS.contains(&&out[..]) {
+        out.push('_');
+    }
+    out
+}
+
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut tag = None;
+    for c in html.chars() {
+        match (c, tag.take()) {
+            ('<', None) => tag = Some(String::new()),
+            ('>', Some(name)) => {
+                let name = name.trim_matches('/').split_whitespace().next().unwrap_or("").to_string();
+                if ["p", "br", "li", "ul", "ol"].contains(&&name[..]) {
+                    text.push(' ');
+                }
+            },
+            (c, Some(mut name)) => {
+                name.push(c);
+                tag = Some(name);
+            },
+            (c, None) => text.push(c),
+        }
+    }
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
@@ -0,0 +1,64 @@
+//! Generates the clients in `aws::services` from the botocore models in
+//! `models/`, one `<service>.rs` for each `models/<service>/service-2.json`.
+//! A `paginators-1.json` next to the model adds iterators over the pages of
+//! those operations.
+//!
+//! The Query, EC2 and REST-XML protocols are supported, JSON ones aren't yet.
+//! Blobs are `Vec<u8>`, sent as they are when they're the payload and in
+//! base64 otherwise. Lists and maps of blobs, and XML lists holding other
+//! lists or maps, stop the build with a panic naming the shape.
+//!
+//! The models checked in are cut down from botocore's: a few operations per
+//! service, and only some of the members of their shapes.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use serialize::json::Json;
+
+mod emit;
+mod model;
+
+use self::model::Model;
+
+pub fn generate(models: &Path, out: &Path) -> io::Result<()> {
+    let mut dirs = Vec::new();
+    for entry in try!(fs::read_dir(models)) {
+        dirs.push(try!(entry).path());
+    }
+    dirs.sort();
+
+    for dir in dirs {
+        let service = match dir.file_name().and_then(|n| n.to_str()) {
+            Some(service) => service.to_string(),
+            None => continue,
+        };
+        let service_path = dir.join("service-2.json");
+        if !service_path.is_file() {
+            continue
+        }
+        let service_json = try!(read_json(&service_path));
+        let paginators_path = dir.join("paginators-1.json");
+        let paginators = if paginators_path.is_file() {
+            Some(try!(read_json(&paginators_path)))
+        } else {
+            None
+        };
+        let model = try!(Model::from_json(&service_json, paginators.as_ref())
+            .map_err(|e| invalid(&service_path, &e)));
+
+        let mut file = try!(File::create(out.join(format!("{}.rs", service))));
+        try!(file.write_all(emit::generate(&service, &model).as_bytes()));
+    }
+    Ok(())
+}
+
+fn read_json(path: &Path) -> io::Result<Json> {
+    let mut contents = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut contents));
+    Json::from_str(&contents).map_err(|e| invalid(path, &e.to_string()))
+}
+
+fn invalid(path: &Path, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+}
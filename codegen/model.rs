@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use serialize::json::Json;
+
+/// A botocore service model, `service-2.json`, with its `paginators-1.json`.
+///
+/// Only what the generator uses is read, the rest of the model is ignored.
+#[derive(Debug)]
+pub struct Model {
+    pub protocol: String,
+    pub api_version: String,
+    pub endpoint_prefix: String,
+    pub service_name: String,
+    pub operations: BTreeMap<String, Operation>,
+    pub shapes: BTreeMap<String, Shape>,
+    pub paginators: BTreeMap<String, Paginator>,
+}
+
+#[derive(Debug)]
+pub struct Operation {
+    pub name: String,
+    pub method: String,
+    pub request_uri: String,
+    pub input: Option<String>,
+    /// The root element of an input sent whole as an XML body, as Route 53's are.
+    pub input_location_name: Option<String>,
+    pub input_namespace: Option<XmlNamespace>,
+    pub output: Option<String>,
+    pub errors: Vec<String>,
+    pub documentation: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Shape {
+    pub kind: String,
+    /// Structure members, in name order since that's all JSON objects keep.
+    pub members: Vec<(String, Member)>,
+    pub required: Vec<String>,
+    /// The element of a list.
+    pub member: Option<Member>,
+    pub key: Option<Member>,
+    pub value: Option<Member>,
+    pub flattened: bool,
+    pub payload: Option<String>,
+    pub exception: bool,
+    /// The code errors of this shape come back with, when it isn't the shape name.
+    pub error_code: Option<String>,
+    pub xml_namespace: Option<XmlNamespace>,
+    pub documentation: Option<String>,
+}
+
+#[derive(Clone,Debug)]
+pub struct Member {
+    pub shape: String,
+    /// `uri`, `querystring`, `header` or `headers` for REST protocols.
+    pub location: Option<String>,
+    pub location_name: Option<String>,
+    pub query_name: Option<String>,
+    pub xml_namespace: Option<XmlNamespace>,
+    /// Sent as an attribute of the structure's element rather than a child.
+    pub xml_attribute: bool,
+}
+
+#[derive(Clone,Debug)]
+pub struct XmlNamespace {
+    pub prefix: Option<String>,
+    pub uri: String,
+}
+
+#[derive(Debug)]
+pub struct Paginator {
+    pub input_token: String,
+    pub output_token: String,
+    pub limit_key: Option<String>,
+    pub more_results: Option<String>,
+    pub result_keys: Vec<String>,
+}
+
+impl Model {
+    pub fn from_json(service: &Json, paginators: Option<&Json>) -> Result<Model, String> {
+        let metadata = try!(service.find("metadata").ok_or("no metadata".to_string()));
+        let mut model = Model {
+            protocol: try!(required(metadata, "protocol")),
+            api_version: try!(required(metadata, "apiVersion")),
+            endpoint_prefix: try!(required(metadata, "endpointPrefix")),
+            service_name: string(metadata, "serviceFullName")
+                .or(string(metadata, "serviceId"))
+                .unwrap_or(String::new()),
+            operations: BTreeMap::new(),
+            shapes: BTreeMap::new(),
+            paginators: BTreeMap::new(),
+        };
+
+        for (name, op) in object(service, "operations") {
+            let http = try!(op.find("http").ok_or(format!("{} has no http binding", name)));
+            model.operations.insert(name.clone(), Operation {
+                name: name.clone(),
+                method: try!(required(http, "method")),
+                request_uri: try!(required(http, "requestUri")),
+                input: op.find("input").and_then(|i| string(i, "shape")),
+                input_location_name: op.find("input").and_then(|i| string(i, "locationName")),
+                input_namespace: op.find("input").and_then(xml_namespace),
+                output: op.find("output").and_then(|o| string(o, "shape")),
+                errors: array(op, "errors").iter().filter_map(|e| string(e, "shape")).collect(),
+                documentation: string(op, "documentation"),
+            });
+        }
+
+        for (name, shape) in object(service, "shapes") {
+            let mut members = Vec::new();
+            for (member_name, member) in object(shape, "members") {
+                members.push((member_name.clone(), try!(Member::from_json(member))));
+            }
+            model.shapes.insert(name.clone(), Shape {
+                kind: try!(required(shape, "type")),
+                members: members,
+                required: array(shape, "required").iter().filter_map(|r| r.as_string()).map(|r| r.to_string()).collect(),
+                member: try!(optional_member(shape, "member")),
+                key: try!(optional_member(shape, "key")),
+                value: try!(optional_member(shape, "value")),
+                flattened: boolean(shape, "flattened"),
+                payload: string(shape, "payload"),
+                exception: boolean(shape, "exception"),
+                error_code: shape.find("error").and_then(|e| string(e, "code")),
+                xml_namespace: xml_namespace(shape),
+                documentation: string(shape, "documentation"),
+            });
+        }
+
+        if let Some(paginators) = paginators {
+            for (name, paginator) in object(paginators, "pagination") {
+                // Tokens made of JMESPath expressions, like S3's ListObjects,
+                // can't be followed with a field, leave those out.
+                let (input_token, output_token) = match (string(paginator, "input_token"),
+                                                         string(paginator, "output_token")) {
+                    (Some(input), Some(output)) => (input, output),
+                    _ => continue,
+                };
+                let result_keys = match paginator.find("result_key") {
+                    Some(&Json::String(ref key)) => vec![key.clone()],
+                    Some(&Json::Array(ref keys)) => keys.iter().filter_map(|k| k.as_string()).map(|k| k.to_string()).collect(),
+                    _ => Vec::new(),
+                };
+                model.paginators.insert(name.clone(), Paginator {
+                    input_token: input_token,
+                    output_token: output_token,
+                    limit_key: string(paginator, "limit_key"),
+                    more_results: string(paginator, "more_results"),
+                    result_keys: result_keys,
+                });
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn shape(&self, name: &str) -> &Shape {
+        match self.shapes.get(name) {
+            Some(shape) => shape,
+            None => panic!("the model refers to an undefined shape {}", name),
+        }
+    }
+}
+
+impl Shape {
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        self.members.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref m)| m)
+    }
+}
+
+impl Member {
+    fn from_json(json: &Json) -> Result<Member, String> {
+        Ok(Member {
+            shape: try!(required(json, "shape")),
+            location: string(json, "location"),
+            location_name: string(json, "locationName"),
+            query_name: string(json, "queryName"),
+            xml_namespace: xml_namespace(json),
+            xml_attribute: boolean(json, "xmlAttribute"),
+        })
+    }
+}
+
+fn xml_namespace(json: &Json) -> Option<XmlNamespace> {
+    json.find("xmlNamespace").and_then(|ns| string(ns, "uri").map(|uri| {
+        XmlNamespace { prefix: string(ns, "prefix"), uri: uri }
+    }))
+}
+
+fn optional_member(json: &Json, key: &str) -> Result<Option<Member>, String> {
+    match json.find(key) {
+        Some(member) => Member::from_json(member).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn string(json: &Json, key: &str) -> Option<String> {
+    json.find(key).and_then(|v| v.as_string()).map(|v| v.to_string())
+}
+
+fn required(json: &Json, key: &str) -> Result<String, String> {
+    string(json, key).ok_or(format!("missing {}", key))
+}
+
+fn boolean(json: &Json, key: &str) -> bool {
+    json.find(key).and_then(|v| v.as_boolean()).unwrap_or(false)
+}
+
+fn object<'a>(json: &'a Json, key: &str) -> Vec<(&'a String, &'a Json)> {
+    json.find(key).and_then(|v| v.as_object()).map(|o| o.iter().collect()).unwrap_or(Vec::new())
+}
+
+fn array<'a>(json: &'a Json, key: &str) -> Vec<&'a Json> {
+    json.find(key).and_then(|v| v.as_array()).map(|a| a.iter().collect()).unwrap_or(Vec::new())
+}
@@ -1,21 +1,230 @@
 extern crate aws;
 
-#[macro_use]
 extern crate log;
 extern crate env_logger;
+extern crate rustc_serialize;
+use aws::config::Config;
 use aws::request::ApiClient;
-use aws::credentials::Credentials;
-use std::io::Read;
+use aws::profile::{default_profile, set_profile_value, Profile, Profiles};
+use aws::providers::CredentialsError;
+use aws::providers::mfa::MfaProvider;
+use aws::providers::sts::StsOptions;
+use aws::providers::sso::{device_login, SsoConfig};
+use aws::query::QueryRequest;
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+const EC2_VERSION: &'static str = "2016-11-15";
 
 pub fn main() {
     env_logger::init().unwrap();
-    let cred = Credentials::new().load();
-    let region = "eu-west-1";
-    let service = "ec2";
-
-    let client = ApiClient::new(cred, region, service);
-    let res = client.get("DescribeInstances");
-    let mut output = String::new();
-    res.unwrap().read_to_string(&mut output);
-    info!("{:?}", output)
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match (args.get(0).map(|a| a.as_ref()), args.get(1).map(|a| a.as_ref())) {
+        (Some("sso"), Some("login")) => sso_login(&args[2..]),
+        (Some("configure"), Some("list-profiles")) => list_profiles(),
+        (Some("configure"), Some("list")) => configure_list(&args[2..]),
+        (Some("configure"), Some("set")) => configure_set(&args[2..]),
+        (Some("configure"), _) => configure(&args[1..]),
+        _ => describe_instances(),
+    }
+}
+
+fn describe_instances() {
+    let mut config = Config::new().load();
+    if let Some(provider) = MfaProvider::from_profile(&config.profile_name(), Box::new(mfa_prompt)) {
+        let provider = provider.sts_options(StsOptions::from_config(&config));
+        config = config.credentials_provider(provider);
+    }
+
+    let client = ApiClient::from_config(&config, "ec2").unwrap_or_else(|e| fail(&e.to_string()));
+    let request = QueryRequest::new("DescribeInstances", EC2_VERSION);
+    let reservations = client.paginate::<DescribeInstances>(&request, "NextToken", "nextToken")
+        .items(|page| page.reservations);
+    for reservation in reservations {
+        let reservation = reservation.unwrap_or_else(|e| fail(&e.to_string()));
+        for instance in reservation.instances.iter() {
+            println!("{}\t{}\t{}\t{}", instance.instance_id, instance.instance_type,
+                     instance.instance_state.name, instance.private_ip_address.as_ref().map_or("-", |ip| ip));
+        }
+    }
+}
+
+#[derive(RustcDecodable)]
+struct DescribeInstances {
+    reservations: Vec<Reservation>,
+}
+
+#[derive(RustcDecodable)]
+struct Reservation {
+    instances: Vec<Instance>,
+}
+
+#[derive(RustcDecodable)]
+struct Instance {
+    instance_id: String,
+    instance_type: String,
+    instance_state: InstanceState,
+    private_ip_address: Option<String>,
+}
+
+#[derive(RustcDecodable)]
+struct InstanceState {
+    name: String,
+}
+
+fn sso_login(args: &[String]) {
+    let profile = flag(args, "--profile").unwrap_or_else(default_profile);
+    let config = match SsoConfig::from_profile(&profile) {
+        Some(config) => config,
+        None => fail(&format!("profile {} has no sso_session or sso_start_url configured", profile)),
+    };
+
+    // for the proxy, CA bundle and timeouts
+    let client = Config::new().profile(&profile).no_imds().load().http_client();
+    let token = device_login(&*client, &config, |auth| {
+        println!("Attempting to open the SSO authorization page in your browser.");
+        println!("If it does not open, visit:\n\n{}\n", auth.verification_uri);
+        println!("Then enter the code:\n\n{}\n", auth.user_code);
+        if let Some(ref uri) = auth.verification_uri_complete {
+            let _ = process::Command::new(opener()).arg(uri).status();
+        }
+    });
+    match token {
+        Ok(_) => println!("Successfully logged into Start URL: {}", config.start_url),
+        Err(e) => fail(&e.to_string()),
+    }
+}
+
+fn list_profiles() {
+    for name in Profiles::load().names() {
+        println!("{}", name);
+    }
+}
+
+fn configure_list(args: &[String]) {
+    // as with `Credentials::load`, keys in the environment don't apply to --profile
+    let explicit = flag(args, "--profile");
+    let name = explicit.clone().unwrap_or_else(default_profile);
+    let resolved = match explicit {
+        Some(_) => Profiles::load().resolve_explicit(&name),
+        None => Profiles::load().resolve(&name),
+    };
+    let profile = match resolved {
+        Ok(profile) => profile,
+        Err(errors) => {
+            for e in errors {
+                let _ = writeln!(io::stderr(), "{}", e);
+            }
+            process::exit(1)
+        }
+    };
+
+    println!("{:>10} {:>24} {}", "Name", "Value", "Location");
+    println!("{:>10} {:>24} {}", "----", "-----", "--------");
+    println!("{:>10} {:>24} {}", "profile", name, profile_source(explicit.is_some()));
+    list_value(&profile, "access_key", "aws_access_key_id", true);
+    list_value(&profile, "secret_key", "aws_secret_access_key", true);
+    list_value(&profile, "region", "region", false);
+}
+
+// Where the profile name came from, in the words of `aws configure list`.
+fn profile_source(explicit: bool) -> &'static str {
+    let set = |name: &str| env::var(name).map(|v| !v.is_empty()).unwrap_or(false);
+    if explicit {
+        "manual --profile"
+    } else if set("AWS_PROFILE") {
+        "env AWS_PROFILE"
+    } else if set("AWS_DEFAULT_PROFILE") {
+        "env AWS_DEFAULT_PROFILE"
+    } else {
+        ""
+    }
+}
+
+fn list_value(profile: &Profile, label: &str, key: &str, masked: bool) {
+    match (profile.get(key), profile.source(key)) {
+        (Some(value), Some(source)) => {
+            let shown = if masked { mask(value) } else { value.to_string() };
+            println!("{:>10} {:>24} {}", label, shown, source)
+        },
+        _ => println!("{:>10} {:>24} {}", label, "<not set>", ""),
+    }
+}
+
+fn mask(value: &str) -> String {
+    let tail: String = value.chars().rev().take(4).collect::<Vec<_>>()
+        .into_iter().rev().collect();
+    format!("****************{}", tail)
+}
+
+// `aws configure set key value [--profile name]`
+fn configure_set(args: &[String]) {
+    let positional: Vec<&String> = args.iter().enumerate()
+        .filter(|&(i, a)| !a.starts_with("--") && (i == 0 || args[i - 1] != "--profile"))
+        .map(|(_, a)| a)
+        .collect();
+    if positional.len() != 2 {
+        fail("usage: aws configure set <key> <value> [--profile <name>]");
+    }
+    let profile = flag(args, "--profile").unwrap_or_else(default_profile);
+    if let Err(e) = set_profile_value(&profile, positional[0], positional[1]) {
+        fail(&e.to_string());
+    }
+}
+
+// Interactive `aws configure`, keeping current values when nothing is entered.
+fn configure(args: &[String]) {
+    let profile = flag(args, "--profile").unwrap_or_else(default_profile);
+    let current = Profiles::load().resolve(&profile).ok();
+    let prompts = [("aws_access_key_id", "AWS Access Key ID", true),
+                   ("aws_secret_access_key", "AWS Secret Access Key", true),
+                   ("region", "Default region name", false),
+                   ("output", "Default output format", false)];
+
+    for &(key, label, masked) in prompts.iter() {
+        let existing = current.as_ref().and_then(|p| p.get(key));
+        let shown = match existing {
+            Some(value) if masked => mask(value),
+            Some(value) => value.to_string(),
+            None => "None".to_string(),
+        };
+        print!("{} [{}]: ", label, shown);
+        io::stdout().flush().unwrap();
+        let mut value = String::new();
+        io::stdin().read_line(&mut value).unwrap();
+        let value = value.trim();
+        if value.is_empty() {
+            continue
+        }
+        if let Err(e) = set_profile_value(&profile, key, value) {
+            fail(&e.to_string());
+        }
+    }
+}
+
+fn mfa_prompt(serial: &str) -> Result<String, CredentialsError> {
+    print!("Enter MFA code for {}: ", serial);
+    try!(io::stdout().flush());
+    let mut code = String::new();
+    try!(io::stdin().read_line(&mut code));
+    Ok(code.trim().to_string())
+}
+
+// Value of a `--name value` style option.
+fn flag(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(|v| v.to_string())
+}
+
+fn opener() -> &'static str {
+    if cfg!(target_os = "macos") { "open" } else { "xdg-open" }
+}
+
+fn fail(message: &str) -> ! {
+    let _ = writeln!(io::stderr(), "{}", message);
+    process::exit(1)
 }
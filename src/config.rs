@@ -0,0 +1,499 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use credentials::Credentials;
+use endpoints::Variant;
+use non_empty_var;
+use profile::{config_path, default_profile, expand_home, profile_value, section_value};
+use providers::{default_provider, CredentialsError, ProvideCredentials, SharedProvider};
+use region::RegionChain;
+use retry::{RetryMode, RetryStrategy};
+use transport::{HttpClient, HyperClient, SharedClient, TransportOptions};
+
+/// Settings shared by the credential providers and the API clients.
+///
+/// Build one with the builder methods and `load` it once, which fills in
+/// whatever wasn't set explicitly from the environment and the shared files.
+/// Cloning is cheap: the credentials provider and the HTTP client, with its
+/// pooled connections, are shared between the clones.
+#[derive(Clone)]
+pub struct Config {
+    /// The region requests go to, `None` if it couldn't be found.
+    pub region: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub user_agent: String,
+    profile: Option<String>,
+    imds: bool,
+    endpoints: BTreeMap<String, String>,
+    endpoint: Option<String>,
+    provider: Option<SharedProvider>,
+    max_attempts: Option<u32>,
+    retry_mode: Option<RetryMode>,
+    retry: Option<Arc<RetryStrategy>>,
+    fips: Option<bool>,
+    dualstack: Option<bool>,
+    ca_bundle: Option<PathBuf>,
+    client: Option<SharedClient>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            region: None,
+            connect_timeout: None,
+            read_timeout: None,
+            user_agent: format!("aws-rs/{}", env!("CARGO_PKG_VERSION")),
+            profile: None,
+            imds: true,
+            endpoints: BTreeMap::new(),
+            endpoint: None,
+            provider: None,
+            max_attempts: None,
+            retry_mode: None,
+            retry: None,
+            fips: None,
+            dualstack: None,
+            ca_bundle: None,
+            client: None,
+        }
+    }
+
+    pub fn profile(mut self, profile: &str) -> Config {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    pub fn region(mut self, region: &str) -> Config {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    /// Don't ask the EC2 instance metadata service for the region.
+    pub fn no_imds(mut self) -> Config {
+        self.imds = false;
+        self
+    }
+
+    pub fn credentials_provider<P>(mut self, provider: P) -> Config
+        where P: ProvideCredentials + Send + Sync + 'static {
+        self.provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Send requests for `service` to `url` instead of the regular AWS endpoint.
+    pub fn endpoint_url(mut self, service: &str, url: &str) -> Config {
+        self.endpoints.insert(service.to_string(), url.to_string());
+        self
+    }
+
+    /// Send requests for every service to `url`, such as a LocalStack instance.
+    pub fn default_endpoint_url(mut self, url: &str) -> Config {
+        self.endpoint = Some(url.to_string());
+        self
+    }
+
+    pub fn max_attempts(mut self, attempts: u32) -> Config {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    pub fn retry_mode(mut self, mode: RetryMode) -> Config {
+        self.retry_mode = Some(mode);
+        self
+    }
+
+    pub fn use_fips_endpoint(mut self, fips: bool) -> Config {
+        self.fips = Some(fips);
+        self
+    }
+
+    pub fn use_dualstack_endpoint(mut self, dualstack: bool) -> Config {
+        self.dualstack = Some(dualstack);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Config {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> Config {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Trust the certificates in this PEM file instead of the system ones.
+    pub fn ca_bundle(mut self, path: &str) -> Config {
+        self.ca_bundle = Some(PathBuf::from(path));
+        self
+    }
+
+    /// Send requests with `client` instead of the default hyper transport.
+    pub fn transport<C>(mut self, client: C) -> Config
+        where C: HttpClient + 'static {
+        self.client = Some(Arc::new(client));
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Config {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// The profile in use, either the one set with `profile` or the default one.
+    pub fn profile_name(&self) -> String {
+        self.profile.clone().unwrap_or_else(default_profile)
+    }
+
+    /// Resolve the region and the credentials provider, see `RegionChain` and
+    /// `providers::default_provider` for the order things are looked up in.
+    ///
+    /// Retries are set up from `AWS_MAX_ATTEMPTS` and `AWS_RETRY_MODE`, or the
+    /// profile's `max_attempts` and `retry_mode`, unless set explicitly. FIPS and
+    /// dual-stack endpoints likewise come from `AWS_USE_FIPS_ENDPOINT` and
+    /// `AWS_USE_DUALSTACK_ENDPOINT`, or `use_fips_endpoint` and `use_dualstack_endpoint`,
+    /// and the CA bundle from `AWS_CA_BUNDLE` or `ca_bundle`.
+    ///
+    /// The default credentials provider shares the HTTP client. The region is
+    /// looked up from instance metadata through a `transport` set explicitly,
+    /// otherwise through a client of its own that gives up after a second.
+    pub fn load(mut self) -> Config {
+        let mut chain = RegionChain::new(&self.profile_name());
+        if let Some(ref region) = self.region {
+            chain = chain.region(region);
+        }
+        if !self.imds {
+            chain = chain.no_imds();
+        }
+        if let Some(ref client) = self.client {
+            chain = chain.http_client(client.clone());
+        }
+        self.region = chain.resolve();
+        let profile = self.profile_name();
+        let setting = |var: &str, key: &str| {
+            non_empty_var(var).or_else(|| profile_value(&profile, key))
+        };
+        if self.max_attempts.is_none() {
+            self.max_attempts = setting("AWS_MAX_ATTEMPTS", "max_attempts")
+                .and_then(|v| v.parse().ok());
+        }
+        if self.retry_mode.is_none() {
+            self.retry_mode = setting("AWS_RETRY_MODE", "retry_mode").and_then(|v| {
+                let mode = RetryMode::parse(&v);
+                if mode.is_none() {
+                    warn!("Ignoring unknown retry mode {}", v);
+                }
+                mode
+            });
+        }
+        if self.fips.is_none() {
+            self.fips = setting("AWS_USE_FIPS_ENDPOINT", "use_fips_endpoint")
+                .map(|v| v.to_lowercase() == "true");
+        }
+        if self.dualstack.is_none() {
+            self.dualstack = setting("AWS_USE_DUALSTACK_ENDPOINT", "use_dualstack_endpoint")
+                .map(|v| v.to_lowercase() == "true");
+        }
+        if self.ca_bundle.is_none() {
+            self.ca_bundle = setting("AWS_CA_BUNDLE", "ca_bundle")
+                .map(|path| PathBuf::from(expand_home(&path)));
+        }
+        self.retry = Some(Arc::new(self.new_retry_strategy()));
+        if self.client.is_none() {
+            self.client = Some(Arc::new(self.new_http_client()));
+        }
+        if self.provider.is_none() {
+            let provider = default_provider(self.profile.as_ref().map(|p| p.as_ref()), &self);
+            self.provider = Some(provider);
+        }
+        self
+    }
+
+    /// Whether to use FIPS and dual-stack endpoints.
+    pub fn endpoint_variant(&self) -> Variant {
+        Variant {
+            fips: self.fips.unwrap_or(false),
+            dualstack: self.dualstack.unwrap_or(false),
+        }
+    }
+
+    /// The retry strategy shared by everything built from this config.
+    pub fn retry_strategy(&self) -> Arc<RetryStrategy> {
+        match self.retry {
+            Some(ref retry) => retry.clone(),
+            None => Arc::new(self.new_retry_strategy()),
+        }
+    }
+
+    fn new_retry_strategy(&self) -> RetryStrategy {
+        RetryStrategy::new(self.retry_mode.unwrap_or(RetryMode::Standard),
+                           self.max_attempts.unwrap_or(3))
+    }
+
+    /// The credentials provider shared by everything built from this config.
+    pub fn provider(&self) -> SharedProvider {
+        match self.provider {
+            Some(ref provider) => provider.clone(),
+            None => default_provider(self.profile.as_ref().map(|p| p.as_ref()), self),
+        }
+    }
+
+    /// Credentials from the configured provider.
+    pub fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        self.provider().credentials()
+    }
+
+    /// The endpoint URL configured for `service`, if any.
+    ///
+    /// In order: `endpoint_url` for the service, `default_endpoint_url`, then
+    /// `AWS_ENDPOINT_URL_<SERVICE>`, `AWS_ENDPOINT_URL`, the service's
+    /// `endpoint_url` in the `[services name]` section the profile points at and
+    /// the profile's own `endpoint_url`. Only the explicit settings are used when
+    /// `AWS_IGNORE_CONFIGURED_ENDPOINT_URLS` or the profile's
+    /// `ignore_configured_endpoint_urls` is `true`.
+    pub fn endpoint_for(&self, service: &str) -> Option<String> {
+        if let Some(url) = self.endpoints.get(service).or(self.endpoint.as_ref()) {
+            return Some(url.clone())
+        }
+        let profile = self.profile_name();
+        let ignore = non_empty_var("AWS_IGNORE_CONFIGURED_ENDPOINT_URLS")
+            .or_else(|| profile_value(&profile, "ignore_configured_endpoint_urls"));
+        if ignore.map_or(false, |v| v.to_lowercase() == "true") {
+            return None
+        }
+
+        let var = format!("AWS_ENDPOINT_URL_{}", service.to_uppercase().replace("-", "_"));
+        non_empty_var(&var)
+            .or_else(|| non_empty_var("AWS_ENDPOINT_URL"))
+            .or_else(|| services_endpoint(&profile, service))
+            .or_else(|| profile_value(&profile, "endpoint_url"))
+    }
+
+    /// The HTTP client shared by everything built from this config, with its
+    /// pooled connections.
+    pub fn http_client(&self) -> SharedClient {
+        match self.client {
+            Some(ref client) => client.clone(),
+            None => Arc::new(self.new_http_client()),
+        }
+    }
+
+    fn new_http_client(&self) -> HyperClient {
+        HyperClient::new(TransportOptions {
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            ca_bundle: self.ca_bundle.clone(),
+            no_proxy: false,
+        })
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("region", &self.region)
+            .field("profile", &self.profile)
+            .field("endpoints", &self.endpoints)
+            .field("endpoint", &self.endpoint)
+            .field("max_attempts", &self.max_attempts)
+            .field("retry_mode", &self.retry_mode)
+            .field("fips", &self.fips)
+            .field("dualstack", &self.dualstack)
+            .field("user_agent", &self.user_agent)
+            .field("ca_bundle", &self.ca_bundle)
+            .finish()
+    }
+}
+
+/// An unloaded config for tests, with the keys from the fixture credentials
+/// file and no instance metadata lookups.
+#[cfg(test)]
+pub fn test_config(region: &str) -> Config {
+    Config::new()
+        .region(region)
+        .no_imds()
+        .credentials_provider(Credentials::new().path("fixtures/credentials.ini").load())
+}
+
+// Settings for a service in a `[services name]` section are nested under its name:
+//
+//     [services local]
+//     ec2 =
+//       endpoint_url = http://localhost:4566
+fn services_endpoint(profile: &str, service: &str) -> Option<String> {
+    let name = match profile_value(profile, "services") {
+        Some(name) => name,
+        None => return None,
+    };
+    let nested = match section_value(&config_path(), &format!("services {}", name), service) {
+        Some(nested) => nested,
+        None => return None,
+    };
+    nested.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.trim() == "endpoint_url" => {
+                    Some(value.trim().to_string())
+                },
+                _ => None,
+            }
+        })
+        .next()
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    message: String,
+}
+
+impl ConfigError {
+    pub fn new(message: &str) -> ConfigError {
+        ConfigError { message: message.to_string() }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<CredentialsError> for ConfigError {
+    fn from(err: CredentialsError) -> ConfigError {
+        ConfigError::new(&err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use std::env;
+    use credentials::{Credentials, Secret};
+    use retry::RetryMode;
+    use ENV_LOCK as LOCK;
+
+    #[test]
+    fn test_load_region() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        env::set_var("AWS_SHARED_CREDENTIALS_FILE", "fixtures/credentials.ini");
+        env::remove_var("AWS_REGION");
+        env::remove_var("AWS_DEFAULT_REGION");
+        let profile = Config::new().profile("sso").no_imds().load();
+        let explicit = Config::new().profile("sso").region("us-west-2").no_imds().load();
+        env::remove_var("AWS_CONFIG_FILE");
+        env::remove_var("AWS_SHARED_CREDENTIALS_FILE");
+
+        assert_eq!(profile.region.unwrap(), "eu-west-1");
+        assert_eq!(explicit.region.unwrap(), "us-west-2")
+    }
+
+    #[test]
+    fn test_credentials() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        env::set_var("AWS_SHARED_CREDENTIALS_FILE", "fixtures/credentials.ini");
+        let creds = Config::new().profile("first").region("eu-west-1").no_imds().load().credentials();
+        env::remove_var("AWS_CONFIG_FILE");
+        env::remove_var("AWS_SHARED_CREDENTIALS_FILE");
+        assert_eq!(creds.unwrap().key.unwrap(), "zxspectrum")
+    }
+
+    #[test]
+    fn test_clones_share_provider() {
+        let _g = LOCK.read().unwrap();
+        let mut creds = Credentials::new();
+        creds.key = Some("akid".to_string());
+        creds.secret = Some(Secret::new("secret"));
+        let config = Config::new()
+            .region("eu-west-1")
+            .no_imds()
+            .credentials_provider(creds)
+            .endpoint_url("ec2", "http://localhost:4566")
+            .load();
+        let clone = config.clone();
+        assert_eq!(clone.credentials().unwrap().key.unwrap(), "akid");
+        assert_eq!(clone.endpoint_for("ec2").unwrap(), "http://localhost:4566")
+    }
+
+    #[test]
+    fn test_retry_settings() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        env::set_var("AWS_MAX_ATTEMPTS", "7");
+        let from_env = Config::new().profile("retries").no_imds().load().retry_strategy();
+        env::remove_var("AWS_MAX_ATTEMPTS");
+        let from_profile = Config::new().profile("retries").no_imds().load().retry_strategy();
+        let explicit = Config::new().profile("retries").max_attempts(2).no_imds().load()
+            .retry_strategy();
+        env::remove_var("AWS_CONFIG_FILE");
+
+        assert_eq!(from_env.max_attempts, 7);
+        assert_eq!(from_profile.max_attempts, 5);
+        assert_eq!(from_profile.mode, RetryMode::Adaptive);
+        assert_eq!(explicit.max_attempts, 2)
+    }
+
+    #[test]
+    fn test_endpoint_variant() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        env::set_var("AWS_SHARED_CREDENTIALS_FILE", "fixtures/credentials.ini");
+        env::set_var("AWS_USE_FIPS_ENDPOINT", "TRUE");
+        let fips = Config::new().region("us-east-1").no_imds().load().endpoint_variant();
+        let explicit = Config::new().region("us-east-1").use_fips_endpoint(false)
+            .use_dualstack_endpoint(true).no_imds().load().endpoint_variant();
+        env::remove_var("AWS_USE_FIPS_ENDPOINT");
+        let from_profile = Config::new().profile("dualstack").no_imds().load().endpoint_variant();
+        env::remove_var("AWS_CONFIG_FILE");
+        env::remove_var("AWS_SHARED_CREDENTIALS_FILE");
+
+        assert!(fips.fips && !fips.dualstack);
+        assert!(!explicit.fips && explicit.dualstack);
+        assert!(!from_profile.fips && from_profile.dualstack)
+    }
+
+    #[test]
+    fn test_endpoint_overrides() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        env::set_var("AWS_SHARED_CREDENTIALS_FILE", "fixtures/credentials.ini");
+        let config = Config::new().profile("local").region("us-east-1").no_imds();
+        let from_services = config.endpoint_for("sqs");
+        let from_profile = config.endpoint_for("ec2");
+        env::set_var("AWS_ENDPOINT_URL", "http://localhost:4000");
+        env::set_var("AWS_ENDPOINT_URL_DYNAMODB", "http://localhost:8000");
+        let from_env = config.endpoint_for("ec2");
+        let from_service_env = config.endpoint_for("dynamodb");
+        let explicit = config.clone().endpoint_url("dynamodb", "http://db:8000")
+            .endpoint_for("dynamodb");
+        let global = config.clone().default_endpoint_url("http://all:1234").endpoint_for("sqs");
+        env::set_var("AWS_IGNORE_CONFIGURED_ENDPOINT_URLS", "true");
+        let ignored = config.endpoint_for("ec2");
+        env::remove_var("AWS_IGNORE_CONFIGURED_ENDPOINT_URLS");
+        env::remove_var("AWS_ENDPOINT_URL");
+        env::remove_var("AWS_ENDPOINT_URL_DYNAMODB");
+        env::remove_var("AWS_CONFIG_FILE");
+        env::remove_var("AWS_SHARED_CREDENTIALS_FILE");
+
+        assert_eq!(from_services.unwrap(), "http://localhost:9324");
+        assert_eq!(from_profile.unwrap(), "http://localhost:4566");
+        assert_eq!(from_env.unwrap(), "http://localhost:4000");
+        assert_eq!(from_service_env.unwrap(), "http://localhost:8000");
+        assert_eq!(explicit.unwrap(), "http://db:8000");
+        assert_eq!(global.unwrap(), "http://all:1234");
+        assert!(ignored.is_none())
+    }
+}
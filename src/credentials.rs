@@ -1,14 +1,68 @@
-use ini::Ini;
+use profile::{config_path, config_section, credentials_path, default_profile, expand_home,
+              ProfileFile};
 use std::path::PathBuf;
 use std::env;
+use std::fmt;
+use std::ptr;
+use serialize::{Decodable, Decoder, Encodable, Encoder};
+use time::{get_time, Timespec};
+
+/// A secret value that never shows up in `Debug` output and is wiped from
+/// memory when dropped. Use `expose` to get at the value itself.
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new<S: Into<String>>(value: S) -> Secret {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "***")
+    }
+}
+
+// Encoded as the plain value, for caches on disk that have to keep it.
+impl Encodable for Secret {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(&self.0)
+    }
+}
+
+impl Decodable for Secret {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Secret, D::Error> {
+        d.read_str().map(Secret)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        // volatile so the writes aren't optimised away on a value about to be freed
+        unsafe {
+            for byte in self.0.as_mut_vec().iter_mut() {
+                ptr::write_volatile(byte, 0);
+            }
+        }
+    }
+}
 
 #[derive(Clone,Debug)]
 pub struct Credentials {
     pub key: Option<String>,
-    pub secret: Option<String>,
-    pub token: Option<String>,
+    pub secret: Option<Secret>,
+    pub token: Option<Secret>,
+    /// When temporary credentials stop working, `None` for long-term keys.
+    pub expiration: Option<Timespec>,
     path: String,
     profile: String,
+    // an explicitly chosen profile wins over credentials in the environment
+    profile_set: bool,
 }
 
 impl<'a> Credentials {
@@ -17,8 +71,10 @@ impl<'a> Credentials {
             key: None,
             secret: None,
             token: None,
+            expiration: None,
             path: get_profile_path(),
             profile: get_default_profile(),
+            profile_set: false,
         }
     }
 
@@ -29,70 +85,84 @@ impl<'a> Credentials {
 
     pub fn profile(mut self, profile: &str) -> Credentials {
         self.profile = String::from(profile);
+        self.profile_set = true;
         self
     }
 
+    /// Whether these credentials have expired, or will have by `at`.
+    pub fn expired_at(&self, at: Timespec) -> bool {
+        match self.expiration {
+            Some(expiration) => at >= expiration,
+            None => false,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expired_at(get_time())
+    }
+
     /// Load access and secret keys from environment or config file
     ///
-    /// Behaviour is as follows:
-    /// 1. If environment variable is present, use that.
+    /// Behaviour is as follows, matching the AWS CLI:
+    /// 1. If a profile was set with `profile`, skip to 2. Otherwise, if both
+    ///    `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` are set, use them along
+    ///    with `AWS_SESSION_TOKEN` (or the older `AWS_SECURITY_TOKEN`). A partial
+    ///    set of variables is ignored rather than mixed with the files.
     /// 2. Otherwise, use the profile:
-    /// 2.1. If profile is set, use that.
+    /// 2.1. If profile is set, use that, then `AWS_PROFILE`, then `AWS_DEFAULT_PROFILE`.
     /// 2.2. Otherwise use default profile.
+    /// 3. Keys are read from the credentials file (`AWS_SHARED_CREDENTIALS_FILE`,
+    ///    or `~/.aws/credentials`) and then the config file. The session token is
+    ///    `aws_session_token`, or the older `aws_security_token`.
     ///
-    /// Behaviour is copied from boto.
+    /// Use `profile::Profiles` to find out where values came from, or why a
+    /// profile is unusable.
     pub fn load(mut self) -> Credentials {
-        if let Ok(conf) = Ini::load_from_file(&self.path) {
-            if let Some(section) = conf.section(Some(&self.profile)) {
-                if let Some(key) = section.get("aws_access_key_id") {
-                    self.key = Some(key.to_string())
-                };
-                if let Some(secret) = section.get("aws_secret_access_key") {
-                    self.secret = Some(secret.to_string())
-                };
-                if let Some(token) = section.get("aws_security_token") {
-                    self.token = Some(token.to_string())
-                }
+        if !self.profile_set {
+            if let (Some(key), Some(secret)) = (non_empty_var("AWS_ACCESS_KEY_ID"),
+                                                non_empty_var("AWS_SECRET_ACCESS_KEY")) {
+                self.key = Some(key);
+                self.secret = Some(Secret::new(secret));
+                self.token = non_empty_var("AWS_SESSION_TOKEN")
+                    .or_else(|| non_empty_var("AWS_SECURITY_TOKEN"))
+                    .map(Secret::new);
+                return self
             }
-        };
-        if let Ok(key) = env::var("AWS_ACCESS_KEY_ID") {
-            self.key = Some(key.to_string())
-        };
-
-        if let Ok(secret) = env::var("AWS_SECRET_ACCESS_KEY") {
-            self.secret = Some(secret.to_string())
-        };
-        if let Ok(token) = env::var("AWS_SESSION_TOKEN") {
-            self.token = Some(token.to_string())
-        };
+        }
+
+        let files = [(ProfileFile::load_or_warn(&self.path), self.profile.clone()),
+                     (ProfileFile::load_or_warn(&config_path()), config_section(&self.profile))];
+        for &(ref file, ref section) in files.iter() {
+            let section = match file.section(section) {
+                Some(section) if section.get("aws_access_key_id").is_some() => section,
+                _ => continue,
+            };
+            let value = |key: &str| section.get(key).map(|e| e.value.clone());
+            self.key = value("aws_access_key_id");
+            self.secret = value("aws_secret_access_key").map(Secret::new);
+            self.token = value("aws_session_token")
+                .or_else(|| value("aws_security_token"))
+                .map(Secret::new);
+            break
+        }
         self
     }
 }
 
 fn get_default_profile() -> String {
-    match env::var("AWS_PROFILE") {
-        Err(_) => "default".to_string(),
-        Ok(s) => s.to_string(),
-    }
+    default_profile()
 }
 
 fn get_profile_path() -> String {
-    let home = match env::var("HOME") {
-        // hell if i know what not having home set means
-        Err(_) => "/root".to_string(),
-        Ok(s) => s,
-    };
-    let mut p = PathBuf::from(&home);
-    p.push(".aws");
-    p.push("credentials");
-    p.to_str().unwrap().to_string()
+    credentials_path()
 }
 
 fn get_absolute_path(val: &str) -> String {
-    let mut p = PathBuf::from(val);
+    let val = expand_home(val);
+    let mut p = PathBuf::from(&val);
     if !p.is_absolute() {
         p = env::current_dir().unwrap();
-        p.push(val);
+        p.push(&val);
     }
     p.to_str().unwrap().to_string()
 }
@@ -101,17 +171,8 @@ fn get_absolute_path(val: &str) -> String {
 mod test {
     use super::Credentials;
     use std::env;
-
-    // Tests depend on Credentials being resolved correctly. Since they are
-    // executed in parallel, the explicit environment tests can mess up
-    // other tests running at the same time.
-    //
-    // * Write lock needs to be acquired if the tests are changing environment
-    //   variables.
-    // * Read lock needs to be acquired if tests depend on environment
-    //   variables (but do not change them).
-    use std::sync::{StaticRwLock, RW_LOCK_INIT};
-    static LOCK: StaticRwLock = RW_LOCK_INIT;
+    use time::Timespec;
+    use ENV_LOCK as LOCK;
 
     #[test]
     fn test_defaults() {
@@ -142,6 +203,27 @@ mod test {
         assert_eq!(cred.key.unwrap(), "zxspectrum")
     }
 
+    #[test]
+    fn test_expiration() {
+        let _g = LOCK.read().unwrap();
+        let mut cred = Credentials::new().path("fixtures/credentials.ini").load();
+        assert!(!cred.is_expired());
+        cred.expiration = Some(Timespec::new(1315611360, 0));
+        assert!(cred.is_expired());
+        assert!(!cred.expired_at(Timespec::new(1315611359, 0)))
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let _g = LOCK.read().unwrap();
+        let cred = Credentials::new().path("fixtures/credentials.ini").profile("aws").load();
+        let out = format!("{:?}", cred);
+        assert!(out.contains("akid"));
+        assert!(out.contains("***"));
+        assert!(!out.contains("wJalrXUtnFEMI"));
+        assert_eq!(cred.secret.unwrap().expose(), "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY")
+    }
+
     #[test]
     fn test_env_first_success() {
         let _g = LOCK.write().unwrap();
@@ -151,7 +233,7 @@ mod test {
         env::remove_var("AWS_ACCESS_KEY_ID");
         env::remove_var("AWS_SECRET_ACCESS_KEY");
         assert_eq!(cred.key.unwrap(), "envaccess");
-        assert_eq!(cred.secret.unwrap(), "envsecret");
+        assert_eq!(cred.secret.unwrap().expose(), "envsecret");
     }
 
     #[test]
@@ -160,7 +242,98 @@ mod test {
         env::set_var("AWS_SECRET_ACCESS_KEY", "envsecret");
         let cred = Credentials::new().path("fixtures/credentials.ini").load();
         env::remove_var("AWS_SECRET_ACCESS_KEY");
+        // a secret without a key is ignored, not mixed with the file's key
         assert_eq!(cred.key.unwrap(), "12345");
-        assert_eq!(cred.secret.unwrap(), "envsecret")
+        assert_eq!(cred.secret.unwrap().expose(), "abc132")
+    }
+
+    const VARS: [&'static str; 8] = ["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY",
+                                     "AWS_SESSION_TOKEN", "AWS_SECURITY_TOKEN", "AWS_PROFILE",
+                                     "AWS_DEFAULT_PROFILE", "AWS_SHARED_CREDENTIALS_FILE",
+                                     "AWS_CONFIG_FILE"];
+
+    // Load with only the given variables set, on top of the precedence fixtures.
+    fn load_with(vars: &[(&str, &str)], profile: Option<&str>) -> Credentials {
+        let saved: Vec<_> = VARS.iter().map(|v| (*v, env::var(v).ok())).collect();
+        for v in VARS.iter() {
+            env::remove_var(v);
+        }
+        env::set_var("AWS_SHARED_CREDENTIALS_FILE", "fixtures/precedence/credentials");
+        env::set_var("AWS_CONFIG_FILE", "fixtures/precedence/config");
+        for &(k, v) in vars {
+            env::set_var(k, v);
+        }
+
+        let mut cred = Credentials::new();
+        if let Some(profile) = profile {
+            cred = cred.profile(profile);
+        }
+        let cred = cred.load();
+
+        for (k, v) in saved {
+            match v {
+                Some(v) => env::set_var(k, v),
+                None => env::remove_var(k),
+            }
+        }
+        cred
+    }
+
+    #[test]
+    fn test_precedence() {
+        let _g = LOCK.write().unwrap();
+        let env_pair = [("AWS_ACCESS_KEY_ID", "envkey"), ("AWS_SECRET_ACCESS_KEY", "envsecret")];
+        let cases: Vec<(&str, Vec<(&str, &str)>, Option<&str>, Option<(&str, &str)>, Option<&str>)> = vec![
+            ("env", env_pair.to_vec(), None, Some(("envkey", "envsecret")), None),
+            ("env session token",
+             vec![env_pair[0], env_pair[1], ("AWS_SESSION_TOKEN", "envtoken")],
+             None, Some(("envkey", "envsecret")), Some("envtoken")),
+            ("env security token",
+             vec![env_pair[0], env_pair[1], ("AWS_SECURITY_TOKEN", "envlegacy")],
+             None, Some(("envkey", "envsecret")), Some("envlegacy")),
+            ("env session token over security token",
+             vec![env_pair[0], env_pair[1], ("AWS_SESSION_TOKEN", "envtoken"),
+                  ("AWS_SECURITY_TOKEN", "envlegacy")],
+             None, Some(("envkey", "envsecret")), Some("envtoken")),
+            ("key without secret", vec![env_pair[0]],
+             None, Some(("defaultkey", "defaultsecret")), None),
+            ("empty key", vec![("AWS_ACCESS_KEY_ID", ""), env_pair[1]],
+             None, Some(("defaultkey", "defaultsecret")), None),
+            ("explicit profile over env", env_pair.to_vec(),
+             Some("first"), Some(("firstkey", "firstsecret")), None),
+            ("AWS_PROFILE", vec![("AWS_PROFILE", "first")],
+             None, Some(("firstkey", "firstsecret")), None),
+            ("AWS_DEFAULT_PROFILE", vec![("AWS_DEFAULT_PROFILE", "first")],
+             None, Some(("firstkey", "firstsecret")), None),
+            ("AWS_PROFILE over AWS_DEFAULT_PROFILE",
+             vec![("AWS_PROFILE", "session"), ("AWS_DEFAULT_PROFILE", "first")],
+             None, Some(("sessionkey", "sessionsecret")), Some("sessiontoken")),
+            ("env over AWS_PROFILE", vec![env_pair[0], env_pair[1], ("AWS_PROFILE", "first")],
+             None, Some(("envkey", "envsecret")), None),
+            ("aws_session_token", vec![],
+             Some("session"), Some(("sessionkey", "sessionsecret")), Some("sessiontoken")),
+            ("aws_security_token", vec![],
+             Some("legacy"), Some(("legacykey", "legacysecret")), Some("legacytoken")),
+            ("aws_session_token over aws_security_token", vec![],
+             Some("both"), Some(("bothkey", "bothsecret")), Some("sessiontoken")),
+            ("credentials file over config file", vec![],
+             Some("first"), Some(("firstkey", "firstsecret")), None),
+            ("config file", vec![],
+             Some("config-only"), Some(("configkey", "configsecret")), None),
+            ("AWS_SHARED_CREDENTIALS_FILE",
+             vec![("AWS_SHARED_CREDENTIALS_FILE", "fixtures/precedence/alt_credentials")],
+             None, Some(("altkey", "altsecret")), None),
+            ("CRLF and byte order mark",
+             vec![("AWS_SHARED_CREDENTIALS_FILE", "fixtures/precedence/crlf_credentials")],
+             None, Some(("crlfkey", "crlfsecret")), None),
+            ("missing profile", vec![], Some("nope"), None, None),
+        ];
+
+        for (name, vars, profile, keys, token) in cases {
+            let cred = load_with(&vars, profile);
+            assert!(cred.key.as_ref().map(|k| &k[..]) == keys.map(|k| k.0), "key: {}", name);
+            assert!(cred.secret.as_ref().map(|s| s.expose()) == keys.map(|k| k.1), "secret: {}", name);
+            assert!(cred.token.as_ref().map(|t| t.expose()) == token, "token: {}", name);
+        }
     }
 }
@@ -0,0 +1,63 @@
+/// Percent encode everything but the unreserved characters of RFC 3986,
+/// `A-Z a-z 0-9 - _ . ~`. This is the encoding SigV4 signs with, and the
+/// one used for paths, query strings and form bodies alike.
+pub fn uri_encode(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+/// Unencoded names and values as a query string or form body, in the
+/// order given.
+pub fn query_string<K: AsRef<str>, V: AsRef<str>>(pairs: &[(K, V)]) -> String {
+    join(&encode_pairs(pairs))
+}
+
+/// The query string as SigV4 signs it, sorted by the encoded names and
+/// then values.
+pub fn sorted_query_string<K: AsRef<str>, V: AsRef<str>>(pairs: &[(K, V)]) -> String {
+    let mut encoded = encode_pairs(pairs);
+    encoded.sort();
+    join(&encoded)
+}
+
+fn encode_pairs<K: AsRef<str>, V: AsRef<str>>(pairs: &[(K, V)]) -> Vec<(String, String)> {
+    pairs.iter().map(|&(ref k, ref v)| (uri_encode(k.as_ref()), uri_encode(v.as_ref()))).collect()
+}
+
+fn join(encoded: &[(String, String)]) -> String {
+    let pairs: Vec<String> = encoded.iter().map(|&(ref k, ref v)| format!("{}={}", k, v)).collect();
+    pairs.join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{query_string, sorted_query_string, uri_encode};
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(uri_encode("a b+c*d/é"), "a%20b%2Bc%2Ad%2F%C3%A9")
+    }
+
+    #[test]
+    fn test_query_string() {
+        assert_eq!(query_string(&[("RoleArn", "arn:aws:iam::1:role/a b"), ("Version", "1")]),
+                   "RoleArn=arn%3Aaws%3Aiam%3A%3A1%3Arole%2Fa%20b&Version=1");
+        let owned = vec![("Key".to_string(), "a=b&c".to_string())];
+        assert_eq!(query_string(&owned), "Key=a%3Db%26c");
+        assert_eq!(query_string::<&str, &str>(&[]), "")
+    }
+
+    #[test]
+    fn test_sorted_query_string() {
+        // "a/b" sorts after "a.b" unencoded but before it once encoded
+        assert_eq!(sorted_query_string(&[("a.b", "1"), ("a/b", "2"), ("A", "3"), ("a.b", "0")]),
+                   "A=3&a%2Fb=2&a.b=0&a.b=1")
+    }
+}
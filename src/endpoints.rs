@@ -0,0 +1,254 @@
+use serialize::json::Json;
+
+use config::ConfigError;
+
+// A trimmed down copy of the partitions and endpoints data the AWS SDKs ship.
+// Only services that don't follow the partition's defaults need to be listed.
+const ENDPOINTS: &'static str = include_str!("../data/endpoints.json");
+
+/// Where to send requests for a service, and what to sign them for.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Endpoint {
+    pub url: String,
+    pub signing_region: String,
+    pub signing_service: String,
+}
+
+impl Endpoint {
+    /// The host name from the URL, and the port if there is one, as signed in
+    /// the `Host` header.
+    pub fn host(&self) -> String {
+        let rest = self.url.splitn(2, "://").last().unwrap();
+        rest.split('/').next().unwrap().to_string()
+    }
+
+    /// The path of the URL, for endpoints such as `http://localhost:9000/prefix/`.
+    pub fn path(&self) -> String {
+        let rest = self.url.splitn(2, "://").last().unwrap();
+        match rest.find('/') {
+            Some(i) => rest[i..].to_string(),
+            None => "/".to_string(),
+        }
+    }
+}
+
+/// Which flavour of endpoint to pick.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Variant {
+    pub fips: bool,
+    pub dualstack: bool,
+}
+
+impl Variant {
+    fn tags(&self) -> Vec<&'static str> {
+        let mut tags = Vec::new();
+        if self.dualstack {
+            tags.push("dualstack");
+        }
+        if self.fips {
+            tags.push("fips");
+        }
+        tags
+    }
+}
+
+/// Resolve the endpoint of `service` in `region`.
+///
+/// The partition is picked by region (`cn-` regions live in `amazonaws.com.cn`,
+/// `us-gov-` regions in GovCloud). Global services such as IAM or Route 53 go to
+/// their partition wide endpoint and are signed for the region that endpoint
+/// lives in, whatever region was asked for.
+///
+/// Endpoints with a fixed host name, such as the global ones, only come in the
+/// variants listed for them: asking for a FIPS endpoint of IAM in China is an
+/// error rather than a made up host. The default variant always resolves.
+pub fn resolve(service: &str, region: &str, variant: Variant) -> Result<Endpoint, ConfigError> {
+    let data = data();
+    let partition = partition(&data, region);
+    let service_data = partition.find_path(&["services", service]);
+
+    let regionalized = service_data
+        .and_then(|s| s.find("isRegionalized"))
+        .and_then(|r| r.as_boolean())
+        .unwrap_or(true);
+    let global = service_data
+        .and_then(|s| s.find("partitionEndpoint"))
+        .and_then(|e| e.as_string());
+    let key = match global {
+        Some(global) if !regionalized => global,
+        _ => region,
+    };
+
+    let endpoint_data = service_data.and_then(|s| s.find_path(&["endpoints", key]));
+    let fixed = endpoint_data.and_then(|e| e.find("hostname")).is_some();
+    let mut candidates = vec![endpoint_data];
+    if !fixed {
+        candidates.push(service_data.and_then(|s| s.find("defaults")));
+        candidates.push(partition.find("defaults"));
+    }
+    let tags = variant.tags();
+    let mut hostname = None;
+    let mut dns_suffix = None;
+    for candidate in candidates.iter().filter_map(|c| *c) {
+        let found = if tags.is_empty() {
+            Some(candidate)
+        } else {
+            find_variant(candidate, &tags)
+        };
+        if let Some(found) = found {
+            if let Some(host) = found.find("hostname").and_then(|h| h.as_string()) {
+                hostname = Some(host.to_string());
+                dns_suffix = found.find("dnsSuffix").and_then(|d| d.as_string());
+                break
+            }
+        }
+    }
+
+    if hostname.is_none() && !tags.is_empty() {
+        return Err(ConfigError::new(&format!("no {} endpoint for {} in {}",
+                                             tags.join(" and "), service, key)))
+    }
+
+    let dns_suffix = dns_suffix
+        .or_else(|| partition.find("dnsSuffix").and_then(|d| d.as_string()))
+        .unwrap_or("amazonaws.com");
+    let host = hostname.unwrap_or("{service}.{region}.{dnsSuffix}".to_string())
+        .replace("{service}", service)
+        .replace("{region}", key)
+        .replace("{dnsSuffix}", dns_suffix);
+
+    let scope = |name: &str| {
+        endpoint_data
+            .and_then(|e| e.find_path(&["credentialScope", name]))
+            .or_else(|| service_data.and_then(|s| s.find_path(&["defaults", "credentialScope", name])))
+            .and_then(|v| v.as_string())
+    };
+    Ok(Endpoint {
+        url: format!("https://{}/", host),
+        signing_region: scope("region").unwrap_or(region).to_string(),
+        signing_service: scope("service").unwrap_or(service).to_string(),
+    })
+}
+
+// The endpoint data. It's a few kilobytes and only read when a client is
+// built, so it's parsed each time rather than kept in a global.
+fn data() -> Json {
+    Json::from_str(ENDPOINTS).unwrap()
+}
+
+// The partition listing the region, or whose region prefix matches best,
+// falling back to the standard partition.
+fn partition<'a>(data: &'a Json, region: &str) -> &'a Json {
+    let partitions = data.find("partitions").and_then(|p| p.as_array()).unwrap();
+    let mut best = (&partitions[0], 0);
+    for partition in partitions.iter() {
+        if partition.find_path(&["regions", region]).is_some() {
+            return partition
+        }
+        let prefixes = partition.find("regionPrefixes").and_then(|p| p.as_array());
+        for prefix in prefixes.into_iter().flat_map(|p| p.iter()).filter_map(|p| p.as_string()) {
+            if region.starts_with(prefix) && prefix.len() > best.1 {
+                best = (partition, prefix.len());
+            }
+        }
+    }
+    best.0
+}
+
+fn find_variant<'a>(data: &'a Json, tags: &[&str]) -> Option<&'a Json> {
+    let variants = match data.find("variants").and_then(|v| v.as_array()) {
+        Some(variants) => variants,
+        None => return None,
+    };
+    variants.iter().find(|v| {
+        let mut found: Vec<&str> = v.find("tags")
+            .and_then(|t| t.as_array())
+            .map(|t| t.iter().filter_map(|tag| tag.as_string()).collect())
+            .unwrap_or(Vec::new());
+        found.sort();
+        found == tags
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, Variant};
+
+    const FIPS: Variant = Variant { fips: true, dualstack: false };
+    const DUALSTACK: Variant = Variant { fips: false, dualstack: true };
+
+    fn url_and_region(service: &str, region: &str, variant: Variant) -> (String, String) {
+        let endpoint = resolve(service, region, variant).unwrap();
+        (endpoint.url, endpoint.signing_region)
+    }
+
+    fn expect(url: &str, region: &str) -> (String, String) {
+        (url.to_string(), region.to_string())
+    }
+
+    #[test]
+    fn test_regional() {
+        assert_eq!(url_and_region("ec2", "eu-west-1", Variant::default()),
+                   expect("https://ec2.eu-west-1.amazonaws.com/", "eu-west-1"));
+        // regions newer than the data file still resolve by prefix
+        assert_eq!(url_and_region("ec2", "ap-southeast-7", Variant::default()),
+                   expect("https://ec2.ap-southeast-7.amazonaws.com/", "ap-southeast-7"))
+    }
+
+    #[test]
+    fn test_global_services() {
+        assert_eq!(url_and_region("iam", "eu-west-1", Variant::default()),
+                   expect("https://iam.amazonaws.com/", "us-east-1"));
+        assert_eq!(url_and_region("route53", "ap-south-1", Variant::default()),
+                   expect("https://route53.amazonaws.com/", "us-east-1"));
+        assert_eq!(url_and_region("sts", "aws-global", Variant::default()),
+                   expect("https://sts.amazonaws.com/", "us-east-1"));
+        assert_eq!(url_and_region("sts", "eu-west-1", Variant::default()),
+                   expect("https://sts.eu-west-1.amazonaws.com/", "eu-west-1"))
+    }
+
+    #[test]
+    fn test_partitions() {
+        assert_eq!(url_and_region("ec2", "cn-north-1", Variant::default()),
+                   expect("https://ec2.cn-north-1.amazonaws.com.cn/", "cn-north-1"));
+        assert_eq!(url_and_region("iam", "cn-northwest-1", Variant::default()),
+                   expect("https://iam.cn-north-1.amazonaws.com.cn/", "cn-north-1"));
+        assert_eq!(url_and_region("ec2", "us-gov-west-1", Variant::default()),
+                   expect("https://ec2.us-gov-west-1.amazonaws.com/", "us-gov-west-1"));
+        assert_eq!(url_and_region("iam", "us-gov-east-1", Variant::default()),
+                   expect("https://iam.us-gov.amazonaws.com/", "us-gov-west-1"))
+    }
+
+    #[test]
+    fn test_variants() {
+        assert_eq!(resolve("ec2", "us-east-1", FIPS).unwrap().url, "https://ec2-fips.us-east-1.amazonaws.com/");
+        assert_eq!(resolve("ec2", "us-east-1", DUALSTACK).unwrap().url, "https://ec2.us-east-1.api.aws/");
+        assert_eq!(resolve("ec2", "us-east-1", Variant { fips: true, dualstack: true }).unwrap().url,
+                   "https://ec2-fips.us-east-1.api.aws/");
+        assert_eq!(resolve("s3", "eu-west-1", DUALSTACK).unwrap().url,
+                   "https://s3.dualstack.eu-west-1.amazonaws.com/");
+        assert_eq!(resolve("iam", "us-east-1", FIPS).unwrap().url, "https://iam-fips.amazonaws.com/");
+        assert_eq!(resolve("sts", "eu-west-1", FIPS).unwrap().url,
+                   "https://sts-fips.eu-west-1.amazonaws.com/");
+        assert_eq!(resolve("sts", "cn-north-1", DUALSTACK).unwrap().url,
+                   "https://sts.cn-north-1.api.amazonwebservices.com.cn/")
+    }
+
+    #[test]
+    fn test_missing_variants() {
+        // global endpoints don't pick up the partition's templates
+        assert!(resolve("sts", "aws-global", FIPS).is_err());
+        assert!(resolve("iam", "cn-north-1", FIPS).is_err());
+        assert!(resolve("route53", "us-east-1", DUALSTACK).is_err())
+    }
+
+    #[test]
+    fn test_host_and_path() {
+        let mut endpoint = resolve("ec2", "eu-west-1", Variant::default()).unwrap();
+        assert_eq!(endpoint.host(), "ec2.eu-west-1.amazonaws.com");
+        assert_eq!(endpoint.path(), "/");
+        endpoint.url = "http://localhost:9000/minio/".to_string();
+        assert_eq!(endpoint.host(), "localhost:9000");
+        assert_eq!(endpoint.path(), "/minio/")
+    }
+}
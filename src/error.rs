@@ -0,0 +1,301 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use hyper;
+use hyper::header::Headers;
+use serialize::json::{DecoderError, Json};
+
+use config::ConfigError;
+use providers::CredentialsError;
+use xml::{self, XmlError};
+
+const THROTTLING_CODES: [&'static str; 14] = [
+    "Throttling", "ThrottlingException", "ThrottledException", "RequestThrottledException",
+    "TooManyRequestsException", "ProvisionedThroughputExceededException",
+    "TransactionInProgressException", "RequestLimitExceeded", "BandwidthLimitExceeded",
+    "LimitExceededException", "RequestThrottled", "SlowDown", "PriorRequestNotComplete",
+    "EC2ThrottledException"];
+
+const TRANSIENT_CODES: [&'static str; 5] = [
+    "RequestTimeout", "RequestTimeoutException", "InternalError", "InternalFailure",
+    "ServiceUnavailable"];
+
+const AUTH_CODES: [&'static str; 13] = [
+    "AuthFailure", "UnauthorizedOperation", "InvalidClientTokenId", "SignatureDoesNotMatch",
+    "IncompleteSignature", "MissingAuthenticationToken", "InvalidAccessKeyId", "ExpiredToken",
+    "ExpiredTokenException", "RequestExpired", "AccessDenied", "AccessDeniedException",
+    "UnrecognizedClientException"];
+
+/// What went wrong, broadly, which decides whether a call is worth retrying.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ErrorKind {
+    /// The service is rate limiting us, retry after backing off.
+    Throttling,
+    /// A 5xx, a timeout or a failed connection, retrying may well work.
+    Transient,
+    /// Bad or expired credentials, or not allowed to make the call.
+    Auth,
+    /// Anything else the caller did wrong, retrying won't help.
+    Client,
+}
+
+/// An error returned by an AWS service, or a failure to get a response at all.
+#[derive(Clone,Debug)]
+pub struct AwsError {
+    pub code: String,
+    pub message: String,
+    pub request_id: Option<String>,
+    /// The HTTP status, `None` when no response was received.
+    pub status: Option<u16>,
+    pub kind: ErrorKind,
+}
+
+impl AwsError {
+    pub fn new(code: &str, message: &str, request_id: Option<String>,
+               status: Option<u16>) -> AwsError {
+        AwsError {
+            code: code.to_string(),
+            message: message.to_string(),
+            request_id: request_id,
+            status: status,
+            kind: classify(code, status),
+        }
+    }
+
+    /// Parse an error response, whichever of the protocols it comes from.
+    ///
+    /// EC2 wraps errors in `<Response><Errors>`, the other Query services in
+    /// `<ErrorResponse>` and REST-XML services send a bare `<Error>`, all with the
+    /// same `Code` and `Message` elements. JSON services send a `__type` (or the
+    /// `x-amzn-ErrorType` header) and a `message`.
+    pub fn from_response(status: u16, headers: &Headers, body: &str) -> AwsError {
+        let header = |name: &str| {
+            headers.get_raw(name)
+                .and_then(|values| values.first())
+                .and_then(|value| String::from_utf8(value.clone()).ok())
+        };
+        let header_id = header("x-amzn-RequestId").or_else(|| header("x-amz-request-id"));
+
+        let body = body.trim();
+        let (code, message, request_id) = if body.starts_with("{") {
+            let json = Json::from_str(body).ok();
+            let field = |names: &[&str]| {
+                json.as_ref().and_then(|json| {
+                    names.iter().filter_map(|name| json.find(name)).next()
+                        .and_then(|v| v.as_string())
+                        .map(|v| v.to_string())
+                })
+            };
+            let code = field(&["__type", "code"]).or_else(|| header("x-amzn-ErrorType"));
+            (code.map(|c| json_code(&c)), field(&["message", "Message"]), header_id)
+        } else {
+            let request_id = xml::find(body, "RequestId")
+                .or_else(|| xml::find(body, "RequestID"))
+                .or(header_id);
+            (xml::find(body, "Code"), xml::find(body, "Message"), request_id)
+        };
+
+        let code = code.unwrap_or_else(|| status_code(status));
+        AwsError::new(&code, &message.unwrap_or(String::new()), request_id, Some(status))
+    }
+
+    pub fn is_throttling(&self) -> bool {
+        self.kind == ErrorKind::Throttling
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.kind == ErrorKind::Transient
+    }
+}
+
+// `aws.protocoltests#Code`, `Code:http://internal.amazon.com/...` or plain `Code`.
+fn json_code(raw: &str) -> String {
+    let code = raw.rsplit('#').next().unwrap_or(raw);
+    code.split(':').next().unwrap_or(code).to_string()
+}
+
+// A code for error responses without a body, such as a 404 to a `HEAD`.
+fn status_code(status: u16) -> String {
+    match status {
+        400 => "BadRequest",
+        403 => "Forbidden",
+        404 => "NotFound",
+        500 => "InternalError",
+        503 => "ServiceUnavailable",
+        _ => "Unknown",
+    }.to_string()
+}
+
+fn classify(code: &str, status: Option<u16>) -> ErrorKind {
+    if THROTTLING_CODES.contains(&code) || status == Some(429) {
+        return ErrorKind::Throttling
+    }
+    if AUTH_CODES.contains(&code) {
+        return ErrorKind::Auth
+    }
+    // without a response it's up to the conversion, see `io_kind`
+    match status {
+        Some(status) if status >= 500 => ErrorKind::Transient,
+        Some(_) if TRANSIENT_CODES.contains(&code) => ErrorKind::Transient,
+        _ => ErrorKind::Client,
+    }
+}
+
+// Failing to connect or to get a response in time is worth retrying, other I/O
+// errors, such as an unreadable file, aren't.
+fn io_kind(err: &io::Error) -> ErrorKind {
+    match err.kind() {
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset |
+        io::ErrorKind::ConnectionAborted | io::ErrorKind::NotConnected |
+        io::ErrorKind::BrokenPipe | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock |
+        io::ErrorKind::UnexpectedEof => ErrorKind::Transient,
+        _ => ErrorKind::Client,
+    }
+}
+
+impl fmt::Display for AwsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}: {}", self.code, self.message));
+        if let Some(status) = self.status {
+            try!(write!(f, " (status {}", status));
+            if let Some(ref id) = self.request_id {
+                try!(write!(f, ", request id {}", id));
+            }
+            try!(write!(f, ")"));
+        }
+        Ok(())
+    }
+}
+
+impl Error for AwsError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<hyper::Error> for AwsError {
+    fn from(err: hyper::Error) -> AwsError {
+        let mut error = AwsError::new("HttpError", &err.to_string(), None, None);
+        if let hyper::Error::Io(ref e) = err {
+            error.kind = io_kind(e);
+        }
+        error
+    }
+}
+
+impl From<io::Error> for AwsError {
+    fn from(err: io::Error) -> AwsError {
+        let mut error = AwsError::new("IoError", &err.to_string(), None, None);
+        error.kind = io_kind(&err);
+        error
+    }
+}
+
+impl From<CredentialsError> for AwsError {
+    fn from(err: CredentialsError) -> AwsError {
+        AwsError::new("CredentialsError", &err.to_string(), None, None)
+    }
+}
+
+impl From<ConfigError> for AwsError {
+    fn from(err: ConfigError) -> AwsError {
+        AwsError::new("ConfigError", &err.to_string(), None, None)
+    }
+}
+
+impl From<XmlError> for AwsError {
+    fn from(err: XmlError) -> AwsError {
+        deserialization(&err.to_string())
+    }
+}
+
+impl From<DecoderError> for AwsError {
+    fn from(err: DecoderError) -> AwsError {
+        deserialization(&err.to_string())
+    }
+}
+
+// The call went through, so retrying won't fix a response we can't read.
+fn deserialization(message: &str) -> AwsError {
+    AwsError::new("DeserializationError", message, None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AwsError, ErrorKind};
+    use std::io;
+    use hyper::header::Headers;
+    use config::ConfigError;
+    use providers::CredentialsError;
+
+    #[test]
+    fn test_ec2_error() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Response><Errors><Error><Code>InvalidInstanceID.NotFound</Code><Message>The instance ID 'i-1a2b3c4d' does not exist</Message></Error></Errors><RequestID>ea966190-f9aa-478e-9ede-example</RequestID></Response>"#;
+        let err = AwsError::from_response(400, &Headers::new(), body);
+        assert_eq!(err.code, "InvalidInstanceID.NotFound");
+        assert_eq!(err.message, "The instance ID 'i-1a2b3c4d' does not exist");
+        assert_eq!(err.request_id.unwrap(), "ea966190-f9aa-478e-9ede-example");
+        assert_eq!(err.kind, ErrorKind::Client)
+    }
+
+    #[test]
+    fn test_query_error() {
+        let body = r#"<ErrorResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <Error>
+    <Type>Sender</Type>
+    <Code>Throttling</Code>
+    <Message>Rate exceeded</Message>
+  </Error>
+  <RequestId>a1b2c3</RequestId>
+</ErrorResponse>"#;
+        let err = AwsError::from_response(400, &Headers::new(), body);
+        assert_eq!(err.code, "Throttling");
+        assert_eq!(err.request_id.unwrap(), "a1b2c3");
+        assert!(err.is_throttling())
+    }
+
+    #[test]
+    fn test_rest_xml_error() {
+        let body = r#"<Error><Code>SignatureDoesNotMatch</Code><Message>The request signature we calculated does not match</Message><RequestId>4442587FB7D0A2F9</RequestId></Error>"#;
+        let err = AwsError::from_response(403, &Headers::new(), body);
+        assert_eq!(err.code, "SignatureDoesNotMatch");
+        assert_eq!(err.status, Some(403));
+        assert_eq!(err.kind, ErrorKind::Auth)
+    }
+
+    #[test]
+    fn test_json_error() {
+        let mut headers = Headers::new();
+        headers.set_raw("x-amzn-RequestId", vec![b"req-1".to_vec()]);
+        let body = r#"{"__type":"com.amazonaws.dynamodb.v20120810#ProvisionedThroughputExceededException","message":"Rate of requests exceeds the allowed throughput."}"#;
+        let err = AwsError::from_response(400, &headers, body);
+        assert_eq!(err.code, "ProvisionedThroughputExceededException");
+        assert_eq!(err.message, "Rate of requests exceeds the allowed throughput.");
+        assert_eq!(err.request_id.unwrap(), "req-1");
+        assert!(err.is_throttling())
+    }
+
+    #[test]
+    fn test_empty_body() {
+        let err = AwsError::from_response(503, &Headers::new(), "");
+        assert_eq!(err.code, "ServiceUnavailable");
+        assert!(err.is_transient());
+        let err = AwsError::from_response(404, &Headers::new(), "");
+        assert_eq!(err.code, "NotFound");
+        assert_eq!(err.kind, ErrorKind::Client)
+    }
+
+    #[test]
+    fn test_no_response() {
+        // only failing to connect or timing out is worth retrying
+        let refused = io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused");
+        assert!(AwsError::from(refused).is_transient());
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "timed out");
+        assert!(AwsError::from(timeout).is_transient());
+        let missing = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        assert_eq!(AwsError::from(missing).kind, ErrorKind::Client);
+        assert_eq!(AwsError::from(CredentialsError::new("no credentials")).kind, ErrorKind::Client);
+        assert_eq!(AwsError::from(ConfigError::new("no region")).kind, ErrorKind::Client)
+    }
+}
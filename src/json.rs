@@ -0,0 +1,95 @@
+use serialize::Encodable;
+use serialize::json::{self, EncoderError, Json};
+
+/// A call to a service speaking the AWS JSON protocol, such as DynamoDB, KMS or
+/// SSM: the operation goes in the `X-Amz-Target` header and its input is
+/// POSTed as a JSON document.
+#[derive(Clone,Debug)]
+pub struct JsonRequest {
+    pub target: String,
+    pub version: String,
+    body: String,
+}
+
+impl JsonRequest {
+    /// A call to `operation` on the service with target prefix `prefix`, such as
+    /// `DynamoDB_20120810` or `TrentService` for KMS.
+    pub fn new(prefix: &str, operation: &str) -> JsonRequest {
+        JsonRequest {
+            target: format!("{}.{}", prefix, operation),
+            version: "1.1".to_string(),
+            body: "{}".to_string(),
+        }
+    }
+
+    /// The protocol version, `1.0` for DynamoDB and a few others, `1.1` otherwise.
+    pub fn json_version(mut self, version: &str) -> JsonRequest {
+        self.version = version.to_string();
+        self
+    }
+
+    pub fn body(mut self, body: Json) -> JsonRequest {
+        self.body = without_nulls(body).to_string();
+        self
+    }
+
+    /// Send `input` as the body. Fields set to `None` are left out rather than
+    /// sent as `null`, which some services refuse.
+    pub fn input<T: Encodable>(self, input: &T) -> Result<JsonRequest, EncoderError> {
+        let encoded = try!(json::encode(input));
+        Ok(self.body(Json::from_str(&encoded).unwrap()))
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("application/x-amz-json-{}", self.version)
+    }
+
+    pub fn payload(&self) -> &str {
+        &self.body
+    }
+}
+
+fn without_nulls(value: Json) -> Json {
+    match value {
+        Json::Object(fields) => Json::Object(fields.into_iter()
+            .filter(|&(_, ref v)| !v.is_null())
+            .map(|(k, v)| (k, without_nulls(v)))
+            .collect()),
+        Json::Array(items) => Json::Array(items.into_iter().map(without_nulls).collect()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonRequest;
+
+    #[allow(non_snake_case)]
+    #[derive(RustcEncodable)]
+    struct Query {
+        TableName: String,
+        Limit: Option<u32>,
+        ExclusiveStartKey: Option<String>,
+    }
+
+    #[test]
+    fn test_request() {
+        let request = JsonRequest::new("DynamoDB_20120810", "ListTables").json_version("1.0");
+        assert_eq!(request.target, "DynamoDB_20120810.ListTables");
+        assert_eq!(request.content_type(), "application/x-amz-json-1.0");
+        assert_eq!(request.payload(), "{}");
+        assert_eq!(JsonRequest::new("TrentService", "ListKeys").content_type(),
+                   "application/x-amz-json-1.1")
+    }
+
+    #[test]
+    fn test_input() {
+        let query = Query {
+            TableName: "jobs".to_string(),
+            Limit: Some(10),
+            ExclusiveStartKey: None,
+        };
+        let request = JsonRequest::new("DynamoDB_20120810", "Scan").input(&query).unwrap();
+        assert_eq!(request.payload(), r#"{"Limit":10,"TableName":"jobs"}"#)
+    }
+}
@@ -8,14 +8,49 @@
 extern crate hyper;
 
 #[cfg(unix)] extern crate openssl;
+extern crate rand;
 extern crate rustc_serialize as serialize;
 extern crate time;
 extern crate url;
-extern crate ini;
 
 #[macro_use]
 extern crate log;
 
+pub mod config;
 pub mod credentials;
+pub mod encoding;
+pub mod endpoints;
+pub mod error;
+pub mod json;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod paginate;
+pub mod profile;
+pub mod providers;
+pub mod query;
+pub mod region;
 pub mod request;
+pub mod rest;
+pub mod retry;
+pub mod services;
 pub mod signers;
+pub mod transport;
+pub mod xml;
+
+// Empty variables count as unset, as they do for the CLI.
+fn non_empty_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().and_then(|v| if v.is_empty() { None } else { Some(v) })
+}
+
+// Tests depend on the environment being resolved correctly. Since they are
+// executed in parallel, the explicit environment tests can mess up other
+// tests running at the same time, in any module.
+//
+// * Write lock needs to be acquired if the tests are changing environment
+//   variables.
+// * Read lock needs to be acquired if tests depend on environment
+//   variables (but do not change them).
+#[cfg(test)]
+use std::sync::{StaticRwLock, RW_LOCK_INIT};
+#[cfg(test)]
+static ENV_LOCK: StaticRwLock = RW_LOCK_INIT;
@@ -0,0 +1,624 @@
+use std::ascii::AsciiExt;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use time::{now_utc, strptime};
+use url::form_urlencoded;
+use url::percent_encoding::percent_decode;
+
+use config::Config;
+use credentials::{Credentials, Secret};
+use signers::sigv4::SigV4;
+use xml;
+
+mod services;
+
+use self::services::Services;
+
+pub const ACCESS_KEY_ID: &'static str = "AKIDMOCKSERVER";
+pub const SECRET_ACCESS_KEY: &'static str = "mock-server-secret";
+pub const ACCOUNT_ID: &'static str = "123456789012";
+
+// Requests signed further than this from the server's clock are refused, as AWS does.
+const MAX_SKEW_SECS: i64 = 15 * 60;
+
+/// A request as the mock server received it.
+#[derive(Clone,Debug)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    /// Header names are lower case.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|&&(ref n, _)| *n == name).map(|&(_, ref v)| &v[..])
+    }
+
+    pub fn body_str(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// A parameter from the query string, or from a form encoded body.
+    pub fn param(&self, name: &str) -> Option<String> {
+        let mut params = form_urlencoded::parse(self.query.as_bytes());
+        if self.header("content-type").map_or(false, |t| t.starts_with("application/x-www-form-urlencoded")) {
+            params.extend(form_urlencoded::parse(&self.body));
+        }
+        params.into_iter().find(|&(ref k, _)| k == name).map(|(_, v)| v)
+    }
+
+    // The service from the credential scope of the signature.
+    fn signing_service(&self) -> Option<String> {
+        self.header("authorization")
+            .and_then(|auth| auth.split("Credential=").nth(1))
+            .and_then(|scope| scope.split(|c| c == ',' || c == ' ').next())
+            .and_then(|scope| scope.split('/').nth(3))
+            .map(|service| service.to_string())
+    }
+}
+
+/// What the mock server answers with.
+#[derive(Clone,Debug)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: &str) -> MockResponse {
+        MockResponse {
+            status: status,
+            headers: Vec::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn xml(body: &str) -> MockResponse {
+        MockResponse::new(200, body).header("Content-Type", "text/xml")
+    }
+
+    pub fn json(body: &str) -> MockResponse {
+        MockResponse::new(200, body).header("Content-Type", "application/json")
+    }
+
+    /// An error in the shape the Query services use.
+    pub fn error(status: u16, code: &str, message: &str) -> MockResponse {
+        let body = format!("<ErrorResponse><Error><Type>Sender</Type><Code>{}</Code>\
+                            <Message>{}</Message></Error><RequestId>{}</RequestId></ErrorResponse>",
+                           code, xml::escape(message), services::request_id());
+        MockResponse::new(status, &body).header("Content-Type", "text/xml")
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> MockResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+struct State {
+    url: String,
+    script: Mutex<VecDeque<MockResponse>>,
+    requests: Mutex<Vec<MockRequest>>,
+    services: Mutex<Services>,
+}
+
+/// An HTTP server on a local port that plays AWS, for tests without network.
+///
+/// Signed requests are checked with the crate's own `SigV4`, against
+/// `ACCESS_KEY_ID` and `SECRET_ACCESS_KEY` or credentials handed out by the
+/// mock STS, and refused the way AWS would refuse them. Only the calls that go
+/// out unsigned for real, `AssumeRoleWithWebIdentity` and container credentials
+/// calls under `/v1/credentials` or `/v2/credentials`, may leave the signature
+/// off; anything else without one gets a `MissingAuthenticationToken` error.
+///
+/// Responses queued with `script` are served first, in order. After that S3
+/// (path style buckets and objects), SQS (queues and messages) and STS
+/// (`GetCallerIdentity` and the `AssumeRole` calls) keep their state in memory
+/// for as long as the server runs.
+///
+/// The server stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<State>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn start() -> io::Result<MockServer> {
+        let listener = try!(TcpListener::bind("127.0.0.1:0"));
+        let addr = try!(listener.local_addr());
+        let state = Arc::new(State {
+            url: format!("http://{}", addr),
+            script: Mutex::new(VecDeque::new()),
+            requests: Mutex::new(Vec::new()),
+            services: Mutex::new(Services::new()),
+        });
+        let running = Arc::new(AtomicBool::new(true));
+
+        let (accept_state, accept_running) = (state.clone(), running.clone());
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !accept_running.load(Ordering::SeqCst) {
+                    break
+                }
+                if let Ok(stream) = stream {
+                    let state = accept_state.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = serve(stream, &state) {
+                            debug!("Mock server connection ended: {}", e);
+                        }
+                    });
+                }
+            }
+        });
+        Ok(MockServer {
+            addr: addr,
+            state: state,
+            running: running,
+            handle: Some(handle),
+        })
+    }
+
+    /// The base URL, such as `http://127.0.0.1:49152`.
+    pub fn url(&self) -> String {
+        self.state.url.clone()
+    }
+
+    /// Credentials the server accepts.
+    pub fn credentials(&self) -> Credentials {
+        let mut creds = Credentials::new();
+        creds.key = Some(ACCESS_KEY_ID.to_string());
+        creds.secret = Some(Secret::new(SECRET_ACCESS_KEY));
+        creds
+    }
+
+    /// A config sending every service to this server, signed for us-east-1.
+    pub fn config(&self) -> Config {
+        Config::new()
+            .region("us-east-1")
+            .no_imds()
+            .credentials_provider(self.credentials())
+            .default_endpoint_url(&self.url())
+            .load()
+    }
+
+    /// Answer the next request with `response`, whatever it is.
+    pub fn script(&self, response: MockResponse) {
+        self.state.script.lock().unwrap().push_back(response);
+    }
+
+    /// Every request received so far.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // wake the accept loop up so it sees it has to stop
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// Answer requests on a kept alive connection until the client closes it.
+fn serve(stream: TcpStream, state: &State) -> io::Result<()> {
+    let mut reader = BufReader::new(try!(stream.try_clone()));
+    let mut writer = stream;
+    loop {
+        let request = match try!(read_request(&mut reader)) {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+        let close = request.header("connection").map_or(false, |c| c.eq_ignore_ascii_case("close"));
+        let response = handle(&request, state);
+        try!(write_response(&mut writer, &request, &response));
+        state.requests.lock().unwrap().push(request);
+        if close {
+            return Ok(())
+        }
+    }
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<MockRequest>> {
+    let mut line = String::new();
+    if try!(reader.read_line(&mut line)) == 0 {
+        return Ok(None)
+    }
+    let mut parts = line.trim_right().split(' ');
+    let method = parts.next().unwrap_or("").to_string();
+    let target = parts.next().unwrap_or("/");
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        try!(reader.read_line(&mut line));
+        let line = line.trim_right();
+        if line.is_empty() {
+            break
+        }
+        if let Some(i) = line.find(':') {
+            headers.push((line[..i].trim().to_lowercase(), line[i + 1..].trim().to_string()));
+        }
+    }
+
+    let length = headers.iter()
+        .find(|&&(ref n, _)| n == "content-length")
+        .and_then(|&(_, ref v)| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = Vec::new();
+    try!(reader.take(length).read_to_end(&mut body));
+
+    Ok(Some(MockRequest {
+        method: method,
+        path: path.to_string(),
+        query: query.to_string(),
+        headers: headers,
+        body: body,
+    }))
+}
+
+fn write_response<W: Write>(writer: &mut W, request: &MockRequest,
+                            response: &MockResponse) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\nContent-Length: {}\r\n",
+                           response.status, reason(response.status), response.body.len());
+    for &(ref name, ref value) in response.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    try!(writer.write_all(head.as_bytes()));
+    if request.method != "HEAD" {
+        try!(writer.write_all(&response.body));
+    }
+    writer.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn handle(request: &MockRequest, state: &State) -> MockResponse {
+    let mut services = state.services.lock().unwrap();
+    if request.header("authorization").is_some() {
+        if let Err(response) = verify(request, &services) {
+            return response
+        }
+    } else if !unsigned_allowed(request) {
+        return MockResponse::error(403, "MissingAuthenticationToken",
+                                   "Request is missing Authentication Token")
+    }
+    if let Some(response) = state.script.lock().unwrap().pop_front() {
+        return response
+    }
+    let service = request.signing_service().unwrap_or_else(|| {
+        // the only unsigned calls with an action are STS ones
+        if request.param("Action").is_some() { "sts".to_string() } else { String::new() }
+    });
+    services.handle(&service, request, &state.url)
+}
+
+// The calls real clients make without signing them.
+fn unsigned_allowed(request: &MockRequest) -> bool {
+    request.param("Action").map_or(false, |action| action == "AssumeRoleWithWebIdentity") ||
+        ["/v1/credentials", "/v2/credentials"].iter().any(|prefix| request.path.starts_with(prefix))
+}
+
+/// Check the signature of `request` by signing it again with the secret of
+/// its access key, the same way the client would have.
+fn verify(request: &MockRequest, services: &Services) -> Result<(), MockResponse> {
+    let authorization = request.header("authorization").unwrap();
+    let field = |name: &str| {
+        authorization.split(|c| c == ',' || c == ' ')
+            .find(|part| part.starts_with(name))
+            .map(|part| &part[name.len()..])
+    };
+    let (scope, signed_headers) = match (field("Credential="), field("SignedHeaders=")) {
+        (Some(scope), Some(signed_headers)) => (scope, signed_headers),
+        _ => return Err(MockResponse::error(400, "IncompleteSignature",
+                                            "Authorization header is missing fields")),
+    };
+    let scope: Vec<&str> = scope.split('/').collect();
+    if scope.len() != 5 {
+        return Err(MockResponse::error(400, "IncompleteSignature", "Malformed credential scope"))
+    }
+
+    let token = request.header("x-amz-security-token");
+    let creds = match services.credentials(scope[0], token) {
+        Some(creds) => creds,
+        None => return Err(MockResponse::error(403, "InvalidClientTokenId",
+                                               "The security token included in the request is invalid.")),
+    };
+    let date = match request.header("x-amz-date").and_then(|d| strptime(d, "%Y%m%dT%H%M%SZ").ok()) {
+        Some(date) => date,
+        None => return Err(MockResponse::error(400, "IncompleteSignature", "Missing X-Amz-Date")),
+    };
+    if (now_utc().to_timespec().sec - date.to_timespec().sec).abs() > MAX_SKEW_SECS {
+        return Err(MockResponse::error(403, "RequestTimeTooSkewed",
+                                       "The difference between the request time and the current time is too large."))
+    }
+
+    // clients sign the query before percent encoding it
+    let query: Vec<(String, String)> = request.query.split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let mut parts = p.splitn(2, '=');
+            (decode(parts.next().unwrap()), decode(parts.next().unwrap_or("")))
+        })
+        .collect();
+    let mut sig = SigV4::new()
+        .credentials(creds)
+        .timestamp(date)
+        .region(scope[2])
+        .service(scope[3])
+        .method(&request.method)
+        .path(&request.path)
+        .payload(&request.body);
+    if !query.is_empty() {
+        sig = sig.query_pairs(&query);
+    }
+    for name in signed_headers.split(';') {
+        if ["x-amz-date", "x-amz-security-token", "authorization", ""].contains(&name) {
+            continue
+        }
+        for &(_, ref value) in request.headers.iter().filter(|&&(ref n, _)| n == name) {
+            sig = sig.header((name, value));
+        }
+    }
+
+    let expected = sig.as_headers();
+    let expected = expected.get_raw("authorization").and_then(|v| v.get(0));
+    if expected.map(|e| &e[..]) != Some(authorization.as_bytes()) {
+        return Err(MockResponse::error(403, "SignatureDoesNotMatch",
+                                       "The request signature we calculated does not match the signature you provided."))
+    }
+    Ok(())
+}
+
+fn decode(value: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(value.as_bytes())).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockResponse, MockServer};
+    use hyper::header::Headers;
+    use hyper::method::Method;
+    use config::Config;
+    use credentials::Secret;
+    use error::ErrorKind;
+    use providers::sts::{self, StsOptions};
+    use query::QueryRequest;
+    use request::ApiClient;
+    use rest::RestRequest;
+    use signers::sigv4::SigV4;
+    use transport::{HttpClient, HttpRequest, HyperClient, TransportOptions};
+    use xml;
+    use ENV_LOCK as LOCK;
+
+    const SQS_VERSION: &'static str = "2012-11-05";
+    const STS_VERSION: &'static str = "2011-06-15";
+
+    #[test]
+    fn test_sqs() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let client = ApiClient::from_config(&server.config(), "sqs").unwrap();
+        let call = |request: QueryRequest| client.call(&request).unwrap().body_str();
+
+        let created = call(QueryRequest::new("CreateQueue", SQS_VERSION).param("QueueName", "jobs"));
+        let url = xml::find(&created, "QueueUrl").unwrap();
+        assert_eq!(url, format!("{}/123456789012/jobs", server.url()));
+
+        call(QueryRequest::new("SendMessage", SQS_VERSION)
+             .param("QueueUrl", &url)
+             .param("MessageBody", "build <42> & test"));
+        let received = call(QueryRequest::new("ReceiveMessage", SQS_VERSION).param("QueueUrl", &url));
+        assert_eq!(xml::find(&received, "Body").unwrap(), "build <42> & test");
+
+        let handle = xml::find(&received, "ReceiptHandle").unwrap();
+        call(QueryRequest::new("DeleteMessage", SQS_VERSION)
+             .param("QueueUrl", &url)
+             .param("ReceiptHandle", &handle));
+        let empty = call(QueryRequest::new("ReceiveMessage", SQS_VERSION).param("QueueUrl", &url));
+        assert!(xml::find(&empty, "Message").is_none());
+
+        let listed = call(QueryRequest::new("ListQueues", SQS_VERSION));
+        assert!(listed.contains(&url))
+    }
+
+    #[derive(RustcDecodable)]
+    struct ListQueues {
+        next_token: Option<String>,
+    }
+
+    #[test]
+    fn test_paginate() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let client = ApiClient::from_config(&server.config(), "sqs").unwrap();
+        for name in ["a", "b", "c"].iter() {
+            client.call(&QueryRequest::new("CreateQueue", SQS_VERSION).param("QueueName", name)).unwrap();
+        }
+
+        let request = QueryRequest::new("ListQueues", SQS_VERSION).param("MaxResults", "2");
+        let tokens: Vec<Option<String>> = client.paginate::<ListQueues>(&request, "NextToken", "NextToken")
+            .map(|page| page.unwrap().next_token)
+            .collect();
+        assert_eq!(tokens, vec![Some("c".to_string()), None])
+    }
+
+    #[derive(RustcDecodable)]
+    struct Object {
+        key: String,
+        size: u64,
+    }
+
+    #[derive(RustcDecodable)]
+    struct ListBucket {
+        contents: Vec<Object>,
+    }
+
+    #[test]
+    fn test_s3() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let client = ApiClient::from_config(&server.config(), "s3").unwrap();
+        let object = |method: Method, key: &str| {
+            RestRequest::new(method, "/{Bucket}/{Key+}").label("Bucket", "builds").label("Key", key)
+        };
+
+        client.call_rest(&RestRequest::new(Method::Put, "/{Bucket}").label("Bucket", "builds")).unwrap();
+        client.call_rest(&object(Method::Put, "logs/run 1.txt").body(b"passed", "text/plain")).unwrap();
+        client.call_rest(&object(Method::Put, "other.txt").body(b"", "text/plain")).unwrap();
+        let res = client.call_rest(&object(Method::Get, "logs/run 1.txt")).unwrap();
+        assert_eq!(res.body_str(), "passed");
+
+        let list = RestRequest::new(Method::Get, "/{Bucket}?list-type=2")
+            .label("Bucket", "builds")
+            .query("prefix", "logs/");
+        let listed: ListBucket = client.call_rest_decoded(&list).unwrap();
+        assert_eq!(listed.contents.len(), 1);
+        assert_eq!(listed.contents[0].key, "logs/run 1.txt");
+        assert_eq!(listed.contents[0].size, 6);
+
+        client.call_rest(&object(Method::Delete, "logs/run 1.txt")).unwrap();
+        let err = client.call_rest(&object(Method::Get, "logs/run 1.txt")).unwrap_err();
+        assert_eq!(err.code, "NoSuchKey")
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let mut creds = server.credentials();
+        creds.secret = Some(Secret::new("not-the-secret"));
+        let config = Config::new()
+            .region("us-east-1")
+            .no_imds()
+            .credentials_provider(creds)
+            .default_endpoint_url(&server.url())
+            .load();
+        let client = ApiClient::from_config(&config, "sts").unwrap();
+        let err = client.call(&QueryRequest::new("GetCallerIdentity", STS_VERSION)).unwrap_err();
+        assert_eq!(err.code, "SignatureDoesNotMatch");
+        assert_eq!(err.kind, ErrorKind::Auth)
+    }
+
+    #[test]
+    fn test_unsigned_requests() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        server.script(MockResponse::json(r#"{"AccessKeyId": "ASIAAGENT"}"#));
+        let client = HyperClient::new(TransportOptions { no_proxy: true, ..TransportOptions::default() });
+        let get = |path: &str| {
+            let request = HttpRequest {
+                method: Method::Get,
+                url: format!("{}{}", server.url(), path),
+                headers: Headers::new(),
+                body: Vec::new(),
+            };
+            client.send(&request).unwrap()
+        };
+
+        let refused = get("/builds");
+        assert_eq!(refused.status, 403);
+        assert!(refused.body_str().contains("MissingAuthenticationToken"));
+        // container agents don't sign, and the scripted answer is still there
+        let agent = get("/v2/credentials/abc");
+        assert_eq!(agent.status, 200);
+        assert!(agent.body_str().contains("ASIAAGENT"))
+    }
+
+    #[test]
+    fn test_binary_body_signature() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let client = HyperClient::new(TransportOptions { no_proxy: true, ..TransportOptions::default() });
+        let host = server.url()["http://".len()..].to_string();
+        let send = |method: Method, path: &str, body: Vec<u8>| {
+            let headers = SigV4::new()
+                .credentials(server.credentials())
+                .region("us-east-1")
+                .service("s3")
+                .header(("Host", &host))
+                .method(&method.to_string())
+                .path(path)
+                .payload(&body)
+                .as_headers();
+            let request = HttpRequest {
+                method: method,
+                url: format!("{}{}", server.url(), path),
+                headers: headers,
+                body: body,
+            };
+            client.send(&request).unwrap()
+        };
+
+        let image = vec![0x89, 0x50, 0xff, 0x00, 0xfe];
+        assert_eq!(send(Method::Put, "/builds", Vec::new()).status, 200);
+        assert_eq!(send(Method::Put, "/builds/logo.png", image.clone()).status, 200);
+        assert_eq!(send(Method::Get, "/builds/logo.png", Vec::new()).body, image)
+    }
+
+    #[test]
+    fn test_script() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        server.script(MockResponse::error(400, "Throttling", "Rate exceeded"));
+        server.script(MockResponse::xml("<DescribeRegionsResponse><regionInfo/></DescribeRegionsResponse>"));
+        let client = ApiClient::from_config(&server.config(), "ec2").unwrap();
+        let res = client.call(&QueryRequest::new("DescribeRegions", "2016-11-15")).unwrap();
+        assert!(res.body_str().contains("regionInfo"));
+        assert_eq!(server.requests().len(), 2)
+    }
+
+    #[test]
+    fn test_assume_role() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let options = StsOptions::from_config(&server.config());
+        let assumed = sts::assume_role(&server.credentials(), &options, Some("us-east-1"),
+                                       "arn:aws:iam::123456789012:role/deploy", "ci", None, None);
+        let assumed = assumed.unwrap();
+        assert!(assumed.token.is_some());
+
+        // the session credentials are good for further calls
+        let config = Config::new()
+            .region("us-east-1")
+            .no_imds()
+            .credentials_provider(assumed)
+            .default_endpoint_url(&server.url())
+            .load();
+        let client = ApiClient::from_config(&config, "sts").unwrap();
+        let identity = client.call(&QueryRequest::new("GetCallerIdentity", STS_VERSION)).unwrap();
+        assert_eq!(xml::find(&identity.body_str(), "Arn").unwrap(),
+                   "arn:aws:sts::123456789012:assumed-role/deploy/ci")
+    }
+}
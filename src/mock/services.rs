@@ -0,0 +1,313 @@
+use std::collections::{BTreeMap, VecDeque};
+use openssl::crypto::hash::hash;
+use openssl::crypto::hash::Type::MD5;
+use rand::{thread_rng, Rng};
+use serialize::hex::ToHex;
+use time::{at_utc, get_time, Duration};
+use url::percent_encoding::percent_decode;
+
+use credentials::{Credentials, Secret};
+use xml::{self, escape};
+use super::{MockRequest, MockResponse, ACCESS_KEY_ID, ACCOUNT_ID, SECRET_ACCESS_KEY};
+
+struct Session {
+    credentials: Credentials,
+    arn: String,
+}
+
+struct Message {
+    id: String,
+    body: String,
+    // set once received, until deleted
+    receipt: Option<String>,
+}
+
+/// The in-memory state of the mocked services.
+pub struct Services {
+    sessions: Vec<Session>,
+    buckets: BTreeMap<String, BTreeMap<String, Vec<u8>>>,
+    queues: BTreeMap<String, VecDeque<Message>>,
+}
+
+impl Services {
+    pub fn new() -> Services {
+        Services {
+            sessions: Vec::new(),
+            buckets: BTreeMap::new(),
+            queues: BTreeMap::new(),
+        }
+    }
+
+    /// The credentials behind an access key, if the server knows it and the
+    /// session token, for temporary credentials, matches.
+    pub fn credentials(&self, key: &str, token: Option<&str>) -> Option<Credentials> {
+        if key == ACCESS_KEY_ID && token.is_none() {
+            let mut creds = Credentials::new();
+            creds.key = Some(ACCESS_KEY_ID.to_string());
+            creds.secret = Some(Secret::new(SECRET_ACCESS_KEY));
+            return Some(creds)
+        }
+        self.sessions.iter()
+            .find(|s| s.credentials.key.as_ref().map_or(false, |k| k == key) &&
+                      s.credentials.token.as_ref().map(|t| t.expose()) == token)
+            .map(|s| s.credentials.clone())
+    }
+
+    pub fn handle(&mut self, service: &str, request: &MockRequest, url: &str) -> MockResponse {
+        match service {
+            "s3" => self.s3(request),
+            "sqs" => self.sqs(request, url),
+            "sts" => self.sts(request),
+            _ => MockResponse::error(400, "UnknownService",
+                                     &format!("The mock server doesn't implement {:?}", service)),
+        }
+    }
+
+    fn s3(&mut self, request: &MockRequest) -> MockResponse {
+        let path = String::from_utf8_lossy(&percent_decode(request.path.as_bytes())).into_owned();
+        let mut parts = path.trim_left_matches('/').splitn(2, '/');
+        let bucket = parts.next().unwrap_or("").to_string();
+        let key = parts.next().unwrap_or("").to_string();
+
+        if bucket.is_empty() {
+            let names: Vec<String> = self.buckets.keys()
+                .map(|b| format!("<Bucket><Name>{}</Name></Bucket>", escape(b)))
+                .collect();
+            return MockResponse::xml(&format!(
+                "<ListAllMyBucketsResult><Owner><ID>{}</ID></Owner><Buckets>{}</Buckets></ListAllMyBucketsResult>",
+                ACCOUNT_ID, names.concat()))
+        }
+        if key.is_empty() {
+            return self.s3_bucket(request, &bucket)
+        }
+
+        let objects = match self.buckets.get_mut(&bucket) {
+            Some(objects) => objects,
+            None => return s3_error(404, "NoSuchBucket", "The specified bucket does not exist"),
+        };
+        match &request.method[..] {
+            "PUT" => {
+                let etag = format!("\"{}\"", hash(MD5, &request.body).to_hex());
+                objects.insert(key, request.body.clone());
+                MockResponse::new(200, "").header("ETag", &etag)
+            },
+            "GET" | "HEAD" => match objects.get(&key) {
+                Some(body) => MockResponse {
+                    status: 200,
+                    headers: vec![("ETag".to_string(), format!("\"{}\"", hash(MD5, body).to_hex()))],
+                    body: body.clone(),
+                },
+                None => s3_error(404, "NoSuchKey", "The specified key does not exist."),
+            },
+            "DELETE" => {
+                objects.remove(&key);
+                MockResponse::new(204, "")
+            },
+            _ => s3_error(405, "MethodNotAllowed", "The specified method is not allowed"),
+        }
+    }
+
+    fn s3_bucket(&mut self, request: &MockRequest, bucket: &str) -> MockResponse {
+        match &request.method[..] {
+            "PUT" => {
+                if self.buckets.contains_key(bucket) {
+                    return s3_error(409, "BucketAlreadyOwnedByYou", "Your previous request to create the named bucket succeeded")
+                }
+                if !request.body.is_empty() {
+                    let location = match xml::parse(&request.body_str()) {
+                        Ok(ref root) if root.name == "CreateBucketConfiguration" => {
+                            root.child("LocationConstraint").map(|l| l.text.clone())
+                        },
+                        _ => return s3_error(400, "MalformedXML", "The XML you provided was not well-formed or did not \
+                                                                  validate against our published schema"),
+                    };
+                    // the server's own region is the default and can't be named
+                    if location.as_ref().map_or(false, |l| l == "us-east-1") {
+                        return s3_error(400, "InvalidLocationConstraint", "The specified location-constraint is not valid")
+                    }
+                }
+                self.buckets.insert(bucket.to_string(), BTreeMap::new());
+                MockResponse::new(200, "").header("Location", &format!("/{}", bucket))
+            },
+            "DELETE" => match self.buckets.get(bucket).map(|o| o.is_empty()) {
+                Some(true) => {
+                    self.buckets.remove(bucket);
+                    MockResponse::new(204, "")
+                },
+                Some(false) => s3_error(409, "BucketNotEmpty", "The bucket you tried to delete is not empty"),
+                None => s3_error(404, "NoSuchBucket", "The specified bucket does not exist"),
+            },
+            "GET" | "HEAD" => {
+                let objects = match self.buckets.get(bucket) {
+                    Some(objects) => objects,
+                    None => return s3_error(404, "NoSuchBucket", "The specified bucket does not exist"),
+                };
+                let prefix = request.param("prefix").unwrap_or(String::new());
+                let contents: Vec<String> = objects.iter()
+                    .filter(|&(k, _)| k.starts_with(&prefix))
+                    .map(|(k, v)| format!("<Contents><Key>{}</Key><Size>{}</Size></Contents>", escape(k), v.len()))
+                    .collect();
+                MockResponse::xml(&format!(
+                    "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount>\
+                     <IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                    escape(bucket), escape(&prefix), contents.len(), contents.concat()))
+            },
+            _ => s3_error(405, "MethodNotAllowed", "The specified method is not allowed"),
+        }
+    }
+
+    fn sqs(&mut self, request: &MockRequest, url: &str) -> MockResponse {
+        let action = request.param("Action").unwrap_or(String::new());
+        let queue_url = |name: &str| format!("{}/{}/{}", url, ACCOUNT_ID, name);
+        let name = request.param("QueueUrl")
+            .map(|u| u.rsplit('/').next().unwrap().to_string())
+            .or_else(|| request.param("QueueName"));
+
+        if action == "ListQueues" {
+            // the token is the name of the first queue on the next page
+            let prefix = request.param("QueueNamePrefix").unwrap_or(String::new());
+            let start = request.param("NextToken").unwrap_or(String::new());
+            let max = request.param("MaxResults").and_then(|m| m.parse().ok()).unwrap_or(1000);
+            let mut names = self.queues.keys().filter(|q| q.starts_with(&prefix) && **q >= start);
+            let mut result: Vec<String> = names.by_ref().take(max)
+                .map(|q| format!("<QueueUrl>{}</QueueUrl>", escape(&queue_url(q))))
+                .collect();
+            if let Some(next) = names.next() {
+                result.push(format!("<NextToken>{}</NextToken>", escape(next)));
+            }
+            return query_response(&action, &result.concat())
+        }
+        let name = match name {
+            Some(name) => name,
+            None => return MockResponse::error(400, "MissingParameter", "A queue name or URL is required"),
+        };
+        if action == "CreateQueue" {
+            if !self.queues.contains_key(&name) {
+                self.queues.insert(name.clone(), VecDeque::new());
+            }
+            return query_response(&action, &format!("<QueueUrl>{}</QueueUrl>", escape(&queue_url(&name))))
+        }
+
+        let messages = match self.queues.get_mut(&name) {
+            Some(messages) => messages,
+            None => return MockResponse::error(400, "AWS.SimpleQueueService.NonExistentQueue",
+                                               "The specified queue does not exist for this wsdl version."),
+        };
+        match &action[..] {
+            "GetQueueUrl" => query_response(&action, &format!("<QueueUrl>{}</QueueUrl>", escape(&queue_url(&name)))),
+            "SendMessage" => {
+                let body = request.param("MessageBody").unwrap_or(String::new());
+                let id = request_id();
+                let md5 = hash(MD5, body.as_bytes()).to_hex();
+                messages.push_back(Message { id: id.clone(), body: body, receipt: None });
+                query_response(&action, &format!("<MessageId>{}</MessageId><MD5OfMessageBody>{}</MD5OfMessageBody>", id, md5))
+            },
+            "ReceiveMessage" => {
+                let max = request.param("MaxNumberOfMessages").and_then(|m| m.parse().ok()).unwrap_or(1);
+                let mut out = String::new();
+                for message in messages.iter_mut().filter(|m| m.receipt.is_none()).take(max) {
+                    let receipt = random_string(64);
+                    out.push_str(&format!("<Message><MessageId>{}</MessageId><ReceiptHandle>{}</ReceiptHandle>\
+                                           <MD5OfBody>{}</MD5OfBody><Body>{}</Body></Message>",
+                                          message.id, receipt, hash(MD5, message.body.as_bytes()).to_hex(),
+                                          escape(&message.body)));
+                    message.receipt = Some(receipt);
+                }
+                query_response(&action, &out)
+            },
+            "DeleteMessage" => {
+                let receipt = request.param("ReceiptHandle");
+                messages.retain(|m| m.receipt.is_none() || m.receipt != receipt);
+                query_response(&action, "")
+            },
+            "DeleteQueue" => {
+                self.queues.remove(&name);
+                query_response(&action, "")
+            },
+            _ => MockResponse::error(400, "InvalidAction", &format!("The action {} is not valid for this endpoint.", action)),
+        }
+    }
+
+    fn sts(&mut self, request: &MockRequest) -> MockResponse {
+        let action = request.param("Action").unwrap_or(String::new());
+        match &action[..] {
+            "GetCallerIdentity" => {
+                let key = access_key(request).unwrap_or(String::new());
+                let arn = self.sessions.iter()
+                    .find(|s| s.credentials.key.as_ref() == Some(&key))
+                    .map(|s| s.arn.clone())
+                    .unwrap_or(format!("arn:aws:iam::{}:user/mock", ACCOUNT_ID));
+                query_response(&action, &format!("<Arn>{}</Arn><UserId>{}</UserId><Account>{}</Account>",
+                                                 escape(&arn), key, ACCOUNT_ID))
+            },
+            "AssumeRole" | "AssumeRoleWithWebIdentity" => {
+                let (role_arn, session_name) = match (request.param("RoleArn"), request.param("RoleSessionName")) {
+                    (Some(role_arn), Some(session_name)) => (role_arn, session_name),
+                    _ => return MockResponse::error(400, "MissingParameter",
+                                                    "RoleArn and RoleSessionName are required"),
+                };
+                let role = role_arn.rsplit('/').next().unwrap().to_string();
+                let arn = format!("arn:aws:sts::{}:assumed-role/{}/{}", ACCOUNT_ID, role, session_name);
+                let result = format!("{}<AssumedRoleUser><Arn>{}</Arn></AssumedRoleUser>",
+                                     self.issue(&arn), escape(&arn));
+                query_response(&action, &result)
+            },
+            "GetSessionToken" => {
+                let arn = format!("arn:aws:iam::{}:user/mock", ACCOUNT_ID);
+                let result = self.issue(&arn);
+                query_response(&action, &result)
+            },
+            _ => MockResponse::error(400, "InvalidAction", &format!("Could not find operation {}", action)),
+        }
+    }
+
+    // Hand out session credentials for `arn`, returning their `<Credentials>` element.
+    fn issue(&mut self, arn: &str) -> String {
+        let mut creds = Credentials::new();
+        let key = format!("ASIA{}", random_string(16).to_uppercase());
+        let secret = random_string(40);
+        let token = random_string(64);
+        let expiration = at_utc(get_time() + Duration::hours(1));
+        let element = format!("<Credentials><AccessKeyId>{}</AccessKeyId><SecretAccessKey>{}</SecretAccessKey>\
+                               <SessionToken>{}</SessionToken><Expiration>{}</Expiration></Credentials>",
+                              key, secret, token, expiration.strftime("%Y-%m-%dT%H:%M:%SZ").unwrap());
+        creds.key = Some(key);
+        creds.secret = Some(Secret::new(secret));
+        creds.token = Some(Secret::new(token));
+        self.sessions.push(Session { credentials: creds, arn: arn.to_string() });
+        element
+    }
+}
+
+pub fn request_id() -> String {
+    let hex = random_hex(32);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+fn random_hex(len: usize) -> String {
+    let bytes: Vec<u8> = thread_rng().gen_iter().take(len / 2).collect();
+    bytes.to_hex()
+}
+
+fn random_string(len: usize) -> String {
+    thread_rng().gen_ascii_chars().take(len).collect()
+}
+
+fn access_key(request: &MockRequest) -> Option<String> {
+    request.header("authorization")
+        .and_then(|auth| auth.split("Credential=").nth(1))
+        .and_then(|scope| scope.split('/').next())
+        .map(|key| key.to_string())
+}
+
+fn query_response(action: &str, result: &str) -> MockResponse {
+    MockResponse::xml(&format!("<{0}Response><{0}Result>{1}</{0}Result>\
+                                <ResponseMetadata><RequestId>{2}</RequestId></ResponseMetadata></{0}Response>",
+                               action, result, request_id()))
+}
+
+fn s3_error(status: u16, code: &str, message: &str) -> MockResponse {
+    let body = format!("<Error><Code>{}</Code><Message>{}</Message><RequestId>{}</RequestId></Error>",
+                       code, escape(message), random_hex(16).to_uppercase());
+    MockResponse::new(status, &body).header("Content-Type", "application/xml")
+}
@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use error::AwsError;
+
+/// The pages of a call that hands back a token for the next one, such as
+/// `NextToken`, `ContinuationToken` or `Marker`, fetched as the iterator is
+/// advanced.
+///
+/// `fetch` is called with the token of the page to get, `None` for the first
+/// one, and returns the page with the token of the next. Iteration stops
+/// when there's no next token, when the service hands back the token it was
+/// given, or after the first error.
+///
+/// Pages are fetched on the calling thread.
+pub struct Paginator<T, E = AwsError> {
+    fetch: Box<FnMut(Option<&str>) -> Result<(T, Option<String>), E> + Send>,
+    // the token of the next page, `None` once there are no more
+    next: Option<Option<String>>,
+}
+
+impl<T, E> Paginator<T, E> {
+    pub fn new<F>(fetch: F) -> Paginator<T, E>
+        where F: FnMut(Option<&str>) -> Result<(T, Option<String>), E> + Send + 'static {
+        Paginator {
+            fetch: Box::new(fetch),
+            next: Some(None),
+        }
+    }
+
+    /// Iterate over the items of each page instead, as returned by `extract`.
+    pub fn items<I, F>(self, extract: F) -> Items<T, E, I>
+        where F: FnMut(T) -> Vec<I> + Send + 'static {
+        Items {
+            pages: self,
+            extract: Box::new(extract),
+            buffer: VecDeque::new(),
+            remaining: None,
+        }
+    }
+}
+
+impl<T, E> Iterator for Paginator<T, E> {
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Result<T, E>> {
+        let token = match self.next.take() {
+            Some(token) => token,
+            None => return None,
+        };
+        let (page, next) = match (self.fetch)(token.as_ref().map(|t| &t[..])) {
+            Ok(fetched) => fetched,
+            Err(err) => return Some(Err(err)),
+        };
+        self.next = match next {
+            Some(next) if !next.is_empty() && Some(&next) != token.as_ref() => Some(Some(next)),
+            _ => None,
+        };
+        Some(Ok(page))
+    }
+}
+
+/// The items of every page, see `Paginator::items`.
+pub struct Items<T, E, I> {
+    pages: Paginator<T, E>,
+    extract: Box<FnMut(T) -> Vec<I> + Send>,
+    buffer: VecDeque<I>,
+    remaining: Option<usize>,
+}
+
+impl<T, E, I> Items<T, E, I> {
+    /// Stop after `max` items, without fetching any more pages than needed.
+    pub fn max_items(mut self, max: usize) -> Items<T, E, I> {
+        self.remaining = Some(max);
+        self
+    }
+}
+
+impl<T, E, I> Iterator for Items<T, E, I> {
+    type Item = Result<I, E>;
+
+    fn next(&mut self) -> Option<Result<I, E>> {
+        if self.remaining == Some(0) {
+            return None
+        }
+        loop {
+            if let Some(item) = self.buffer.pop_front() {
+                self.remaining = self.remaining.map(|r| r - 1);
+                return Some(Ok(item))
+            }
+            match self.pages.next() {
+                Some(Ok(page)) => self.buffer.extend((self.extract)(page)),
+                Some(Err(err)) => return Some(Err(err)),
+                None => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Paginator;
+    use std::sync::{Arc, Mutex};
+
+    // Pages of three numbers, up to 7, each token the first number of its page.
+    fn numbers(calls: Arc<Mutex<usize>>) -> Paginator<Vec<u32>, String> {
+        Paginator::new(move |token: Option<&str>| {
+            *calls.lock().unwrap() += 1;
+            let start: u32 = token.map_or(1, |t| t.parse().unwrap());
+            let page: Vec<u32> = (start..start + 3).filter(|n| *n <= 7).collect();
+            let next = if start + 3 <= 7 { Some((start + 3).to_string()) } else { None };
+            Ok((page, next))
+        })
+    }
+
+    #[test]
+    fn test_pages() {
+        let calls = Arc::new(Mutex::new(0));
+        let pages: Vec<Vec<u32>> = numbers(calls.clone()).map(|p| p.unwrap()).collect();
+        assert_eq!(pages, vec![vec![1, 2, 3], vec![4, 5, 6], vec![7]]);
+        assert_eq!(*calls.lock().unwrap(), 3);
+
+        let items: Vec<u32> = numbers(calls.clone()).items(|p| p).map(|i| i.unwrap()).collect();
+        assert_eq!(items, vec![1, 2, 3, 4, 5, 6, 7])
+    }
+
+    #[test]
+    fn test_max_items() {
+        let calls = Arc::new(Mutex::new(0));
+        let items: Vec<u32> = numbers(calls.clone()).items(|p| p).max_items(4).map(|i| i.unwrap()).collect();
+        assert_eq!(items, vec![1, 2, 3, 4]);
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_stops() {
+        let mut failing = Paginator::new(|token: Option<&str>| match token {
+            None => Ok((1, Some("2".to_string()))),
+            Some(_) => Err("InvalidToken".to_string()),
+        });
+        assert_eq!(failing.next(), Some(Ok(1)));
+        assert_eq!(failing.next(), Some(Err("InvalidToken".to_string())));
+        assert_eq!(failing.next(), None);
+
+        // a service handing back the token it was given has no more pages
+        let mut repeating = Paginator::new(|_: Option<&str>| -> Result<(u8, Option<String>), String> {
+            Ok((1, Some("same".to_string())))
+        });
+        assert_eq!(repeating.next(), Some(Ok(1)));
+        assert_eq!(repeating.next(), Some(Ok(1)));
+        assert_eq!(repeating.next(), None)
+    }
+}
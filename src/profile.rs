@@ -0,0 +1,887 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use rand::{thread_rng, Rng};
+
+use non_empty_var;
+
+/// The user's home directory.
+///
+/// `HOME` is checked first, then the Windows variables `USERPROFILE` and
+/// `HOMEDRIVE` with `HOMEPATH`, the same order the AWS CLI uses.
+pub fn home_dir() -> Option<PathBuf> {
+    if let Some(home) = non_empty_var("HOME") {
+        return Some(PathBuf::from(home))
+    }
+    if let Some(profile) = non_empty_var("USERPROFILE") {
+        return Some(PathBuf::from(profile))
+    }
+    if let (Some(drive), Some(path)) = (non_empty_var("HOMEDRIVE"), non_empty_var("HOMEPATH")) {
+        return Some(PathBuf::from(format!("{}{}", drive, path)))
+    }
+    env::home_dir()
+}
+
+/// Expand a leading `~` to the home directory.
+pub fn expand_home(path: &str) -> String {
+    let rest = if path == "~" {
+        ""
+    } else if path.starts_with("~/") || path.starts_with("~\\") {
+        &path[2..]
+    } else {
+        return path.to_string()
+    };
+    match home_dir() {
+        Some(mut home) => {
+            if !rest.is_empty() {
+                home.push(rest);
+            }
+            home.to_str().unwrap().to_string()
+        },
+        None => path.to_string(),
+    }
+}
+
+/// Path to a file under `~/.aws`.
+pub fn aws_path(file: &str) -> String {
+    let mut p = match home_dir() {
+        Some(home) => home,
+        None => {
+            warn!("Could not find a home directory, looking for .aws in the working directory");
+            PathBuf::new()
+        }
+    };
+    p.push(".aws");
+    p.push(file);
+    p.to_str().unwrap().to_string()
+}
+
+/// Path to the shared credentials file, `~/.aws/credentials` unless
+/// `AWS_SHARED_CREDENTIALS_FILE` is set.
+pub fn credentials_path() -> String {
+    match env::var("AWS_SHARED_CREDENTIALS_FILE") {
+        Ok(ref path) if !path.is_empty() => expand_home(path),
+        _ => aws_path("credentials"),
+    }
+}
+
+/// Path to the shared config file, `~/.aws/config` unless `AWS_CONFIG_FILE` is set.
+pub fn config_path() -> String {
+    match env::var("AWS_CONFIG_FILE") {
+        Ok(ref path) if !path.is_empty() => expand_home(path),
+        _ => aws_path("config"),
+    }
+}
+
+/// The profile to use when none is given: `AWS_PROFILE`, then
+/// `AWS_DEFAULT_PROFILE`, then `default`.
+pub fn default_profile() -> String {
+    non_empty_var("AWS_PROFILE")
+        .or_else(|| non_empty_var("AWS_DEFAULT_PROFILE"))
+        .unwrap_or("default".to_string())
+}
+
+/// Section name for a profile in the shared config file, which prefixes
+/// everything but the default profile with `profile `.
+pub fn config_section(profile: &str) -> String {
+    if profile == "default" {
+        profile.to_string()
+    } else {
+        format!("profile {}", profile)
+    }
+}
+
+/// Look up a single key for a profile in the shared config file.
+pub fn config_value(path: &str, profile: &str, key: &str) -> Option<String> {
+    section_value(path, &config_section(profile), key)
+        .or_else(|| section_value(path, profile, key))
+}
+
+/// Look up a single key for a profile in the config file, then the credentials file.
+pub fn profile_value(profile: &str, key: &str) -> Option<String> {
+    config_value(&config_path(), profile, key)
+        .or_else(|| config_value(&credentials_path(), profile, key))
+}
+
+/// Look up a single key in any section of an ini file, such as `sso-session name`.
+pub fn section_value(path: &str, section: &str, key: &str) -> Option<String> {
+    match ProfileFile::load(path) {
+        Ok(file) => file.get(section, key).map(|v| v.to_string()),
+        Err(_) => None,
+    }
+}
+
+/// Keys `aws configure set` writes to the credentials file rather than the config file.
+const CREDENTIAL_KEYS: [&'static str; 3] =
+    ["aws_access_key_id", "aws_secret_access_key", "aws_session_token"];
+
+/// Set a key for a profile in the file it belongs in, the way `aws configure set` does.
+pub fn set_profile_value(profile: &str, key: &str, value: &str) -> io::Result<()> {
+    let (path, section) = if CREDENTIAL_KEYS.contains(&key) {
+        (credentials_path(), profile.to_string())
+    } else {
+        (config_path(), config_section(profile))
+    };
+    let mut file = try!(ProfileFile::load_or_empty(&path));
+    file.set(&section, key, value);
+    file.save()
+}
+
+/// Write a file only the current user can read, creating its directory.
+///
+/// The body is written to a new file beside it, readable only by the user
+/// from the start, which is then renamed over the original. A crash can't
+/// leave the file half written. When the path is a symlink, the file it
+/// points to is replaced and the link is kept.
+pub fn write_private(path: &Path, body: &str) -> io::Result<()> {
+    replace_file(path, body.as_bytes(), create_private)
+}
+
+/// Write a file through a new file beside it that is renamed over the
+/// original, as `write_private` does but with the usual permissions.
+pub fn write_atomic(path: &Path, body: &[u8]) -> io::Result<()> {
+    replace_file(path, body, |temp| OpenOptions::new().write(true).create_new(true).open(temp))
+}
+
+fn replace_file<F>(path: &Path, body: &[u8], create: F) -> io::Result<()>
+    where F: Fn(&Path) -> io::Result<File> {
+    let path = &try!(resolve_links(path));
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    try!(fs::create_dir_all(&dir));
+    let name = path.file_name().map_or("file".to_string(), |n| n.to_string_lossy().into_owned());
+    let temp = dir.join(format!(".{}.{:08x}.tmp", name, thread_rng().gen::<u32>()));
+
+    let written = create(&temp)
+        .and_then(|mut file| {
+            try!(file.write_all(body));
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+// Follow symlinks to the file they name, even one that doesn't exist yet,
+// so dotfiles linked in from elsewhere stay linked.
+fn resolve_links(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(ref meta) if meta.file_type().is_symlink() => {
+                let target = try!(fs::read_link(&path));
+                // a relative target is relative to the link's directory
+                path = match path.parent() {
+                    Some(dir) => dir.join(target),
+                    None => target,
+                };
+            },
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::Other, "too many levels of symbolic links"))
+}
+
+#[cfg(unix)]
+fn create_private(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Where a profile value was found.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Source {
+    /// An environment variable, by name.
+    Env(String),
+    /// A file and the line number of the key, counting from one.
+    File(String, usize),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Env(ref var) => write!(f, "env {}", var),
+            Source::File(ref path, line) => write!(f, "{}:{}", path, line),
+        }
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+    /// Last line of the value, past `line` for nested values.
+    pub end: usize,
+}
+
+#[derive(Clone,Debug)]
+pub struct Section {
+    pub name: String,
+    pub line: usize,
+    /// Last line holding a key of this section, or the header if it has none.
+    pub end: usize,
+    pub entries: Vec<Entry>,
+}
+
+impl Section {
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.key == key)
+    }
+}
+
+/// An ini file in the format of `~/.aws/config` and `~/.aws/credentials`,
+/// keeping track of the line every section and key came from.
+///
+/// The original lines are kept, so a file can be edited with `set` and saved
+/// with its comments, blank lines, ordering, line endings and byte order
+/// mark intact.
+#[derive(Clone,Debug)]
+pub struct ProfileFile {
+    pub path: String,
+    pub sections: Vec<Section>,
+    lines: Vec<String>,
+    newline: &'static str,
+    bom: bool,
+}
+
+impl ProfileFile {
+    pub fn load(path: &str) -> io::Result<ProfileFile> {
+        let mut body = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut body));
+        Ok(ProfileFile::parse(path, &body))
+    }
+
+    /// A missing file is treated as an empty one, any other error is returned.
+    pub fn load_or_empty(path: &str) -> io::Result<ProfileFile> {
+        match ProfileFile::load(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(ProfileFile::parse(path, "")),
+            result => result,
+        }
+    }
+
+    /// For lookups, where a file that can't be read is logged and then
+    /// treated as an empty one.
+    pub fn load_or_warn(path: &str) -> ProfileFile {
+        ProfileFile::load_or_empty(path).unwrap_or_else(|e| {
+            warn!("Ignoring {}: {}", path, e);
+            ProfileFile::parse(path, "")
+        })
+    }
+
+    pub fn parse(path: &str, body: &str) -> ProfileFile {
+        // editors on Windows like to start files with a byte order mark
+        let bom = body.starts_with('\u{feff}');
+        let body = body.trim_left_matches('\u{feff}');
+        let mut sections: Vec<Section> = Vec::new();
+        for (i, raw) in body.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with("#") || line.starts_with(";") {
+                continue
+            }
+            if line.starts_with("[") && line.ends_with("]") {
+                sections.push(Section {
+                    name: line[1..line.len() - 1].trim().to_string(),
+                    line: i + 1,
+                    end: i + 1,
+                    entries: Vec::new(),
+                });
+                continue
+            }
+            let section = match sections.last_mut() {
+                Some(section) => section,
+                None => continue,
+            };
+            // indented lines continue the previous value, as in nested `s3 =` settings
+            let indented = raw.starts_with(" ") || raw.starts_with("\t");
+            if indented && !section.entries.is_empty() {
+                let entry = section.entries.last_mut().unwrap();
+                if !entry.value.is_empty() {
+                    entry.value.push('\n');
+                }
+                entry.value.push_str(line);
+                entry.end = i + 1;
+                section.end = i + 1;
+                continue
+            }
+            if let Some(eq) = line.find('=') {
+                section.entries.push(Entry {
+                    key: line[..eq].trim().to_string(),
+                    value: line[eq + 1..].trim().to_string(),
+                    line: i + 1,
+                    end: i + 1,
+                });
+                section.end = i + 1;
+            }
+        }
+        ProfileFile {
+            path: path.to_string(),
+            sections: sections,
+            lines: body.lines().map(|l| l.to_string()).collect(),
+            newline: if body.contains("\r\n") { "\r\n" } else { "\n" },
+            bom: bom,
+        }
+    }
+
+    /// Set a key, replacing its current value, adding it to the end of its
+    /// section, or adding the section to the end of the file.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let line = format!("{} = {}", key, value);
+        let existing = self.section(section).map(|s| match s.get(key) {
+            Some(entry) => (entry.line - 1, entry.end),
+            None => (s.end, s.end),
+        });
+        let (start, end) = match existing {
+            Some(range) => range,
+            None => {
+                while self.lines.last().map_or(false, |l| l.trim().is_empty()) {
+                    self.lines.pop();
+                }
+                if !self.lines.is_empty() {
+                    self.lines.push(String::new());
+                }
+                self.lines.push(format!("[{}]", section));
+                (self.lines.len(), self.lines.len())
+            }
+        };
+        let tail = self.lines.split_off(end);
+        self.lines.truncate(start);
+        self.lines.push(line);
+        self.lines.extend(tail);
+
+        let (path, body) = (self.path.clone(), self.to_string());
+        *self = ProfileFile::parse(&path, &body);
+    }
+
+    /// Write the file back to its path, readable only by the current user.
+    pub fn save(&self) -> io::Result<()> {
+        write_private(Path::new(&self.path), &self.to_string())
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section).and_then(|s| s.get(key)).map(|e| e.value.as_ref())
+    }
+}
+
+impl fmt::Display for ProfileFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.bom {
+            try!(write!(f, "\u{feff}"));
+        }
+        for line in self.lines.iter() {
+            try!(write!(f, "{}{}", line, self.newline));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct Value {
+    pub value: String,
+    pub source: Source,
+}
+
+/// A resolved profile, with every value tagged with where it came from.
+#[derive(Clone,Debug)]
+pub struct Profile {
+    pub name: String,
+    pub values: BTreeMap<String, Value>,
+    /// The resolved `source_profile`, if this profile has one.
+    pub source_profile: Option<Box<Profile>>,
+}
+
+impl Profile {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|v| v.value.as_ref())
+    }
+
+    pub fn source(&self, key: &str) -> Option<&Source> {
+        self.values.get(key).map(|v| &v.source)
+    }
+}
+
+/// Why a profile could not be resolved or would not work.
+#[derive(Clone,Debug,PartialEq)]
+pub enum ProfileError {
+    NotFound(String),
+    /// A profile with `aws_access_key_id` but no `aws_secret_access_key`.
+    MissingSecret(String),
+    /// A profile with `aws_secret_access_key` but no `aws_access_key_id`.
+    MissingKey(String),
+    /// A profile with `role_arn` and nothing to assume the role with.
+    MissingRoleSource(String),
+    /// A `source_profile` that doesn't exist, as (profile, source_profile).
+    MissingSourceProfile(String, String),
+    /// A chain of `source_profile`s that loops back on itself.
+    SourceProfileLoop(Vec<String>),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProfileError::NotFound(ref p) => write!(f, "profile {} could not be found", p),
+            ProfileError::MissingSecret(ref p) =>
+                write!(f, "profile {} has aws_access_key_id but no aws_secret_access_key", p),
+            ProfileError::MissingKey(ref p) =>
+                write!(f, "profile {} has aws_secret_access_key but no aws_access_key_id", p),
+            ProfileError::MissingRoleSource(ref p) =>
+                write!(f, "profile {} has role_arn but no source_profile, credential_source \
+                           or web_identity_token_file", p),
+            ProfileError::MissingSourceProfile(ref p, ref s) =>
+                write!(f, "profile {} has source_profile {} which could not be found", p, s),
+            ProfileError::SourceProfileLoop(ref chain) =>
+                write!(f, "source_profile loop: {}", chain.join(" -> ")),
+        }
+    }
+}
+
+impl Error for ProfileError {
+    fn description(&self) -> &str {
+        "invalid profile"
+    }
+}
+
+// Environment variables that override the region, in order of precedence.
+const REGION_VARS: [&'static str; 2] = ["AWS_REGION", "AWS_DEFAULT_REGION"];
+
+// How much of the environment applies to a profile being resolved.
+#[derive(Clone,Copy,PartialEq)]
+enum EnvOverrides {
+    // source profiles come from the files alone
+    Nothing,
+    // a profile chosen explicitly still takes its region from the environment
+    Region,
+    All,
+}
+
+/// Every profile in the shared config and credentials files.
+#[derive(Clone,Debug)]
+pub struct Profiles {
+    pub config: ProfileFile,
+    pub credentials: ProfileFile,
+}
+
+impl Profiles {
+    /// Read the files at their default locations.
+    pub fn load() -> Profiles {
+        Profiles::from_paths(&config_path(), &credentials_path())
+    }
+
+    pub fn from_paths(config: &str, credentials: &str) -> Profiles {
+        Profiles {
+            config: ProfileFile::load_or_warn(config),
+            credentials: ProfileFile::load_or_warn(credentials),
+        }
+    }
+
+    /// Names of all profiles, in the order they first appear in the credentials
+    /// file and then the config file.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        let from_credentials = self.credentials.sections.iter().map(|s| s.name.clone());
+        let from_config = self.config.sections.iter().filter_map(|s| {
+            if s.name == "default" {
+                Some(s.name.clone())
+            } else if s.name.starts_with("profile ") {
+                Some(s.name["profile ".len()..].trim().to_string())
+            } else {
+                None
+            }
+        });
+        for name in from_credentials.chain(from_config) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Resolve a profile, following `source_profile`, and check that it can work.
+    ///
+    /// Values come from the environment first, then the credentials file, then
+    /// the config file. As with `Credentials::load`, keys in the environment are
+    /// only used when both `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` are
+    /// set, and then replace the file's along with its session token. All
+    /// problems found are returned, not just the first.
+    pub fn resolve(&self, name: &str) -> Result<Profile, Vec<ProfileError>> {
+        self.resolve_with(name, EnvOverrides::All)
+    }
+
+    /// Resolve a profile that was asked for by name, as with `--profile`. Keys
+    /// in the environment are ignored then, only the region is taken from it.
+    pub fn resolve_explicit(&self, name: &str) -> Result<Profile, Vec<ProfileError>> {
+        self.resolve_with(name, EnvOverrides::Region)
+    }
+
+    fn resolve_with(&self, name: &str, env: EnvOverrides) -> Result<Profile, Vec<ProfileError>> {
+        let mut errors = Vec::new();
+        let profile = self.resolve_chain(name, &mut Vec::new(), &mut errors, env);
+        match profile {
+            Some(profile) => if errors.is_empty() { Ok(profile) } else { Err(errors) },
+            None => Err(errors),
+        }
+    }
+
+    fn file_values(&self, name: &str) -> Option<BTreeMap<String, Value>> {
+        let config = self.config.section(&config_section(name))
+            .or_else(|| self.config.section(name));
+        let credentials = self.credentials.section(name);
+        if config.is_none() && credentials.is_none() {
+            return None
+        }
+
+        let mut values = BTreeMap::new();
+        for &(section, path) in [(config, &self.config.path), (credentials, &self.credentials.path)].iter() {
+            if let Some(section) = section {
+                for entry in section.entries.iter() {
+                    values.insert(entry.key.clone(), Value {
+                        value: entry.value.clone(),
+                        source: Source::File(path.clone(), entry.line),
+                    });
+                }
+            }
+        }
+        Some(values)
+    }
+
+    fn resolve_chain(&self, name: &str, seen: &mut Vec<String>, errors: &mut Vec<ProfileError>,
+                     env: EnvOverrides) -> Option<Profile> {
+        if seen.iter().any(|s| s == name) {
+            let mut chain = seen.clone();
+            chain.push(name.to_string());
+            errors.push(ProfileError::SourceProfileLoop(chain));
+            return None
+        }
+        seen.push(name.to_string());
+
+        let mut values = match self.file_values(name) {
+            Some(values) => values,
+            None if env == EnvOverrides::All => BTreeMap::new(),
+            None => return None,
+        };
+        if env != EnvOverrides::Nothing {
+            for var in REGION_VARS.iter().rev() {
+                if let Some(value) = non_empty_var(var) {
+                    values.insert("region".to_string(), Value {
+                        value: value,
+                        source: Source::Env(var.to_string()),
+                    });
+                }
+            }
+        }
+        if env == EnvOverrides::All {
+            if let (Some(key), Some(secret)) = (non_empty_var("AWS_ACCESS_KEY_ID"),
+                                                non_empty_var("AWS_SECRET_ACCESS_KEY")) {
+                let mut keys = vec![("aws_access_key_id", key, "AWS_ACCESS_KEY_ID"),
+                                    ("aws_secret_access_key", secret, "AWS_SECRET_ACCESS_KEY")];
+                keys.extend(["AWS_SESSION_TOKEN", "AWS_SECURITY_TOKEN"].iter()
+                    .filter_map(|var| non_empty_var(var).map(|token| ("aws_session_token", token, *var)))
+                    .next());
+                values.remove("aws_security_token");
+                values.remove("aws_session_token");
+                for (key, value, var) in keys {
+                    values.insert(key.to_string(), Value {
+                        value: value,
+                        source: Source::Env(var.to_string()),
+                    });
+                }
+            }
+        }
+        if values.is_empty() {
+            errors.push(ProfileError::NotFound(name.to_string()));
+            return None
+        }
+
+        match (values.contains_key("aws_access_key_id"), values.contains_key("aws_secret_access_key")) {
+            (true, false) => errors.push(ProfileError::MissingSecret(name.to_string())),
+            (false, true) => errors.push(ProfileError::MissingKey(name.to_string())),
+            _ => {},
+        }
+
+        let mut source_profile = None;
+        if let Some(source) = values.get("source_profile").map(|v| v.value.clone()) {
+            // a profile sourcing itself is how static keys get used to assume a role
+            if source != name {
+                source_profile = self.resolve_chain(&source, seen, errors, EnvOverrides::Nothing).map(Box::new);
+                if source_profile.is_none() && self.file_values(&source).is_none() {
+                    errors.push(ProfileError::MissingSourceProfile(name.to_string(), source));
+                }
+            }
+        } else if values.contains_key("role_arn") && !values.contains_key("credential_source")
+            && !values.contains_key("web_identity_token_file") {
+            errors.push(ProfileError::MissingRoleSource(name.to_string()));
+        }
+
+        Some(Profile {
+            name: name.to_string(),
+            values: values,
+            source_profile: source_profile,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{config_section, config_value, expand_home, home_dir, section_value, ProfileError,
+                ProfileFile, Profiles, Source};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use rand::{thread_rng, Rng};
+    use ENV_LOCK as LOCK;
+
+    fn profiles() -> Profiles {
+        Profiles::from_paths("fixtures/profiles.ini", "fixtures/credentials.ini")
+    }
+
+    // A new, empty directory for one test's files.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("aws-rs-{}-{:08x}", name, thread_rng().gen::<u32>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_home_fallback() {
+        let _g = LOCK.write().unwrap();
+        let saved: Vec<_> = ["HOME", "USERPROFILE", "HOMEDRIVE", "HOMEPATH"].iter()
+            .map(|v| (*v, env::var(v).ok()))
+            .collect();
+        env::remove_var("HOME");
+        env::set_var("USERPROFILE", "/users/profile");
+        let profile = home_dir();
+        env::remove_var("USERPROFILE");
+        env::set_var("HOMEDRIVE", "/drive");
+        env::set_var("HOMEPATH", "/users/path");
+        let drive = home_dir();
+        let expanded = expand_home("~/.aws/credentials");
+        for (k, v) in saved {
+            match v {
+                Some(v) => env::set_var(k, v),
+                None => env::remove_var(k),
+            }
+        }
+
+        assert_eq!(profile.unwrap().to_str().unwrap(), "/users/profile");
+        assert_eq!(drive.unwrap().to_str().unwrap(), "/drive/users/path");
+        assert_eq!(expanded, "/drive/users/path/.aws/credentials");
+        assert_eq!(expand_home("/abs/~/path"), "/abs/~/path")
+    }
+
+    #[test]
+    fn test_config_section() {
+        assert_eq!(config_section("default"), "default");
+        assert_eq!(config_section("dev"), "profile dev")
+    }
+
+    #[test]
+    fn test_config_value() {
+        let path = "fixtures/config.ini";
+        assert_eq!(config_value(path, "default", "region").unwrap(), "eu-west-1");
+        assert_eq!(config_value(path, "web", "role_arn").unwrap(),
+                   "arn:aws:iam::123456789012:role/web");
+        assert!(config_value(path, "web", "missing").is_none());
+        assert!(config_value("fixtures/nonexistent", "web", "role_arn").is_none())
+    }
+
+    #[test]
+    fn test_section_value() {
+        assert_eq!(section_value("fixtures/config.ini", "sso-session corp", "sso_region").unwrap(),
+                   "us-east-1");
+        assert!(section_value("fixtures/config.ini", "sso-session other", "sso_region").is_none())
+    }
+
+    #[test]
+    fn test_parse() {
+        let file = ProfileFile::parse("inline", "# comment\n[default]\nregion = eu-west-1\n\n\
+                                                 [profile dev]\ns3 =\n  max_concurrent_requests = 20\n");
+        assert_eq!(file.sections.len(), 2);
+        assert_eq!(file.section("default").unwrap().line, 2);
+        assert_eq!(file.section("default").unwrap().get("region").unwrap().line, 3);
+        assert_eq!(file.get("profile dev", "s3").unwrap(), "max_concurrent_requests = 20")
+    }
+
+    #[test]
+    fn test_set_existing_key() {
+        let mut file = ProfileFile::parse("inline", "# keep me\n[default]\nregion = eu-west-1\n\
+                                                     output = json\n");
+        file.set("default", "region", "us-east-1");
+        assert_eq!(file.to_string(), "# keep me\n[default]\nregion = us-east-1\noutput = json\n")
+    }
+
+    #[test]
+    fn test_set_new_key() {
+        let mut file = ProfileFile::parse("inline", "[default]\nregion = eu-west-1\n\n\
+                                                     # dev account\n[profile dev]\nregion = us-east-1\n");
+        file.set("default", "output", "json");
+        assert_eq!(file.to_string(), "[default]\nregion = eu-west-1\noutput = json\n\n\
+                                      # dev account\n[profile dev]\nregion = us-east-1\n");
+        assert_eq!(file.get("default", "output").unwrap(), "json")
+    }
+
+    #[test]
+    fn test_set_nested_value() {
+        let mut file = ProfileFile::parse("inline", "[default]\ns3 =\n  addressing_style = path\n\
+                                                     region = eu-west-1\n");
+        file.set("default", "s3", "");
+        assert_eq!(file.to_string(), "[default]\ns3 = \nregion = eu-west-1\n")
+    }
+
+    #[test]
+    fn test_set_new_section() {
+        let mut file = ProfileFile::parse("inline", "[default]\nregion = eu-west-1\n\n");
+        file.set("profile dev", "region", "us-east-1");
+        assert_eq!(file.to_string(), "[default]\nregion = eu-west-1\n\n[profile dev]\nregion = us-east-1\n");
+
+        let mut empty = ProfileFile::parse("inline", "");
+        empty.set("default", "region", "eu-west-1");
+        assert_eq!(empty.to_string(), "[default]\nregion = eu-west-1\n")
+    }
+
+    #[test]
+    fn test_set_keeps_line_endings() {
+        let mut file = ProfileFile::parse("inline", "\u{feff}[default]\r\nregion = eu-west-1\r\n");
+        file.set("default", "output", "json");
+        assert_eq!(file.to_string(), "\u{feff}[default]\r\nregion = eu-west-1\r\noutput = json\r\n")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("save-permissions");
+        let path = dir.join("credentials").to_str().unwrap().to_string();
+        let mut file = ProfileFile::load_or_empty(&path).unwrap();
+        file.set("default", "aws_access_key_id", "AKID");
+        file.save().unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(ProfileFile::load(&path).unwrap().get("default", "aws_access_key_id").unwrap(),
+                   "AKID");
+        // the temporary file was renamed over the original, nothing is left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_through_symlink() {
+        use std::os::unix::fs::symlink;
+
+        let dir = scratch_dir("save-symlink");
+        fs::create_dir(dir.join("dotfiles")).unwrap();
+        File::create(dir.join("dotfiles/credentials")).unwrap()
+            .write_all(b"[default]\nregion = eu-west-1\n").unwrap();
+        symlink("dotfiles/credentials", dir.join("credentials")).unwrap();
+
+        let path = dir.join("credentials").to_str().unwrap().to_string();
+        let mut file = ProfileFile::load_or_empty(&path).unwrap();
+        file.set("default", "output", "json");
+        file.save().unwrap();
+
+        assert!(fs::symlink_metadata(&path).unwrap().file_type().is_symlink());
+        let target = ProfileFile::load(dir.join("dotfiles/credentials").to_str().unwrap()).unwrap();
+        assert_eq!(target.get("default", "output").unwrap(), "json");
+        fs::remove_dir_all(&dir).unwrap()
+    }
+
+    #[test]
+    fn test_load_or_empty() {
+        let dir = scratch_dir("load-or-empty");
+        let missing = dir.join("credentials").to_str().unwrap().to_string();
+        assert!(ProfileFile::load_or_empty(&missing).unwrap().sections.is_empty());
+        // a directory can't be read as a file, which mustn't look like an empty one
+        assert!(ProfileFile::load_or_empty(dir.to_str().unwrap()).is_err());
+        fs::remove_dir_all(&dir).unwrap()
+    }
+
+    #[test]
+    fn test_names() {
+        let names = profiles().names();
+        assert_eq!(names, vec!["default", "first", "aws", "child", "half", "orphan",
+                               "loop-a", "loop-b"])
+    }
+
+    #[test]
+    fn test_resolve_sources() {
+        let _g = LOCK.read().unwrap();
+        let profile = profiles().resolve("child").unwrap();
+        assert_eq!(profile.get("region").unwrap(), "ap-southeast-2");
+        assert_eq!(profile.source("region").unwrap(),
+                   &Source::File("fixtures/profiles.ini".to_string(), 5));
+        let source = profile.source_profile.unwrap();
+        assert_eq!(source.name, "first");
+        assert_eq!(source.get("aws_access_key_id").unwrap(), "zxspectrum");
+        assert_eq!(source.source("aws_access_key_id").unwrap(),
+                   &Source::File("fixtures/credentials.ini".to_string(), 6))
+    }
+
+    #[test]
+    fn test_resolve_env() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_DEFAULT_REGION", "us-west-2");
+        let profile = profiles().resolve("default");
+        env::remove_var("AWS_DEFAULT_REGION");
+        let profile = profile.unwrap();
+        assert_eq!(profile.get("region").unwrap(), "us-west-2");
+        assert_eq!(profile.source("region").unwrap(),
+                   &Source::Env("AWS_DEFAULT_REGION".to_string()))
+    }
+
+    #[test]
+    fn test_resolve_env_keys() {
+        let _g = LOCK.write().unwrap();
+        // half a pair is ignored rather than mixed with the file's key
+        env::set_var("AWS_SECRET_ACCESS_KEY", "from-env");
+        let partial = profiles().resolve("default");
+        env::set_var("AWS_ACCESS_KEY_ID", "AKIDENV");
+        let full = profiles().resolve("default");
+        let explicit = profiles().resolve_explicit("default");
+        env::remove_var("AWS_SECRET_ACCESS_KEY");
+        env::remove_var("AWS_ACCESS_KEY_ID");
+
+        let partial = partial.unwrap();
+        assert_eq!(partial.get("aws_access_key_id").unwrap(), "12345");
+        assert_eq!(partial.get("aws_secret_access_key").unwrap(), "abc132");
+        let full = full.unwrap();
+        assert_eq!(full.get("aws_access_key_id").unwrap(), "AKIDENV");
+        assert_eq!(full.source("aws_secret_access_key").unwrap(),
+                   &Source::Env("AWS_SECRET_ACCESS_KEY".to_string()));
+        assert_eq!(explicit.unwrap().get("aws_access_key_id").unwrap(), "12345")
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let _g = LOCK.read().unwrap();
+        let profiles = profiles();
+        assert_eq!(profiles.resolve("half").unwrap_err(),
+                   vec![ProfileError::MissingSecret("half".to_string())]);
+        assert_eq!(profiles.resolve("orphan").unwrap_err(),
+                   vec![ProfileError::MissingSourceProfile("orphan".to_string(),
+                                                           "nowhere".to_string())]);
+        assert_eq!(profiles.resolve("loop-a").unwrap_err(),
+                   vec![ProfileError::SourceProfileLoop(vec!["loop-a".to_string(),
+                                                             "loop-b".to_string(),
+                                                             "loop-a".to_string()])]);
+    }
+}
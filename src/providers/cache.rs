@@ -0,0 +1,291 @@
+use std::sync::{Condvar, Mutex};
+use rand::{thread_rng, Rng};
+use time::{get_time, Duration, Timespec};
+
+use credentials::Credentials;
+use super::{CredentialsError, ProvideCredentials};
+
+struct State {
+    credentials: Option<Credentials>,
+    refresh_at: Option<Timespec>,
+    refreshing: bool,
+    // bumped after every refresh attempt, so waiters can tell theirs has finished
+    generation: u64,
+    last_error: Option<String>,
+    // no refresh is attempted before this after one failed
+    retry_after: Option<Timespec>,
+}
+
+/// Holds on to the credentials of another provider until they are about to expire.
+///
+/// Credentials are refreshed `buffer` plus up to `jitter` before they expire, the
+/// jitter keeping a fleet of processes from all refreshing at the same moment.
+/// Only one caller refreshes at a time: while it does, the others keep getting the
+/// cached credentials if those are still valid, or wait for the refresh otherwise.
+/// A failed refresh is only reported once the cached credentials have expired,
+/// and the inner provider isn't asked again for `backoff` after one, so an
+/// outage isn't met with a call per caller. Credentials without an expiration
+/// are cached forever.
+pub struct CachedProvider<P> {
+    inner: P,
+    buffer: Duration,
+    jitter: Duration,
+    backoff: Duration,
+    state: Mutex<State>,
+    refreshed: Condvar,
+}
+
+impl<P: ProvideCredentials> CachedProvider<P> {
+    pub fn new(inner: P) -> CachedProvider<P> {
+        CachedProvider {
+            inner: inner,
+            buffer: Duration::minutes(5),
+            jitter: Duration::minutes(5),
+            backoff: Duration::seconds(10),
+            state: Mutex::new(State {
+                credentials: None,
+                refresh_at: None,
+                refreshing: false,
+                generation: 0,
+                last_error: None,
+                retry_after: None,
+            }),
+            refreshed: Condvar::new(),
+        }
+    }
+
+    pub fn buffer(mut self, buffer: Duration) -> CachedProvider<P> {
+        self.buffer = buffer;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: Duration) -> CachedProvider<P> {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Duration) -> CachedProvider<P> {
+        self.backoff = backoff;
+        self
+    }
+
+    fn refresh_at(&self, creds: &Credentials) -> Option<Timespec> {
+        creds.expiration.map(|expiration| {
+            let jitter = self.jitter.num_seconds();
+            let extra = if jitter > 0 { thread_rng().gen_range(0, jitter + 1) } else { 0 };
+            expiration - self.buffer - Duration::seconds(extra)
+        })
+    }
+}
+
+impl<P: ProvideCredentials> ProvideCredentials for CachedProvider<P> {
+    fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        let mut state = self.state.lock().unwrap();
+        let mut waited_for = None;
+        loop {
+            let now = get_time();
+            let backing_off = state.retry_after.map_or(false, |at| now < at);
+            if let Some(ref creds) = state.credentials {
+                let fresh = match state.refresh_at {
+                    Some(at) => now < at,
+                    None => true,
+                };
+                if fresh || ((state.refreshing || backing_off) && !creds.expired_at(now)) {
+                    return Ok(creds.clone())
+                }
+            }
+            if backing_off || waited_for.map_or(false, |gen| gen != state.generation) {
+                if let Some(ref err) = state.last_error {
+                    return Err(CredentialsError::new(err))
+                }
+            }
+            if !state.refreshing {
+                break
+            }
+            waited_for = Some(state.generation);
+            state = self.refreshed.wait(state).unwrap();
+        }
+
+        state.refreshing = true;
+        drop(state);
+        let mut guard = RefreshGuard { provider: self, done: false };
+        let result = self.inner.credentials();
+        guard.done = true;
+
+        let mut state = self.state.lock().unwrap();
+        state.refreshing = false;
+        state.generation += 1;
+        self.refreshed.notify_all();
+        match result {
+            Ok(creds) => {
+                state.refresh_at = self.refresh_at(&creds);
+                state.credentials = Some(creds.clone());
+                state.last_error = None;
+                state.retry_after = None;
+                Ok(creds)
+            },
+            Err(e) => {
+                state.last_error = Some(e.to_string());
+                state.retry_after = Some(get_time() + self.backoff);
+                match state.credentials {
+                    Some(ref creds) if !creds.is_expired() => {
+                        warn!("Refreshing credentials failed, using cached credentials: {}", e);
+                        Ok(creds.clone())
+                    },
+                    _ => Err(e),
+                }
+            }
+        }
+    }
+}
+
+// Hands the refresh back if the inner provider panics, so waiters don't hang.
+struct RefreshGuard<'a, P: 'a> {
+    provider: &'a CachedProvider<P>,
+    done: bool,
+}
+
+impl<'a, P> Drop for RefreshGuard<'a, P> {
+    fn drop(&mut self) {
+        if self.done {
+            return
+        }
+        let mut state = self.provider.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.refreshing = false;
+        state.generation += 1;
+        state.last_error = Some("refreshing credentials panicked".to_string());
+        state.retry_after = Some(get_time() + self.provider.backoff);
+        self.provider.refreshed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CachedProvider;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration as StdDuration;
+    use time::{get_time, Duration};
+    use credentials::{Credentials, Secret};
+    use providers::{CredentialsError, ProvideCredentials};
+    use ENV_LOCK as LOCK;
+
+    struct Counting {
+        calls: AtomicUsize,
+        fail: AtomicBool,
+        panic: AtomicBool,
+        lifetime: i64,
+        delay_ms: u64,
+    }
+
+    fn counting(lifetime: i64, delay_ms: u64) -> Counting {
+        Counting {
+            calls: AtomicUsize::new(0),
+            fail: AtomicBool::new(false),
+            panic: AtomicBool::new(false),
+            lifetime: lifetime,
+            delay_ms: delay_ms,
+        }
+    }
+
+    impl ProvideCredentials for Counting {
+        fn credentials(&self) -> Result<Credentials, CredentialsError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(StdDuration::from_millis(self.delay_ms));
+            if self.panic.load(Ordering::SeqCst) {
+                panic!("provider panicked")
+            }
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(CredentialsError::new("refresh failed"))
+            }
+            let mut creds = Credentials::new();
+            creds.key = Some("akid".to_string());
+            creds.secret = Some(Secret::new("secret"));
+            creds.expiration = Some(get_time() + Duration::seconds(self.lifetime));
+            Ok(creds)
+        }
+    }
+
+    #[test]
+    fn test_caches_until_refresh() {
+        let _g = LOCK.read().unwrap();
+        let provider = CachedProvider::new(counting(3600, 0)).jitter(Duration::zero());
+        provider.credentials().unwrap();
+        provider.credentials().unwrap();
+        assert_eq!(provider.inner.calls.load(Ordering::SeqCst), 1)
+    }
+
+    #[test]
+    fn test_refreshes_inside_buffer() {
+        let _g = LOCK.read().unwrap();
+        let provider = CachedProvider::new(counting(60, 0)).jitter(Duration::zero());
+        provider.credentials().unwrap();
+        provider.credentials().unwrap();
+        assert_eq!(provider.inner.calls.load(Ordering::SeqCst), 2)
+    }
+
+    #[test]
+    fn test_failed_refresh_serves_cached() {
+        let _g = LOCK.read().unwrap();
+        let provider = CachedProvider::new(counting(60, 0)).jitter(Duration::zero());
+        let first = provider.credentials().unwrap();
+        provider.inner.fail.store(true, Ordering::SeqCst);
+        let second = provider.credentials().unwrap();
+        assert_eq!(first.expiration, second.expiration);
+        assert_eq!(provider.inner.calls.load(Ordering::SeqCst), 2)
+    }
+
+    #[test]
+    fn test_failed_refresh_without_cache() {
+        let _g = LOCK.read().unwrap();
+        let provider = CachedProvider::new(counting(60, 0));
+        provider.inner.fail.store(true, Ordering::SeqCst);
+        assert!(provider.credentials().is_err())
+    }
+
+    #[test]
+    fn test_backs_off_after_failure() {
+        let _g = LOCK.read().unwrap();
+        let provider = CachedProvider::new(counting(3600, 0)).backoff(Duration::milliseconds(100));
+        provider.inner.fail.store(true, Ordering::SeqCst);
+        assert!(provider.credentials().is_err());
+        assert!(provider.credentials().is_err());
+        assert_eq!(provider.inner.calls.load(Ordering::SeqCst), 1);
+
+        thread::sleep(StdDuration::from_millis(150));
+        provider.inner.fail.store(false, Ordering::SeqCst);
+        assert!(provider.credentials().is_ok());
+        assert_eq!(provider.inner.calls.load(Ordering::SeqCst), 2)
+    }
+
+    #[test]
+    fn test_panicking_refresh_wakes_waiters() {
+        let _g = LOCK.read().unwrap();
+        let provider = Arc::new(CachedProvider::new(counting(3600, 100)));
+        provider.inner.panic.store(true, Ordering::SeqCst);
+        let refreshing = {
+            let provider = provider.clone();
+            thread::spawn(move || provider.credentials())
+        };
+        thread::sleep(StdDuration::from_millis(20));
+        // waits on the refresh above, which must hand it back when it panics
+        assert!(provider.credentials().is_err());
+        assert!(refreshing.join().is_err());
+        assert_eq!(provider.inner.calls.load(Ordering::SeqCst), 1)
+    }
+
+    #[test]
+    fn test_concurrent_callers_share_refresh() {
+        let _g = LOCK.read().unwrap();
+        let provider = Arc::new(CachedProvider::new(counting(3600, 100)));
+        let threads: Vec<_> = (0..8).map(|_| {
+            let provider = provider.clone();
+            thread::spawn(move || provider.credentials().unwrap())
+        }).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(provider.inner.calls.load(Ordering::SeqCst), 1)
+    }
+}
@@ -0,0 +1,281 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
+use hyper::header::Headers;
+use hyper::method::Method;
+use serialize::json::Json;
+
+use credentials::{Credentials, Secret};
+use transport::{HttpRequest, HyperClient, SharedClient, TransportOptions};
+use super::{parse_expiration, CredentialsError, ProvideCredentials};
+
+const ECS_HOST: &'static str = "http://169.254.170.2";
+// EKS Pod Identity agent addresses, the only plain http hosts allowed besides loopback
+const ALLOWED_HOSTS: [&'static str; 3] = ["169.254.170.2", "169.254.170.23", "fd00:ec2::23"];
+
+/// Transport settings for the container agents: two seconds to connect and
+/// to read, as the AWS CLI allows, and never through a proxy.
+pub fn container_options() -> TransportOptions {
+    TransportOptions {
+        connect_timeout: Some(Duration::from_secs(2)),
+        read_timeout: Some(Duration::from_secs(2)),
+        ca_bundle: None,
+        no_proxy: true,
+    }
+}
+
+/// Credentials served by the ECS agent or the EKS Pod Identity agent.
+///
+/// The endpoint is taken from `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI` or
+/// `AWS_CONTAINER_CREDENTIALS_FULL_URI`, and the authorization token from
+/// `AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE` or `AWS_CONTAINER_AUTHORIZATION_TOKEN`.
+/// Every call goes back to the agent, wrap the provider in a `CachedProvider`
+/// to hold on to the credentials until they near expiry.
+#[derive(Debug)]
+pub struct ContainerProvider {
+    uri: String,
+    token: Option<Secret>,
+    token_file: Option<String>,
+    client: SharedClient,
+}
+
+impl ContainerProvider {
+    pub fn new(uri: &str) -> ContainerProvider {
+        ContainerProvider {
+            uri: uri.to_string(),
+            token: None,
+            token_file: None,
+            client: Arc::new(HyperClient::new(container_options())),
+        }
+    }
+
+    pub fn token(mut self, token: &str) -> ContainerProvider {
+        self.token = Some(Secret::new(token));
+        self
+    }
+
+    pub fn token_file(mut self, path: &str) -> ContainerProvider {
+        self.token_file = Some(path.to_string());
+        self
+    }
+
+    /// Talk to the agent through `client` instead of a direct connection.
+    pub fn http_client(mut self, client: SharedClient) -> ContainerProvider {
+        self.client = client;
+        self
+    }
+
+    /// Build a provider from the container environment, if there is one.
+    pub fn from_env() -> Result<Option<ContainerProvider>, CredentialsError> {
+        let uri = if let Ok(relative) = env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI") {
+            format!("{}{}", ECS_HOST, relative)
+        } else if let Ok(full) = env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI") {
+            if !allowed_full_uri(&full) {
+                return Err(CredentialsError::new(
+                    &format!("{} is not an allowed container credentials endpoint", full)));
+            }
+            full
+        } else {
+            return Ok(None)
+        };
+
+        let mut provider = ContainerProvider::new(&uri);
+        if let Ok(path) = env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE") {
+            provider = provider.token_file(&path);
+        } else if let Ok(token) = env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN") {
+            provider = provider.token(&token);
+        }
+        Ok(Some(provider))
+    }
+
+    // The token file is re-read on every fetch since the agent rotates it.
+    fn authorization(&self) -> Result<Option<Secret>, CredentialsError> {
+        if let Some(ref path) = self.token_file {
+            let mut token = String::new();
+            try!(try!(File::open(path)).read_to_string(&mut token));
+            let token = Secret::new(token);
+            return Ok(Some(Secret::new(token.expose().trim())))
+        }
+        Ok(self.token.clone())
+    }
+
+    fn fetch(&self) -> Result<Credentials, CredentialsError> {
+        let mut headers = Headers::new();
+        if let Some(token) = try!(self.authorization()) {
+            headers.set_raw("Authorization", vec![token.expose().as_bytes().to_vec()]);
+        }
+
+        debug!("Fetching container credentials from {}", self.uri);
+        let request = HttpRequest {
+            method: Method::Get,
+            url: self.uri.clone(),
+            headers: headers,
+            body: Vec::new(),
+        };
+        let res = try!(self.client.send(&request));
+        let body = res.body_str();
+        if res.status != 200 {
+            return Err(CredentialsError::new(
+                &format!("container endpoint returned {}: {}", res.status, body)));
+        }
+        parse_response(&body)
+    }
+}
+
+impl ProvideCredentials for ContainerProvider {
+    fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        self.fetch()
+    }
+}
+
+fn parse_response(body: &str) -> Result<Credentials, CredentialsError> {
+    let json = try!(Json::from_str(body));
+    let field = |name: &str| {
+        match json.find(name).and_then(|v| v.as_string()) {
+            Some(v) => Ok(v.to_string()),
+            None => Err(CredentialsError::new(&format!("response is missing {}", name))),
+        }
+    };
+
+    let mut creds = Credentials::new();
+    creds.key = Some(try!(field("AccessKeyId")));
+    creds.secret = Some(Secret::new(try!(field("SecretAccessKey"))));
+    creds.token = Some(Secret::new(try!(field("Token"))));
+    creds.expiration = Some(try!(parse_expiration(&try!(field("Expiration")))));
+    Ok(creds)
+}
+
+// Full URIs must either be https or point at the local machine or a known agent,
+// otherwise we'd be handing the authorization token to anyone.
+fn allowed_full_uri(uri: &str) -> bool {
+    if uri.starts_with("https://") {
+        return true
+    }
+    if !uri.starts_with("http://") {
+        return false
+    }
+    let rest = &uri["http://".len()..];
+    let authority = rest.split('/').next().unwrap_or("");
+    let host = if authority.starts_with("[") {
+        match authority.find(']') {
+            Some(i) => &authority[1..i],
+            None => return false,
+        }
+    } else {
+        authority.split(':').next().unwrap_or("")
+    };
+    // besides localhost only addresses count, 127.example.com could resolve anywhere
+    if host == "localhost" {
+        return true
+    }
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        return ip.is_loopback() || ALLOWED_HOSTS.iter().any(|h| h.parse() == Ok(ip))
+    }
+    if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        return ip.is_loopback() || ALLOWED_HOSTS.iter().any(|h| h.parse() == Ok(ip))
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{allowed_full_uri, parse_response, ContainerProvider};
+    use std::env;
+    use std::sync::Arc;
+    use hyper::header::Headers;
+    use error::AwsError;
+    use transport::{HttpClient, HttpRequest, HttpResponse};
+    use ENV_LOCK as LOCK;
+
+    // An agent that only answers requests carrying its token.
+    struct Agent;
+
+    impl HttpClient for Agent {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError> {
+            let authorized = request.headers.get_raw("Authorization") == Some(&[b"sekrit".to_vec()][..]);
+            let (status, body) = if authorized {
+                (200, r#"{"AccessKeyId": "ASIAAGENT", "SecretAccessKey": "secret",
+                          "Token": "token", "Expiration": "2011-09-09T23:36:00Z"}"#)
+            } else {
+                (401, "")
+            };
+            Ok(HttpResponse { status: status, headers: Headers::new(), body: body.as_bytes().to_vec() })
+        }
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let _g = LOCK.read().unwrap();
+        let body = r#"{"AccessKeyId": "ASIAEXAMPLE", "SecretAccessKey": "secret",
+                       "Token": "token", "Expiration": "2011-09-09T23:36:00Z"}"#;
+        let creds = parse_response(body).unwrap();
+        assert_eq!(creds.key.unwrap(), "ASIAEXAMPLE");
+        assert_eq!(creds.secret.unwrap().expose(), "secret");
+        assert_eq!(creds.token.unwrap().expose(), "token");
+        assert_eq!(creds.expiration.unwrap().sec, 1315611360)
+    }
+
+    #[test]
+    fn test_parse_response_missing_field() {
+        let _g = LOCK.read().unwrap();
+        let body = r#"{"AccessKeyId": "ASIAEXAMPLE", "Expiration": "2011-09-09T23:36:00Z"}"#;
+        assert!(parse_response(body).is_err())
+    }
+
+    #[test]
+    fn test_allowed_full_uri() {
+        assert!(allowed_full_uri("https://example.com/creds"));
+        assert!(allowed_full_uri("http://127.0.0.1:8080/creds"));
+        assert!(allowed_full_uri("http://localhost/creds"));
+        assert!(allowed_full_uri("http://169.254.170.23/v1/credentials"));
+        assert!(allowed_full_uri("http://[fd00:ec2::23]/v1/credentials"));
+        assert!(allowed_full_uri("http://[::1]:8080/creds"));
+        assert!(!allowed_full_uri("http://example.com/creds"));
+        assert!(!allowed_full_uri("http://127.attacker.com/creds"));
+        assert!(!allowed_full_uri("http://127.0.0.1.attacker.com/creds"));
+        assert!(!allowed_full_uri("http://169.254.170.3/creds"));
+        assert!(!allowed_full_uri("ftp://127.0.0.1/creds"))
+    }
+
+    #[test]
+    fn test_from_env_relative() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI", "/v2/credentials/abc");
+        env::set_var("AWS_CONTAINER_AUTHORIZATION_TOKEN", "sekrit");
+        let provider = ContainerProvider::from_env();
+        env::remove_var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI");
+        env::remove_var("AWS_CONTAINER_AUTHORIZATION_TOKEN");
+        let provider = provider.unwrap().unwrap();
+        assert_eq!(provider.uri, "http://169.254.170.2/v2/credentials/abc");
+        assert_eq!(provider.token.unwrap().expose(), "sekrit")
+    }
+
+    #[test]
+    fn test_from_env_rejects_remote_full_uri() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONTAINER_CREDENTIALS_FULL_URI", "http://example.com/creds");
+        let provider = ContainerProvider::from_env();
+        env::remove_var("AWS_CONTAINER_CREDENTIALS_FULL_URI");
+        assert!(provider.is_err())
+    }
+
+    #[test]
+    fn test_token_file() {
+        let provider = ContainerProvider::new("http://127.0.0.1/")
+            .token("ignored")
+            .token_file("fixtures/container_token");
+        assert_eq!(provider.authorization().unwrap().unwrap().expose(), "container-token")
+    }
+
+    #[test]
+    fn test_fetch_through_client() {
+        let provider = ContainerProvider::new("http://169.254.170.2/v2/credentials/abc")
+            .http_client(Arc::new(Agent));
+        assert!(provider.fetch().is_err());
+        let provider = provider.token("sekrit");
+        assert_eq!(provider.fetch().unwrap().key.unwrap(), "ASIAAGENT")
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use openssl::crypto::hash::hash;
+use openssl::crypto::hash::Type::SHA1;
+use serialize::hex::ToHex;
+use serialize::json::{Json, ToJson};
+use time::{get_time, Duration};
+
+use credentials::{Credentials, Secret};
+use profile::{aws_path, profile_value};
+use super::{format_expiration, parse_expiration, sts, write_private, CredentialsError,
+            ProvideCredentials};
+use super::sts::StsOptions;
+
+/// Asks the user for the current code of the MFA device with the given serial.
+pub type MfaPrompt = Box<Fn(&str) -> Result<String, CredentialsError> + Send + Sync>;
+
+/// Session credentials for profiles protected by `mfa_serial`.
+///
+/// With a `role_arn` the code goes to `AssumeRole`, otherwise to `GetSessionToken`.
+/// The session is cached in `~/.aws/cli/cache` like the AWS CLI does, so the
+/// prompt only comes up again once it has expired.
+pub struct MfaProvider {
+    source: Credentials,
+    serial: String,
+    role_arn: Option<String>,
+    session_name: Option<String>,
+    duration: Option<u32>,
+    region: Option<String>,
+    sts: StsOptions,
+    cache_dir: Option<PathBuf>,
+    prompt: MfaPrompt,
+}
+
+impl MfaProvider {
+    pub fn new(source: Credentials, serial: &str, prompt: MfaPrompt) -> MfaProvider {
+        MfaProvider {
+            source: source,
+            serial: serial.to_string(),
+            role_arn: None,
+            session_name: None,
+            duration: None,
+            region: None,
+            sts: StsOptions::default(),
+            cache_dir: Some(default_cache_dir()),
+            prompt: prompt,
+        }
+    }
+
+    pub fn role_arn(mut self, arn: &str) -> MfaProvider {
+        self.role_arn = Some(arn.to_string());
+        self
+    }
+
+    pub fn session_name(mut self, name: &str) -> MfaProvider {
+        self.session_name = Some(name.to_string());
+        self
+    }
+
+    pub fn duration_seconds(mut self, seconds: u32) -> MfaProvider {
+        self.duration = Some(seconds);
+        self
+    }
+
+    pub fn region(mut self, region: &str) -> MfaProvider {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    /// Reach STS as `options` says, such as through its FIPS endpoint.
+    pub fn sts_options(mut self, options: StsOptions) -> MfaProvider {
+        self.sts = options;
+        self
+    }
+
+    pub fn cache_dir(mut self, dir: &str) -> MfaProvider {
+        self.cache_dir = Some(PathBuf::from(dir));
+        self
+    }
+
+    /// Don't read or write cached sessions.
+    pub fn no_cache(mut self) -> MfaProvider {
+        self.cache_dir = None;
+        self
+    }
+
+    /// Build a provider for a profile with an `mfa_serial` key.
+    ///
+    /// A profile with `role_arn` and `source_profile` assumes the role with the
+    /// source profile's keys, otherwise the profile's own keys get a session token.
+    pub fn from_profile(profile: &str, prompt: MfaPrompt) -> Option<MfaProvider> {
+        let serial = match profile_value(profile, "mfa_serial") {
+            Some(serial) => serial,
+            None => return None,
+        };
+        let role_arn = profile_value(profile, "role_arn");
+        let source = match (&role_arn, profile_value(profile, "source_profile")) {
+            (&Some(_), Some(source)) => source,
+            _ => profile.to_string(),
+        };
+
+        let mut provider = MfaProvider::new(Credentials::new().profile(&source).load(),
+                                            &serial, prompt);
+        if let Some(arn) = role_arn {
+            provider = provider.role_arn(&arn);
+        }
+        if let Some(name) = profile_value(profile, "role_session_name") {
+            provider = provider.session_name(&name);
+        }
+        if let Some(seconds) = profile_value(profile, "duration_seconds").and_then(|d| d.parse().ok()) {
+            provider = provider.duration_seconds(seconds);
+        }
+        if let Some(region) = profile_value(profile, "region") {
+            provider = provider.region(&region);
+        }
+        Some(provider)
+    }
+
+    // Same scheme as the CLI: a hash of the compact, key-sorted JSON of the call's arguments.
+    fn cache_key(&self) -> String {
+        let mut args = BTreeMap::new();
+        match self.role_arn {
+            Some(ref arn) => {
+                args.insert("RoleArn".to_string(), arn.to_json());
+                if let Some(ref name) = self.session_name {
+                    args.insert("RoleSessionName".to_string(), name.to_json());
+                }
+            },
+            None => {
+                let key = self.source.key.clone().unwrap_or(String::new());
+                args.insert("AccessKeyId".to_string(), key.to_json());
+            }
+        }
+        args.insert("SerialNumber".to_string(), self.serial.to_json());
+        if let Some(duration) = self.duration {
+            args.insert("DurationSeconds".to_string(), duration.to_json());
+        }
+        hash(SHA1, Json::Object(args).to_string().as_bytes()).to_hex()
+    }
+
+    fn cache_path(&self) -> Option<PathBuf> {
+        self.cache_dir.as_ref().map(|dir| {
+            let mut p = dir.clone();
+            p.push(format!("{}.json", self.cache_key()));
+            p
+        })
+    }
+
+    fn cached(&self) -> Option<Credentials> {
+        let path = match self.cache_path() {
+            Some(path) => path,
+            None => return None,
+        };
+        let mut body = String::new();
+        if File::open(&path).and_then(|mut f| f.read_to_string(&mut body)).is_err() {
+            return None
+        }
+        let json = match Json::from_str(&body) {
+            Ok(json) => json,
+            Err(_) => return None,
+        };
+        let field = |name: &str| {
+            json.find_path(&["Credentials", name]).and_then(|v| v.as_string()).map(|v| v.to_string())
+        };
+
+        let mut creds = Credentials::new();
+        creds.key = field("AccessKeyId");
+        creds.secret = field("SecretAccessKey").map(Secret::new);
+        creds.token = field("SessionToken").map(Secret::new);
+        creds.expiration = field("Expiration").and_then(|e| parse_expiration(&e).ok());
+        if creds.key.is_none() || creds.secret.is_none() || creds.expiration.is_none() {
+            return None
+        }
+        Some(creds)
+    }
+
+    fn store(&self, creds: &Credentials) -> Result<(), CredentialsError> {
+        let path = match self.cache_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut inner = BTreeMap::new();
+        inner.insert("AccessKeyId".to_string(), creds.key.to_json());
+        inner.insert("SecretAccessKey".to_string(), exposed(&creds.secret).to_json());
+        inner.insert("SessionToken".to_string(), exposed(&creds.token).to_json());
+        inner.insert("Expiration".to_string(),
+                     creds.expiration.map(format_expiration).to_json());
+        let mut outer = BTreeMap::new();
+        outer.insert("Credentials".to_string(), Json::Object(inner));
+        write_private(&path, &Json::Object(outer).to_string())
+    }
+}
+
+impl ProvideCredentials for MfaProvider {
+    fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        if let Some(creds) = self.cached() {
+            if !creds.expired_at(get_time() + Duration::minutes(5)) {
+                return Ok(creds)
+            }
+        }
+
+        let code = try!((self.prompt)(&self.serial));
+        let code = code.trim();
+        let region = self.region.as_ref().map(|r| r.as_ref());
+        let creds = match self.role_arn {
+            Some(ref arn) => {
+                let name = self.session_name.clone().unwrap_or(sts::default_session_name());
+                try!(sts::assume_role(&self.source, &self.sts, region, arn, &name,
+                                      Some((&self.serial[..], code)), self.duration))
+            },
+            None => try!(sts::get_session_token(&self.source, &self.sts, region, &self.serial,
+                                                code, self.duration)),
+        };
+        if let Err(e) = self.store(&creds) {
+            warn!("Could not cache MFA session: {}", e);
+        }
+        Ok(creds)
+    }
+}
+
+fn exposed(secret: &Option<Secret>) -> Option<String> {
+    secret.as_ref().map(|s| s.expose().to_string())
+}
+
+fn default_cache_dir() -> PathBuf {
+    let mut p = PathBuf::from(aws_path("cli"));
+    p.push("cache");
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MfaProvider;
+    use std::env;
+    use credentials::Credentials;
+    use providers::{CredentialsError, ProvideCredentials};
+    use ENV_LOCK as LOCK;
+
+    fn no_prompt() -> super::MfaPrompt {
+        Box::new(|serial: &str| -> Result<String, CredentialsError> {
+            panic!("prompted for {}", serial)
+        })
+    }
+
+    #[test]
+    fn test_from_profile_role() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        let provider = MfaProvider::from_profile("mfa-role", no_prompt());
+        let missing = MfaProvider::from_profile("web", no_prompt());
+        env::remove_var("AWS_CONFIG_FILE");
+
+        let provider = provider.unwrap();
+        assert_eq!(provider.serial, "arn:aws:iam::123456789012:mfa/user");
+        assert_eq!(provider.role_arn.unwrap(), "arn:aws:iam::123456789012:role/admin");
+        assert_eq!(provider.duration.unwrap(), 3600);
+        assert!(missing.is_none())
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_role() {
+        let _g = LOCK.read().unwrap();
+        let creds = Credentials::new().path("fixtures/credentials.ini").load();
+        let session = MfaProvider::new(creds.clone(), "serial", no_prompt());
+        let role = MfaProvider::new(creds, "serial", no_prompt()).role_arn("arn:role");
+        assert!(session.cache_key() != role.cache_key());
+        assert_eq!(session.cache_key().len(), 40)
+    }
+
+    #[test]
+    fn test_cached_session_skips_prompt() {
+        let _g = LOCK.read().unwrap();
+        let creds = Credentials::new().path("fixtures/credentials.ini").load();
+        let provider = MfaProvider::new(creds, "arn:aws:iam::123456789012:mfa/user", no_prompt())
+            .role_arn("arn:aws:iam::123456789012:role/admin")
+            .cache_dir("fixtures/cli/cache");
+        let session = provider.credentials().unwrap();
+        assert_eq!(session.key.unwrap(), "ASIACACHED");
+        assert_eq!(session.token.unwrap().expose(), "cached-session-token")
+    }
+
+    #[test]
+    fn test_prompt_without_cache() {
+        let _g = LOCK.read().unwrap();
+        let creds = Credentials::new().path("fixtures/credentials.ini").load();
+        let provider = MfaProvider::new(creds, "serial", Box::new(|_: &str| {
+            Err(CredentialsError::new("no code given"))
+        })).no_cache();
+        assert!(provider.credentials().is_err())
+    }
+}
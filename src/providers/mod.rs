@@ -0,0 +1,188 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use hyper;
+use serialize::json;
+use time::{at_utc, strptime, Timespec};
+
+use config::{Config, ConfigError};
+use credentials::Credentials;
+use error::AwsError;
+use non_empty_var;
+use profile;
+use self::cache::CachedProvider;
+use self::container::ContainerProvider;
+use self::sso::SsoProvider;
+use self::sts::StsOptions;
+use self::web_identity::WebIdentityProvider;
+
+pub mod cache;
+pub mod container;
+pub mod mfa;
+pub mod sso;
+pub mod sts;
+pub mod web_identity;
+
+/// Anything that can hand out a set of credentials for signing.
+///
+/// Providers that deal in temporary credentials fetch a new set on every call,
+/// wrap them in a `CachedProvider` to reuse credentials until they near expiry.
+pub trait ProvideCredentials {
+    fn credentials(&self) -> Result<Credentials, CredentialsError>;
+}
+
+impl ProvideCredentials for Credentials {
+    fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        match (&self.key, &self.secret) {
+            (&Some(_), &Some(_)) if !self.is_expired() => Ok(self.clone()),
+            (&Some(_), &Some(_)) => Err(CredentialsError::new("credentials have expired")),
+            _ => Err(CredentialsError::new("no access key and secret configured")),
+        }
+    }
+}
+
+/// A provider that can be shared between threads and clients.
+pub type SharedProvider = Arc<ProvideCredentials + Send + Sync>;
+
+/// The provider the AWS CLI would pick, given the environment and the shared files.
+///
+/// Keys in the environment come first unless a profile is given explicitly,
+/// then the profile's web identity or SSO settings, `AWS_WEB_IDENTITY_TOKEN_FILE`,
+/// keys in the profile and finally the container agent. Anything handing out
+/// temporary credentials is wrapped in a `CachedProvider`. Prompting for MFA
+/// codes needs a `MfaProvider` set up by the caller. The providers call STS,
+/// SSO and the container agent with the settings and HTTP client of `config`.
+pub fn default_provider(profile: Option<&str>, config: &Config) -> SharedProvider {
+    let sts = StsOptions::from_config(config);
+    let name = profile.map(|p| p.to_string()).unwrap_or_else(profile::default_profile);
+    let creds = match profile {
+        Some(profile) => Credentials::new().profile(profile).load(),
+        None => Credentials::new().load(),
+    };
+    if profile.is_none() && has_env_keys() {
+        return Arc::new(creds)
+    }
+    if let Some(provider) = WebIdentityProvider::from_profile(&name) {
+        return Arc::new(CachedProvider::new(provider.sts_options(sts.clone())))
+    }
+    if let Some(provider) = SsoProvider::from_profile(&name) {
+        return Arc::new(CachedProvider::new(provider.http_client(config.http_client())))
+    }
+    if let Some(provider) = WebIdentityProvider::from_env() {
+        return Arc::new(CachedProvider::new(provider.sts_options(sts.clone())))
+    }
+    if creds.key.is_some() {
+        return Arc::new(creds)
+    }
+    match ContainerProvider::from_env() {
+        Ok(Some(provider)) => {
+            return Arc::new(CachedProvider::new(provider))
+        },
+        Ok(None) => {},
+        Err(e) => warn!("Ignoring container credentials: {}", e),
+    }
+    // errors with "no access key" when asked for credentials
+    Arc::new(creds)
+}
+
+fn has_env_keys() -> bool {
+    ["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY"].iter().all(|name| non_empty_var(name).is_some())
+}
+
+/// Parse the ISO 8601 expiry stamps handed out by STS and the container agents.
+pub fn parse_expiration(val: &str) -> Result<Timespec, CredentialsError> {
+    match strptime(val, "%Y-%m-%dT%H:%M:%SZ") {
+        Ok(tm) => Ok(tm.to_timespec()),
+        Err(_) => Err(CredentialsError::new(&format!("invalid expiration {}", val))),
+    }
+}
+
+pub fn format_expiration(ts: Timespec) -> String {
+    at_utc(ts).strftime("%Y-%m-%dT%H:%M:%SZ").unwrap().to_string()
+}
+
+/// Write a cache file only the current user can read, creating its directory.
+pub fn write_private(path: &Path, body: &str) -> Result<(), CredentialsError> {
+    Ok(try!(profile::write_private(path, body)))
+}
+
+#[derive(Debug)]
+pub struct CredentialsError {
+    message: String,
+}
+
+impl CredentialsError {
+    pub fn new(message: &str) -> CredentialsError {
+        CredentialsError { message: message.to_string() }
+    }
+}
+
+impl fmt::Display for CredentialsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "could not load credentials: {}", self.message)
+    }
+}
+
+impl Error for CredentialsError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<io::Error> for CredentialsError {
+    fn from(err: io::Error) -> CredentialsError {
+        CredentialsError::new(&err.to_string())
+    }
+}
+
+impl From<hyper::Error> for CredentialsError {
+    fn from(err: hyper::Error) -> CredentialsError {
+        CredentialsError::new(&err.to_string())
+    }
+}
+
+impl From<AwsError> for CredentialsError {
+    fn from(err: AwsError) -> CredentialsError {
+        CredentialsError::new(&err.to_string())
+    }
+}
+
+impl From<ConfigError> for CredentialsError {
+    fn from(err: ConfigError) -> CredentialsError {
+        CredentialsError::new(&err.to_string())
+    }
+}
+
+impl From<json::ParserError> for CredentialsError {
+    fn from(err: json::ParserError) -> CredentialsError {
+        CredentialsError::new(&err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_provider, ProvideCredentials};
+    use config::Config;
+    use std::env;
+    use ENV_LOCK as LOCK;
+
+    #[test]
+    fn test_default_provider() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_SHARED_CREDENTIALS_FILE", "fixtures/credentials.ini");
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        env::set_var("AWS_ACCESS_KEY_ID", "envaccess");
+        env::set_var("AWS_SECRET_ACCESS_KEY", "envsecret");
+        let from_env = default_provider(None, &Config::new()).credentials();
+        let from_profile = default_provider(Some("first"), &Config::new()).credentials();
+        env::remove_var("AWS_ACCESS_KEY_ID");
+        env::remove_var("AWS_SECRET_ACCESS_KEY");
+        env::remove_var("AWS_SHARED_CREDENTIALS_FILE");
+        env::remove_var("AWS_CONFIG_FILE");
+
+        assert_eq!(from_env.unwrap().key.unwrap(), "envaccess");
+        assert_eq!(from_profile.unwrap().key.unwrap(), "zxspectrum")
+    }
+}
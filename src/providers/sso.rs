@@ -0,0 +1,462 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+use hyper::header::Headers;
+use hyper::method::Method;
+use openssl::crypto::hash::hash;
+use openssl::crypto::hash::Type::SHA1;
+use serialize::hex::ToHex;
+use serialize::json::{self, Json, ToJson};
+use time::{get_time, Duration, Timespec};
+
+use credentials::{Credentials, Secret};
+use encoding::query_string;
+use endpoints::{self, Variant};
+use profile::{aws_path, config_path, config_value, section_value};
+use transport::{HttpClient, HttpRequest, HttpResponse, HyperClient, SharedClient, TransportOptions};
+use super::{format_expiration, parse_expiration, write_private, CredentialsError,
+            ProvideCredentials};
+
+const CLIENT_NAME: &'static str = "aws-rs";
+const DEVICE_CODE_GRANT: &'static str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// The IAM Identity Center settings of a profile.
+///
+/// These come either from an `[sso-session name]` section referenced by the
+/// profile's `sso_session` key, or from the legacy `sso_start_url` and
+/// `sso_region` keys on the profile itself.
+#[derive(Clone,Debug)]
+pub struct SsoConfig {
+    pub start_url: String,
+    pub region: String,
+    pub session_name: Option<String>,
+    pub scopes: Vec<String>,
+    pub account_id: Option<String>,
+    pub role_name: Option<String>,
+}
+
+impl SsoConfig {
+    pub fn from_profile(profile: &str) -> Option<SsoConfig> {
+        let path = config_path();
+        let session_name = config_value(&path, profile, "sso_session");
+        let (start_url, region, scopes) = match session_name {
+            Some(ref name) => {
+                let section = format!("sso-session {}", name);
+                (section_value(&path, &section, "sso_start_url"),
+                 section_value(&path, &section, "sso_region"),
+                 section_value(&path, &section, "sso_registration_scopes"))
+            },
+            None => (config_value(&path, profile, "sso_start_url"),
+                     config_value(&path, profile, "sso_region"),
+                     None),
+        };
+        let (start_url, region) = match (start_url, region) {
+            (Some(url), Some(region)) => (url, region),
+            _ => return None,
+        };
+
+        Some(SsoConfig {
+            start_url: start_url,
+            region: region,
+            session_name: session_name,
+            scopes: scopes.map(|s| s.split(',').map(|v| v.trim().to_string()).collect())
+                .unwrap_or(Vec::new()),
+            account_id: config_value(&path, profile, "sso_account_id"),
+            role_name: config_value(&path, profile, "sso_role_name"),
+        })
+    }
+
+    /// Name of the token cache file, shared with the AWS CLI.
+    pub fn cache_key(&self) -> String {
+        let key = match self.session_name {
+            Some(ref name) => name,
+            None => &self.start_url,
+        };
+        hash(SHA1, key.as_bytes()).to_hex()
+    }
+}
+
+/// An access token as cached in `~/.aws/sso/cache`.
+#[allow(non_snake_case)]
+#[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
+pub struct SsoToken {
+    pub accessToken: Secret,
+    pub expiresAt: String,
+    pub refreshToken: Option<Secret>,
+    pub clientId: Option<String>,
+    pub clientSecret: Option<Secret>,
+    pub registrationExpiresAt: Option<String>,
+    pub region: Option<String>,
+    pub startUrl: Option<String>,
+}
+
+impl SsoToken {
+    pub fn load(path: &PathBuf) -> Result<SsoToken, CredentialsError> {
+        let mut body = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut body));
+        json::decode(&body).map_err(|e| CredentialsError::new(&e.to_string()))
+    }
+
+    pub fn save(&self, path: &PathBuf) -> Result<(), CredentialsError> {
+        let body = try!(json::encode(self).map_err(|e| CredentialsError::new(&e.to_string())));
+        write_private(path, &body)
+    }
+
+    fn expires_within(&self, window: Duration) -> bool {
+        match parse_expiration(&self.expiresAt.replace("UTC", "Z")) {
+            Ok(expiry) => get_time() + window >= expiry,
+            Err(_) => true,
+        }
+    }
+}
+
+/// Role credentials from IAM Identity Center, using the token left behind by
+/// `aws sso login`.
+///
+/// When the cached access token is about to expire and the cache holds a
+/// refresh token, a new access token is requested and written back to the cache.
+#[derive(Debug)]
+pub struct SsoProvider {
+    config: SsoConfig,
+    cache_dir: PathBuf,
+    client: SharedClient,
+}
+
+impl SsoProvider {
+    pub fn new(config: SsoConfig) -> SsoProvider {
+        SsoProvider {
+            config: config,
+            cache_dir: default_cache_dir(),
+            client: Arc::new(HyperClient::new(TransportOptions::default())),
+        }
+    }
+
+    pub fn cache_dir(mut self, dir: &str) -> SsoProvider {
+        self.cache_dir = PathBuf::from(dir);
+        self
+    }
+
+    /// Call IAM Identity Center through `client`, such as the one from
+    /// `Config::http_client`.
+    pub fn http_client(mut self, client: SharedClient) -> SsoProvider {
+        self.client = client;
+        self
+    }
+
+    pub fn from_profile(profile: &str) -> Option<SsoProvider> {
+        SsoConfig::from_profile(profile).map(SsoProvider::new)
+    }
+
+    fn token_path(&self) -> PathBuf {
+        token_path(&self.cache_dir, &self.config)
+    }
+
+    fn token(&self) -> Result<SsoToken, CredentialsError> {
+        let path = self.token_path();
+        let token = match SsoToken::load(&path) {
+            Ok(token) => token,
+            Err(_) => return Err(CredentialsError::new(
+                "no cached SSO token, run `aws sso login` first")),
+        };
+        if !token.expires_within(Duration::minutes(5)) {
+            return Ok(token)
+        }
+        match refresh_token(&*self.client, &self.config, &token) {
+            Ok(refreshed) => {
+                try!(refreshed.save(&path));
+                Ok(refreshed)
+            },
+            Err(e) => {
+                if token.expires_within(Duration::zero()) {
+                    Err(CredentialsError::new(
+                        &format!("SSO token has expired, run `aws sso login` again ({})", e)))
+                } else {
+                    Ok(token)
+                }
+            }
+        }
+    }
+
+    fn fetch(&self) -> Result<Credentials, CredentialsError> {
+        let (account_id, role_name) = match (&self.config.account_id, &self.config.role_name) {
+            (&Some(ref account), &Some(ref role)) => (account, role),
+            _ => return Err(CredentialsError::new(
+                "profile needs sso_account_id and sso_role_name")),
+        };
+        let token = try!(self.token());
+
+        let url = format!("{}/federation/credentials?{}",
+                          try!(endpoint_url("portal.sso", &self.config.region)),
+                          query_string(&[("account_id", account_id), ("role_name", role_name)]));
+        let mut headers = Headers::new();
+        headers.set_raw("x-amz-sso_bearer_token", vec![token.accessToken.expose().as_bytes().to_vec()]);
+
+        debug!("Fetching SSO role credentials for {}/{}", account_id, role_name);
+        let res = try!(send(&*self.client, Method::Get, &url, headers, Vec::new()));
+        let body = res.body_str();
+        if res.status != 200 {
+            return Err(CredentialsError::new(
+                &format!("GetRoleCredentials returned {}: {}", res.status, body)));
+        }
+        parse_role_credentials(&body)
+    }
+}
+
+impl ProvideCredentials for SsoProvider {
+    fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        self.fetch()
+    }
+}
+
+/// What the user has to do to finish a device code login.
+#[derive(Clone,Debug)]
+pub struct DeviceAuthorization {
+    pub verification_uri: String,
+    pub verification_uri_complete: Option<String>,
+    pub user_code: String,
+    device_code: String,
+    interval: u64,
+}
+
+/// Run the OIDC device code flow and cache the resulting token where
+/// `SsoProvider` will find it.
+///
+/// `prompt` is called once the user code is known; it should show the user the
+/// verification URI and code. This then polls until the login is approved.
+pub fn device_login<F>(client: &HttpClient, config: &SsoConfig, prompt: F)
+                       -> Result<SsoToken, CredentialsError>
+    where F: Fn(&DeviceAuthorization) {
+    let oidc = try!(endpoint_url("oidc", &config.region));
+
+    let mut register = BTreeMap::new();
+    register.insert("clientName".to_string(), CLIENT_NAME.to_json());
+    register.insert("clientType".to_string(), "public".to_json());
+    if !config.scopes.is_empty() {
+        register.insert("scopes".to_string(), config.scopes.to_json());
+    }
+    let registration = try!(post_json(client, &format!("{}/client/register", oidc),
+                                      &Json::Object(register)));
+    let client_id = try!(json_string(&registration, "clientId"));
+    let client_secret = try!(json_string(&registration, "clientSecret"));
+
+    let mut start = BTreeMap::new();
+    start.insert("clientId".to_string(), client_id.to_json());
+    start.insert("clientSecret".to_string(), client_secret.to_json());
+    start.insert("startUrl".to_string(), config.start_url.to_json());
+    let device = try!(post_json(client, &format!("{}/device_authorization", oidc),
+                                &Json::Object(start)));
+    let mut auth = DeviceAuthorization {
+        verification_uri: try!(json_string(&device, "verificationUri")),
+        verification_uri_complete: json_string(&device, "verificationUriComplete").ok(),
+        user_code: try!(json_string(&device, "userCode")),
+        device_code: try!(json_string(&device, "deviceCode")),
+        interval: device.find("interval").and_then(|v| v.as_u64()).unwrap_or(5),
+    };
+    prompt(&auth);
+
+    let mut create = BTreeMap::new();
+    create.insert("clientId".to_string(), client_id.to_json());
+    create.insert("clientSecret".to_string(), client_secret.to_json());
+    create.insert("grantType".to_string(), DEVICE_CODE_GRANT.to_json());
+    create.insert("deviceCode".to_string(), auth.device_code.to_json());
+    let create = Json::Object(create);
+    let created;
+    loop {
+        thread::sleep(StdDuration::from_secs(auth.interval));
+        match post_json(client, &format!("{}/token", oidc), &create) {
+            Ok(token) => {
+                created = token;
+                break
+            },
+            Err(ref e) if e.to_string().contains("authorization_pending") => continue,
+            Err(ref e) if e.to_string().contains("slow_down") => auth.interval += 5,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let expires_in = created.find("expiresIn").and_then(|v| v.as_i64()).unwrap_or(0);
+    let registration_expires = registration.find("clientSecretExpiresAt").and_then(|v| v.as_i64());
+    let token = SsoToken {
+        accessToken: Secret::new(try!(json_string(&created, "accessToken"))),
+        expiresAt: format_expiration(get_time() + Duration::seconds(expires_in)),
+        refreshToken: json_string(&created, "refreshToken").ok().map(Secret::new),
+        clientId: Some(client_id),
+        clientSecret: Some(Secret::new(client_secret)),
+        registrationExpiresAt: registration_expires.map(|s| format_expiration(Timespec::new(s, 0))),
+        region: Some(config.region.clone()),
+        startUrl: Some(config.start_url.clone()),
+    };
+    try!(token.save(&token_path(&default_cache_dir(), config)));
+    Ok(token)
+}
+
+fn refresh_token(client: &HttpClient, config: &SsoConfig,
+                 token: &SsoToken) -> Result<SsoToken, CredentialsError> {
+    let (client_id, client_secret, refresh) =
+        match (&token.clientId, &token.clientSecret, &token.refreshToken) {
+            (&Some(ref id), &Some(ref secret), &Some(ref refresh)) => (id, secret, refresh),
+            _ => return Err(CredentialsError::new("cached SSO token cannot be refreshed")),
+        };
+
+    let mut body = BTreeMap::new();
+    body.insert("clientId".to_string(), client_id.to_json());
+    body.insert("clientSecret".to_string(), client_secret.expose().to_json());
+    body.insert("grantType".to_string(), "refresh_token".to_json());
+    body.insert("refreshToken".to_string(), refresh.expose().to_json());
+
+    debug!("Refreshing SSO token for {}", config.start_url);
+    let oidc = try!(endpoint_url("oidc", &config.region));
+    let created = try!(post_json(client, &format!("{}/token", oidc), &Json::Object(body)));
+    let expires_in = created.find("expiresIn").and_then(|v| v.as_i64()).unwrap_or(0);
+
+    let mut refreshed = token.clone();
+    refreshed.accessToken = Secret::new(try!(json_string(&created, "accessToken")));
+    refreshed.expiresAt = format_expiration(get_time() + Duration::seconds(expires_in));
+    if let Ok(refresh) = json_string(&created, "refreshToken") {
+        refreshed.refreshToken = Some(Secret::new(refresh));
+    }
+    Ok(refreshed)
+}
+
+fn default_cache_dir() -> PathBuf {
+    let mut p = PathBuf::from(aws_path("sso"));
+    p.push("cache");
+    p
+}
+
+fn token_path(dir: &PathBuf, config: &SsoConfig) -> PathBuf {
+    let mut p = dir.clone();
+    p.push(format!("{}.json", config.cache_key()));
+    p
+}
+
+// The portal and OIDC hosts of the region's partition, such as
+// `oidc.cn-north-1.amazonaws.com.cn`, without a trailing slash.
+fn endpoint_url(service: &str, region: &str) -> Result<String, CredentialsError> {
+    let endpoint = try!(endpoints::resolve(service, region, Variant::default()));
+    Ok(endpoint.url.trim_right_matches('/').to_string())
+}
+
+fn post_json(client: &HttpClient, url: &str, body: &Json) -> Result<Json, CredentialsError> {
+    let mut headers = Headers::new();
+    headers.set_raw("Content-Type", vec![b"application/json".to_vec()]);
+
+    let res = try!(send(client, Method::Post, url, headers, body.to_string().into_bytes()));
+    let output = res.body_str();
+    if res.status != 200 {
+        return Err(CredentialsError::new(&format!("{} returned {}: {}", url, res.status, output)));
+    }
+    Ok(try!(Json::from_str(&output)))
+}
+
+fn send(client: &HttpClient, method: Method, url: &str, headers: Headers,
+        body: Vec<u8>) -> Result<HttpResponse, CredentialsError> {
+    let request = HttpRequest {
+        method: method,
+        url: url.to_string(),
+        headers: headers,
+        body: body,
+    };
+    Ok(try!(client.send(&request)))
+}
+
+fn json_string(json: &Json, name: &str) -> Result<String, CredentialsError> {
+    match json.find(name).and_then(|v| v.as_string()) {
+        Some(v) => Ok(v.to_string()),
+        None => Err(CredentialsError::new(&format!("response is missing {}", name))),
+    }
+}
+
+fn parse_role_credentials(body: &str) -> Result<Credentials, CredentialsError> {
+    let json = try!(Json::from_str(body));
+    let role = match json.find("roleCredentials") {
+        Some(role) => role,
+        None => return Err(CredentialsError::new("response is missing roleCredentials")),
+    };
+
+    let mut creds = Credentials::new();
+    creds.key = Some(try!(json_string(role, "accessKeyId")));
+    creds.secret = Some(Secret::new(try!(json_string(role, "secretAccessKey"))));
+    creds.token = Some(Secret::new(try!(json_string(role, "sessionToken"))));
+    // GetRoleCredentials hands back milliseconds since the epoch
+    creds.expiration = match role.find("expiration").and_then(|v| v.as_i64()) {
+        Some(ms) => Some(Timespec::new(ms / 1000, 0)),
+        None => return Err(CredentialsError::new("response is missing expiration")),
+    };
+    Ok(creds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{endpoint_url, parse_role_credentials, SsoConfig, SsoProvider};
+    use std::env;
+    use time::Duration;
+    use ENV_LOCK as LOCK;
+
+    fn sso_config(profile: &str) -> SsoConfig {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        let config = SsoConfig::from_profile(profile);
+        env::remove_var("AWS_CONFIG_FILE");
+        config.unwrap()
+    }
+
+    #[test]
+    fn test_session_config() {
+        let config = sso_config("sso");
+        assert_eq!(config.start_url, "https://corp.awsapps.com/start");
+        assert_eq!(config.region, "us-east-1");
+        assert_eq!(config.scopes, vec!["sso:account:access".to_string()]);
+        assert_eq!(config.account_id.unwrap(), "111122223333");
+        assert_eq!(config.role_name.unwrap(), "ReadOnly")
+    }
+
+    #[test]
+    fn test_legacy_config() {
+        let config = sso_config("legacy-sso");
+        assert_eq!(config.start_url, "https://legacy.awsapps.com/start");
+        assert_eq!(config.region, "eu-west-1");
+        assert!(config.session_name.is_none())
+    }
+
+    #[test]
+    fn test_cache_key() {
+        // the session name is hashed when there is one, the start url otherwise
+        assert_eq!(sso_config("sso").cache_key(), "ee0bfd2552fbd840c02cc48b6e823320543c450f");
+        assert_eq!(sso_config("legacy-sso").cache_key(),
+                   "79e435d7a515078e81c9dffc35f38d5687ebd3a7")
+    }
+
+    #[test]
+    fn test_cached_token() {
+        let provider = SsoProvider::new(sso_config("sso")).cache_dir("fixtures/sso/cache");
+        let token = provider.token().unwrap();
+        assert_eq!(token.accessToken.expose(), "cached-access-token");
+        assert!(!format!("{:?}", token).contains("cached-access-token"));
+        assert!(!token.expires_within(Duration::minutes(5)))
+    }
+
+    #[test]
+    fn test_parse_role_credentials() {
+        let _g = LOCK.read().unwrap();
+        let body = r#"{"roleCredentials": {"accessKeyId": "ASIASSO", "secretAccessKey": "secret",
+                       "sessionToken": "token", "expiration": 1315611360000}}"#;
+        let creds = parse_role_credentials(body).unwrap();
+        assert_eq!(creds.key.unwrap(), "ASIASSO");
+        assert_eq!(creds.token.unwrap().expose(), "token");
+        assert_eq!(creds.expiration.unwrap().sec, 1315611360)
+    }
+
+    #[test]
+    fn test_endpoint_url() {
+        assert_eq!(endpoint_url("oidc", "eu-west-1").unwrap(), "https://oidc.eu-west-1.amazonaws.com");
+        assert_eq!(endpoint_url("portal.sso", "cn-north-1").unwrap(),
+                   "https://portal.sso.cn-north-1.amazonaws.com.cn");
+        assert_eq!(endpoint_url("oidc", "us-gov-west-1").unwrap(),
+                   "https://oidc.us-gov-west-1.amazonaws.com")
+    }
+}
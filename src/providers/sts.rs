@@ -0,0 +1,282 @@
+use std::sync::Arc;
+use time::get_time;
+
+use config::Config;
+use credentials::{Credentials, Secret};
+use endpoints::{self, Endpoint, Variant};
+use query::QueryRequest;
+use request::ApiClient;
+use transport::{HyperClient, SharedClient, TransportOptions};
+use xml;
+use super::{parse_expiration, CredentialsError, SharedProvider};
+
+const STS_VERSION: &'static str = "2011-06-15";
+
+/// How the providers reach STS, beyond the region they call it in.
+#[derive(Clone,Debug)]
+pub struct StsOptions {
+    pub variant: Variant,
+    /// URL to send the calls to instead of the regular endpoint.
+    pub endpoint: Option<String>,
+    pub client: SharedClient,
+}
+
+impl StsOptions {
+    /// The FIPS and dual-stack settings of `config`, its endpoint override for
+    /// STS, see `Config::endpoint_for`, and its HTTP client.
+    pub fn from_config(config: &Config) -> StsOptions {
+        StsOptions {
+            variant: config.endpoint_variant(),
+            endpoint: config.endpoint_for("sts"),
+            client: config.http_client(),
+        }
+    }
+}
+
+impl Default for StsOptions {
+    fn default() -> StsOptions {
+        StsOptions {
+            variant: Variant::default(),
+            endpoint: None,
+            client: Arc::new(HyperClient::new(TransportOptions::default())),
+        }
+    }
+}
+
+/// STS endpoint for a region, falling back to the global endpoint.
+///
+/// An overridden endpoint is still signed for the region, so the providers can
+/// be pointed at a local server.
+pub fn endpoint(options: &StsOptions, region: Option<&str>) -> Result<Endpoint, CredentialsError> {
+    let mut endpoint = try!(endpoints::resolve("sts", region.unwrap_or("aws-global"),
+                                               options.variant));
+    if let Some(ref url) = options.endpoint {
+        endpoint.url = format!("{}/", url.trim_right_matches('/'));
+    }
+    Ok(endpoint)
+}
+
+/// A session name for when the profile doesn't set one.
+pub fn default_session_name() -> String {
+    format!("aws-rs-session-{}", get_time().sec)
+}
+
+/// Exchange an OIDC token for role credentials.
+///
+/// This call is not signed, the token is the proof of identity. It goes in
+/// the POST body, so it never ends up in a URL.
+pub fn assume_role_with_web_identity(options: &StsOptions, region: Option<&str>, role_arn: &str,
+                                     session_name: &str, token: &str)
+                                     -> Result<Credentials, CredentialsError> {
+    let request = QueryRequest::new("AssumeRoleWithWebIdentity", STS_VERSION)
+        .param("RoleArn", role_arn)
+        .param("RoleSessionName", session_name)
+        .param("WebIdentityToken", token);
+
+    debug!("Calling AssumeRoleWithWebIdentity for {}", role_arn);
+    call(None, options, region, &request)
+}
+
+/// Trade long-term credentials and an MFA code for a session.
+pub fn get_session_token(creds: &Credentials, options: &StsOptions, region: Option<&str>,
+                         serial: &str, code: &str, duration: Option<u32>)
+                         -> Result<Credentials, CredentialsError> {
+    let mut request = QueryRequest::new("GetSessionToken", STS_VERSION)
+        .param("SerialNumber", serial)
+        .param("TokenCode", code);
+    if let Some(duration) = duration {
+        request = request.param("DurationSeconds", &duration.to_string());
+    }
+    call(Some(creds), options, region, &request)
+}
+
+/// Assume a role with `creds`, passing along an MFA serial and code if the role needs them.
+pub fn assume_role(creds: &Credentials, options: &StsOptions, region: Option<&str>,
+                   role_arn: &str, session_name: &str, mfa: Option<(&str, &str)>,
+                   duration: Option<u32>) -> Result<Credentials, CredentialsError> {
+    let mut request = QueryRequest::new("AssumeRole", STS_VERSION)
+        .param("RoleArn", role_arn)
+        .param("RoleSessionName", session_name);
+    if let Some((serial, code)) = mfa {
+        request = request.param("SerialNumber", serial).param("TokenCode", code);
+    }
+    if let Some(duration) = duration {
+        request = request.param("DurationSeconds", &duration.to_string());
+    }
+    call(Some(creds), options, region, &request)
+}
+
+// A Query call POSTed to STS, signed with `creds` if there are any. Error
+// responses are classified and retried like any other call's.
+fn call(creds: Option<&Credentials>, options: &StsOptions, region: Option<&str>,
+        request: &QueryRequest) -> Result<Credentials, CredentialsError> {
+    let endpoint = try!(endpoint(options, region));
+    let provider = creds.map(|creds| Arc::new(creds.clone()) as SharedProvider);
+    let client = ApiClient::for_endpoint(provider, &endpoint, options.client.clone());
+
+    debug!("Calling {} on {}", request.action, endpoint.host());
+    let res = try!(client.call(request));
+    parse_credentials(&res.body_str())
+}
+
+/// Pull the `<Credentials>` out of an STS response, or the error if there was one.
+pub fn parse_credentials(body: &str) -> Result<Credentials, CredentialsError> {
+    if let Some(code) = xml::find(body, "Code") {
+        let message = xml::find(body, "Message").unwrap_or(String::new());
+        return Err(CredentialsError::new(&format!("STS returned {}: {}", code, message)));
+    }
+    let field = |name: &str| {
+        match xml::find(body, name) {
+            Some(v) => Ok(v),
+            None => Err(CredentialsError::new(&format!("STS response is missing {}", name))),
+        }
+    };
+
+    let mut creds = Credentials::new();
+    creds.key = Some(try!(field("AccessKeyId")));
+    creds.secret = Some(Secret::new(try!(field("SecretAccessKey"))));
+    creds.token = Some(Secret::new(try!(field("SessionToken"))));
+    creds.expiration = Some(try!(parse_expiration(&try!(field("Expiration")))));
+    Ok(creds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assume_role_with_web_identity, endpoint, get_session_token, parse_credentials,
+                StsOptions};
+    use std::sync::{Arc, Mutex};
+    use hyper::header::Headers;
+    use hyper::method::Method;
+    use credentials::{Credentials, Secret};
+    use endpoints::Variant;
+    use error::AwsError;
+    use transport::{HttpClient, HttpRequest, HttpResponse};
+    use ENV_LOCK as LOCK;
+
+    const ASSUMED: &'static str = "<AssumeRoleWithWebIdentityResponse><AssumeRoleWithWebIdentityResult>\
+        <Credentials><AccessKeyId>ASIAWEB</AccessKeyId><SecretAccessKey>secret</SecretAccessKey>\
+        <SessionToken>token</SessionToken><Expiration>2014-10-24T23:00:23Z</Expiration></Credentials>\
+        </AssumeRoleWithWebIdentityResult></AssumeRoleWithWebIdentityResponse>";
+
+    struct Canned {
+        status: u16,
+        body: &'static str,
+        sent: Arc<Mutex<Vec<HttpRequest>>>,
+    }
+
+    impl HttpClient for Canned {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError> {
+            self.sent.lock().unwrap().push(request.clone());
+            Ok(HttpResponse {
+                status: self.status,
+                headers: Headers::new(),
+                body: self.body.as_bytes().to_vec(),
+            })
+        }
+    }
+
+    fn canned(status: u16, body: &'static str) -> (StsOptions, Arc<Mutex<Vec<HttpRequest>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let client = Canned { status: status, body: body, sent: sent.clone() };
+        (StsOptions { client: Arc::new(client), ..StsOptions::default() }, sent)
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let _g = LOCK.read().unwrap();
+        let options = StsOptions::default();
+        let fips = StsOptions { variant: Variant { fips: true, dualstack: false }, ..StsOptions::default() };
+        let local = StsOptions { endpoint: Some("http://localhost:4566".to_string()), ..StsOptions::default() };
+        let url = |options: &StsOptions, region| endpoint(options, region).map(|e| e.url);
+        assert_eq!(url(&options, Some("eu-west-1")).unwrap(), "https://sts.eu-west-1.amazonaws.com/");
+        assert_eq!(url(&options, Some("cn-north-1")).unwrap(),
+                   "https://sts.cn-north-1.amazonaws.com.cn/");
+        assert_eq!(url(&options, None).unwrap(), "https://sts.amazonaws.com/");
+        assert_eq!(url(&fips, Some("us-east-2")).unwrap(), "https://sts-fips.us-east-2.amazonaws.com/");
+        assert!(url(&fips, None).is_err());
+        assert_eq!(endpoint(&options, None).unwrap().signing_region, "us-east-1");
+        let local = endpoint(&local, Some("eu-west-1")).unwrap();
+        assert_eq!((&local.url[..], &local.signing_region[..]), ("http://localhost:4566/", "eu-west-1"))
+    }
+
+    #[test]
+    fn test_parse_credentials() {
+        let _g = LOCK.read().unwrap();
+        let body = r#"<AssumeRoleWithWebIdentityResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleWithWebIdentityResult>
+    <Credentials>
+      <SessionToken>AQoDYXdzEE0a8ANXXXXXXXXNO1ewxE5TijQyp+IEXAMPLE</SessionToken>
+      <SecretAccessKey>wJalrXUtnFEMI/K7MDENG/bPxRfiCYzEXAMPLEKEY</SecretAccessKey>
+      <Expiration>2014-10-24T23:00:23Z</Expiration>
+      <AccessKeyId>ASgeIAIOSFODNN7EXAMPLE</AccessKeyId>
+    </Credentials>
+  </AssumeRoleWithWebIdentityResult>
+</AssumeRoleWithWebIdentityResponse>"#;
+        let creds = parse_credentials(body).unwrap();
+        assert_eq!(creds.key.unwrap(), "ASgeIAIOSFODNN7EXAMPLE");
+        assert_eq!(creds.secret.unwrap().expose(), "wJalrXUtnFEMI/K7MDENG/bPxRfiCYzEXAMPLEKEY");
+        assert_eq!(creds.token.unwrap().expose(), "AQoDYXdzEE0a8ANXXXXXXXXNO1ewxE5TijQyp+IEXAMPLE");
+        assert_eq!(creds.expiration.unwrap().sec, 1414191623)
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let body = r#"<ErrorResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <Error>
+    <Type>Sender</Type>
+    <Code>InvalidIdentityToken</Code>
+    <Message>No OpenIDConnect provider found in your account</Message>
+  </Error>
+  <RequestId>a1b2c3</RequestId>
+</ErrorResponse>"#;
+        let err = parse_credentials(body).unwrap_err();
+        assert!(err.to_string().contains("InvalidIdentityToken"))
+    }
+
+    #[test]
+    fn test_web_identity_in_body() {
+        let _g = LOCK.read().unwrap();
+        let (options, sent) = canned(200, ASSUMED);
+        let creds = assume_role_with_web_identity(&options, Some("eu-west-1"), "arn:aws:iam::1:role/web",
+                                                  "session", "oidc-token").unwrap();
+        assert_eq!(creds.key.unwrap(), "ASIAWEB");
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].method, Method::Post);
+        assert_eq!(sent[0].url, "https://sts.eu-west-1.amazonaws.com/");
+        let body = String::from_utf8(sent[0].body.clone()).unwrap();
+        assert!(body.starts_with("Action=AssumeRoleWithWebIdentity&Version=2011-06-15&"));
+        assert!(body.contains("&WebIdentityToken=oidc-token"));
+        assert!(sent[0].headers.get_raw("Authorization").is_none())
+    }
+
+    #[test]
+    fn test_error_status() {
+        let _g = LOCK.read().unwrap();
+        let (options, sent) = canned(400, "<ErrorResponse><Error><Type>Sender</Type>\
+            <Code>InvalidIdentityToken</Code><Message>bad token</Message></Error></ErrorResponse>");
+        let err = assume_role_with_web_identity(&options, None, "arn:aws:iam::1:role/web", "session",
+                                                "oidc-token").unwrap_err();
+        assert!(err.to_string().contains("InvalidIdentityToken"));
+        // a client error isn't retried
+        assert_eq!(sent.lock().unwrap().len(), 1)
+    }
+
+    #[test]
+    fn test_signed_call_in_body() {
+        let _g = LOCK.read().unwrap();
+        let (options, sent) = canned(200, ASSUMED);
+        let mut creds = Credentials::new();
+        creds.key = Some("AKID".to_string());
+        creds.secret = Some(Secret::new("secret"));
+        get_session_token(&creds, &options, None, "arn:aws:iam::1:mfa/user", "123456", Some(900)).unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].method, Method::Post);
+        assert_eq!(sent[0].url, "https://sts.amazonaws.com/");
+        assert_eq!(String::from_utf8(sent[0].body.clone()).unwrap(),
+                   "Action=GetSessionToken&Version=2011-06-15&SerialNumber=arn%3Aaws%3Aiam%3A%3A1%3Amfa%2Fuser\
+                    &TokenCode=123456&DurationSeconds=900");
+        assert!(sent[0].headers.get_raw("Authorization").is_some())
+    }
+}
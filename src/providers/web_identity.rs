@@ -0,0 +1,150 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+use credentials::Credentials;
+use profile::{config_path, config_value};
+use region::env_region;
+use super::{sts, CredentialsError, ProvideCredentials};
+use super::sts::StsOptions;
+
+/// Role credentials obtained by trading an OIDC token with `AssumeRoleWithWebIdentity`.
+///
+/// This is what EKS service accounts (IRSA) use. The token file is read again on
+/// every refresh, since whatever mounts it rotates it well before the role session
+/// expires.
+#[derive(Debug)]
+pub struct WebIdentityProvider {
+    role_arn: String,
+    token_file: String,
+    session_name: String,
+    region: Option<String>,
+    sts: StsOptions,
+}
+
+impl WebIdentityProvider {
+    pub fn new(role_arn: &str, token_file: &str) -> WebIdentityProvider {
+        WebIdentityProvider {
+            role_arn: role_arn.to_string(),
+            token_file: token_file.to_string(),
+            session_name: sts::default_session_name(),
+            region: None,
+            sts: StsOptions::default(),
+        }
+    }
+
+    pub fn session_name(mut self, name: &str) -> WebIdentityProvider {
+        self.session_name = name.to_string();
+        self
+    }
+
+    pub fn region(mut self, region: &str) -> WebIdentityProvider {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    /// Reach STS as `options` says, such as through its FIPS endpoint.
+    pub fn sts_options(mut self, options: StsOptions) -> WebIdentityProvider {
+        self.sts = options;
+        self
+    }
+
+    /// Build a provider from `AWS_WEB_IDENTITY_TOKEN_FILE`, `AWS_ROLE_ARN` and
+    /// `AWS_ROLE_SESSION_NAME`, if the first two are set.
+    pub fn from_env() -> Option<WebIdentityProvider> {
+        let (token_file, role_arn) = match (env::var("AWS_WEB_IDENTITY_TOKEN_FILE"),
+                                            env::var("AWS_ROLE_ARN")) {
+            (Ok(file), Ok(arn)) => (file, arn),
+            _ => return None,
+        };
+        let mut provider = WebIdentityProvider::new(&role_arn, &token_file);
+        if let Ok(name) = env::var("AWS_ROLE_SESSION_NAME") {
+            provider = provider.session_name(&name);
+        }
+        if let Some(region) = env_region() {
+            provider = provider.region(&region);
+        }
+        Some(provider)
+    }
+
+    /// Build a provider from the `web_identity_token_file`, `role_arn` and
+    /// `role_session_name` keys of a profile in the shared config file.
+    pub fn from_profile(profile: &str) -> Option<WebIdentityProvider> {
+        let path = config_path();
+        let (token_file, role_arn) = match (config_value(&path, profile, "web_identity_token_file"),
+                                            config_value(&path, profile, "role_arn")) {
+            (Some(file), Some(arn)) => (file, arn),
+            _ => return None,
+        };
+        let mut provider = WebIdentityProvider::new(&role_arn, &token_file);
+        if let Some(name) = config_value(&path, profile, "role_session_name") {
+            provider = provider.session_name(&name);
+        }
+        if let Some(region) = env_region().or_else(|| config_value(&path, profile, "region")) {
+            provider = provider.region(&region);
+        }
+        Some(provider)
+    }
+
+    fn fetch(&self) -> Result<Credentials, CredentialsError> {
+        let mut token = String::new();
+        try!(try!(File::open(&self.token_file)).read_to_string(&mut token));
+        let region = self.region.as_ref().map(|r| r.as_ref());
+        sts::assume_role_with_web_identity(&self.sts, region, &self.role_arn, &self.session_name,
+                                           token.trim())
+    }
+}
+
+impl ProvideCredentials for WebIdentityProvider {
+    fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        self.fetch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WebIdentityProvider;
+    use std::env;
+    use ENV_LOCK as LOCK;
+
+    #[test]
+    fn test_from_env() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_WEB_IDENTITY_TOKEN_FILE", "fixtures/web_identity_token");
+        env::set_var("AWS_ROLE_ARN", "arn:aws:iam::123456789012:role/irsa");
+        env::set_var("AWS_ROLE_SESSION_NAME", "irsa-session");
+        let provider = WebIdentityProvider::from_env();
+        env::remove_var("AWS_WEB_IDENTITY_TOKEN_FILE");
+        env::remove_var("AWS_ROLE_ARN");
+        env::remove_var("AWS_ROLE_SESSION_NAME");
+
+        let provider = provider.unwrap();
+        assert_eq!(provider.role_arn, "arn:aws:iam::123456789012:role/irsa");
+        assert_eq!(provider.token_file, "fixtures/web_identity_token");
+        assert_eq!(provider.session_name, "irsa-session")
+    }
+
+    #[test]
+    fn test_from_env_incomplete() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_ROLE_ARN", "arn:aws:iam::123456789012:role/irsa");
+        let provider = WebIdentityProvider::from_env();
+        env::remove_var("AWS_ROLE_ARN");
+        assert!(provider.is_none())
+    }
+
+    #[test]
+    fn test_from_profile() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        let provider = WebIdentityProvider::from_profile("web");
+        let missing = WebIdentityProvider::from_profile("default");
+        env::remove_var("AWS_CONFIG_FILE");
+
+        let provider = provider.unwrap();
+        assert_eq!(provider.role_arn, "arn:aws:iam::123456789012:role/web");
+        assert_eq!(provider.token_file, "fixtures/web_identity_token");
+        assert_eq!(provider.session_name, "web-session");
+        assert!(missing.is_none())
+    }
+}
@@ -0,0 +1,224 @@
+use encoding::query_string;
+
+/// A parameter of a Query protocol call, before it's flattened into keys.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Param {
+    Value(String),
+    /// A list numbered from 1, as EC2 wants them: `Name.1`, `Name.2`.
+    List(Vec<Param>),
+    /// A list wrapped in `member`, as the other Query services want them:
+    /// `Name.member.1`.
+    Members(Vec<Param>),
+    /// Nested fields: `Name.Field`.
+    Struct(Vec<(String, Param)>),
+}
+
+impl Param {
+    pub fn value(value: &str) -> Param {
+        Param::Value(value.to_string())
+    }
+
+    pub fn values(values: &[&str]) -> Param {
+        Param::List(values.iter().map(|v| Param::value(v)).collect())
+    }
+
+    pub fn fields(fields: Vec<(&str, Param)>) -> Param {
+        Param::Struct(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+}
+
+/// Conversion into a parameter, implemented by the input types of the
+/// generated clients in `aws::services`.
+pub trait ToParam {
+    fn to_param(&self) -> Param;
+}
+
+impl ToParam for String {
+    fn to_param(&self) -> Param {
+        Param::Value(self.clone())
+    }
+}
+
+impl ToParam for bool {
+    fn to_param(&self) -> Param {
+        Param::Value(self.to_string())
+    }
+}
+
+impl ToParam for i32 {
+    fn to_param(&self) -> Param {
+        Param::Value(self.to_string())
+    }
+}
+
+impl ToParam for i64 {
+    fn to_param(&self) -> Param {
+        Param::Value(self.to_string())
+    }
+}
+
+impl ToParam for f64 {
+    fn to_param(&self) -> Param {
+        Param::Value(self.to_string())
+    }
+}
+
+/// A Query protocol call: an action, the API version of the service and its parameters.
+#[derive(Clone,Debug)]
+pub struct QueryRequest {
+    pub action: String,
+    pub version: String,
+    params: Vec<(String, Param)>,
+}
+
+impl QueryRequest {
+    pub fn new(action: &str, version: &str) -> QueryRequest {
+        QueryRequest {
+            action: action.to_string(),
+            version: version.to_string(),
+            params: Vec::new(),
+        }
+    }
+
+    pub fn param(mut self, name: &str, value: &str) -> QueryRequest {
+        self.params.push((name.to_string(), Param::value(value)));
+        self
+    }
+
+    pub fn list(mut self, name: &str, values: &[&str]) -> QueryRequest {
+        self.params.push((name.to_string(), Param::values(values)));
+        self
+    }
+
+    /// Add a parameter of any shape, such as a list of `Filter` structs.
+    pub fn nested(mut self, name: &str, value: Param) -> QueryRequest {
+        self.params.push((name.to_string(), value));
+        self
+    }
+
+    /// Add every field of `input` as a parameter. Anything but a struct is
+    /// ignored, since it would have no name.
+    pub fn input<T: ToParam>(mut self, input: &T) -> QueryRequest {
+        if let Param::Struct(fields) = input.to_param() {
+            self.params.extend(fields);
+        }
+        self
+    }
+
+    /// The flattened keys and values, starting with `Action` and `Version`.
+    pub fn flatten(&self) -> Vec<(String, String)> {
+        let mut out = vec![("Action".to_string(), self.action.clone()),
+                           ("Version".to_string(), self.version.clone())];
+        for &(ref name, ref param) in self.params.iter() {
+            flatten(name, param, &mut out);
+        }
+        out
+    }
+
+    /// The form encoded body to POST.
+    pub fn body(&self) -> String {
+        query_string(&self.flatten())
+    }
+}
+
+fn flatten(prefix: &str, param: &Param, out: &mut Vec<(String, String)>) {
+    match *param {
+        Param::Value(ref value) => out.push((prefix.to_string(), value.clone())),
+        Param::List(ref items) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten(&format!("{}.{}", prefix, i + 1), item, out);
+            }
+        },
+        Param::Members(ref items) => {
+            // an empty list still has to be sent, or the service can't tell it apart from unset
+            if items.is_empty() {
+                out.push((prefix.to_string(), String::new()));
+            }
+            for (i, item) in items.iter().enumerate() {
+                flatten(&format!("{}.member.{}", prefix, i + 1), item, out);
+            }
+        },
+        Param::Struct(ref fields) => {
+            for &(ref name, ref value) in fields.iter() {
+                flatten(&format!("{}.{}", prefix, name), value, out);
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Param, QueryRequest, ToParam};
+
+    fn pairs(request: &QueryRequest) -> Vec<(String, String)> {
+        request.flatten().into_iter().skip(2).collect()
+    }
+
+    fn pair(k: &str, v: &str) -> (String, String) {
+        (k.to_string(), v.to_string())
+    }
+
+    #[test]
+    fn test_ec2_filters() {
+        let request = QueryRequest::new("DescribeInstances", "2016-11-15")
+            .list("InstanceId", &["i-1", "i-2"])
+            .nested("Filter", Param::List(vec![
+                Param::fields(vec![("Name", Param::value("instance-state-name")),
+                                   ("Value", Param::values(&["running", "stopped"]))]),
+                Param::fields(vec![("Name", Param::value("tag:Env")),
+                                   ("Value", Param::values(&["prod"]))])]));
+        assert_eq!(pairs(&request), vec![
+            pair("InstanceId.1", "i-1"),
+            pair("InstanceId.2", "i-2"),
+            pair("Filter.1.Name", "instance-state-name"),
+            pair("Filter.1.Value.1", "running"),
+            pair("Filter.1.Value.2", "stopped"),
+            pair("Filter.2.Name", "tag:Env"),
+            pair("Filter.2.Value.1", "prod")])
+    }
+
+    #[test]
+    fn test_member_lists() {
+        let request = QueryRequest::new("SetSubnets", "2015-12-01")
+            .nested("Subnets", Param::Members(vec![Param::value("subnet-1")]))
+            .nested("Tags", Param::Members(vec![
+                Param::fields(vec![("Key", Param::value("team")),
+                                   ("Value", Param::value("infra"))])]))
+            .nested("SecurityGroups", Param::Members(vec![]));
+        assert_eq!(pairs(&request), vec![
+            pair("Subnets.member.1", "subnet-1"),
+            pair("Tags.member.1.Key", "team"),
+            pair("Tags.member.1.Value", "infra"),
+            pair("SecurityGroups", "")])
+    }
+
+    struct Filter {
+        name: String,
+        values: Vec<String>,
+    }
+
+    impl ToParam for Filter {
+        fn to_param(&self) -> Param {
+            Param::fields(vec![("Name", self.name.to_param()),
+                               ("Value", Param::List(self.values.iter().map(ToParam::to_param).collect()))])
+        }
+    }
+
+    #[test]
+    fn test_input() {
+        let filter = Filter { name: "vpc-id".to_string(), values: vec!["vpc-1".to_string()] };
+        let request = QueryRequest::new("DescribeSubnets", "2016-11-15").input(&filter);
+        assert_eq!(pairs(&request), vec![pair("Name", "vpc-id"), pair("Value.1", "vpc-1")]);
+        let request = QueryRequest::new("DescribeSubnets", "2016-11-15").input(&"ignored".to_string());
+        assert!(pairs(&request).is_empty())
+    }
+
+    #[test]
+    fn test_body() {
+        let request = QueryRequest::new("DescribeInstances", "2016-11-15")
+            .param("Filter.1.Name", "tag:Name")
+            .param("Filter.1.Value.1", "web server");
+        assert_eq!(request.body(),
+                   "Action=DescribeInstances&Version=2016-11-15&Filter.1.Name=tag%3AName&Filter.1.Value.1=web%20server")
+    }
+}
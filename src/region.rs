@@ -0,0 +1,223 @@
+use std::env;
+use std::time::Duration;
+use hyper::header::Headers;
+use hyper::method::Method;
+
+use profile::profile_value;
+use transport::{HttpClient, HttpRequest, HyperClient, SharedClient, TransportOptions};
+
+const IMDS_HOST: &'static str = "http://169.254.169.254";
+
+/// Finds the region to talk to, the same way the AWS CLI does.
+///
+/// In order: a region set with `region`, `AWS_REGION`, `AWS_DEFAULT_REGION`,
+/// the profile's `region` key and finally the placement of the EC2 instance
+/// we're running on. The instance metadata lookup is skipped with `no_imds` or
+/// when `AWS_EC2_METADATA_DISABLED` is `true`.
+#[derive(Clone,Debug)]
+pub struct RegionChain {
+    region: Option<String>,
+    profile: String,
+    imds: bool,
+    client: Option<SharedClient>,
+}
+
+impl RegionChain {
+    pub fn new(profile: &str) -> RegionChain {
+        RegionChain {
+            region: None,
+            profile: profile.to_string(),
+            imds: true,
+            client: None,
+        }
+    }
+
+    pub fn region(mut self, region: &str) -> RegionChain {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    pub fn no_imds(mut self) -> RegionChain {
+        self.imds = false;
+        self
+    }
+
+    /// Ask the instance metadata service through `client` instead of a
+    /// transport set up with `imds_options`.
+    pub fn http_client(mut self, client: SharedClient) -> RegionChain {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn resolve(&self) -> Option<String> {
+        if let Some(ref region) = self.region {
+            return Some(region.clone())
+        }
+        env_region()
+            .or_else(|| profile_value(&self.profile, "region"))
+            .or_else(|| if self.imds { self.imds_region() } else { None })
+    }
+
+    fn imds_region(&self) -> Option<String> {
+        match self.client {
+            Some(ref client) if imds_enabled() => imds_region_with(&**client),
+            Some(_) => None,
+            None => imds_region(),
+        }
+    }
+}
+
+/// `AWS_REGION`, falling back to `AWS_DEFAULT_REGION`.
+pub fn env_region() -> Option<String> {
+    ["AWS_REGION", "AWS_DEFAULT_REGION"].iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|region| !region.is_empty())
+}
+
+fn imds_enabled() -> bool {
+    match env::var("AWS_EC2_METADATA_DISABLED") {
+        Ok(val) => val.to_lowercase() != "true",
+        Err(_) => true,
+    }
+}
+
+/// Region of the EC2 instance we're running on, from the instance metadata
+/// service, unless `AWS_EC2_METADATA_DISABLED` is `true`.
+///
+/// An IMDSv2 session token is asked for first, instances that only speak
+/// IMDSv1 are queried without one. As with the AWS CLI, each request gives up
+/// after a second so this doesn't hang off EC2.
+pub fn imds_region() -> Option<String> {
+    if !imds_enabled() {
+        return None
+    }
+    imds_region_with(&HyperClient::new(imds_options()))
+}
+
+/// Transport settings for the instance metadata service: a second to connect
+/// and to read, and never through a proxy.
+pub fn imds_options() -> TransportOptions {
+    TransportOptions {
+        connect_timeout: Some(Duration::from_secs(1)),
+        read_timeout: Some(Duration::from_secs(1)),
+        ca_bundle: None,
+        no_proxy: true,
+    }
+}
+
+fn imds_region_with(client: &HttpClient) -> Option<String> {
+    let fetch = |method: Method, path: &str, headers: Headers| {
+        let request = HttpRequest {
+            method: method,
+            url: format!("{}{}", IMDS_HOST, path),
+            headers: headers,
+            body: Vec::new(),
+        };
+        client.send(&request).ok()
+            .and_then(|res| if res.status == 200 { Some(res.body_str()) } else { None })
+    };
+
+    let mut token_headers = Headers::new();
+    token_headers.set_raw("X-aws-ec2-metadata-token-ttl-seconds", vec![b"21600".to_vec()]);
+    let token = fetch(Method::Put, "/latest/api/token", token_headers);
+
+    let mut headers = Headers::new();
+    if let Some(token) = token {
+        headers.set_raw("X-aws-ec2-metadata-token", vec![token.into_bytes()]);
+    }
+    debug!("Looking up the region from instance metadata");
+    fetch(Method::Get, "/latest/meta-data/placement/region", headers)
+        .map(|region| region.trim().to_string())
+        .and_then(|region| if region.is_empty() { None } else { Some(region) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{env_region, imds_region, imds_region_with, RegionChain};
+    use std::env;
+    use std::sync::Mutex;
+    use hyper::header::Headers;
+    use hyper::method::Method;
+    use error::AwsError;
+    use transport::{HttpClient, HttpRequest, HttpResponse};
+    use ENV_LOCK as LOCK;
+
+    // An instance metadata service that hands out a token and wants it back.
+    struct Imds {
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl HttpClient for Imds {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError> {
+            self.requests.lock().unwrap().push(format!("{} {}", request.method, request.url));
+            let token = request.headers.get_raw("X-aws-ec2-metadata-token").map(|v| v[0].clone());
+            let (status, body) = match request.method {
+                Method::Put => (200, "imds-token"),
+                _ if token == Some(b"imds-token".to_vec()) => (200, "eu-central-1\n"),
+                _ => (401, ""),
+            };
+            Ok(HttpResponse { status: status, headers: Headers::new(), body: body.as_bytes().to_vec() })
+        }
+    }
+
+    fn clear() {
+        env::remove_var("AWS_REGION");
+        env::remove_var("AWS_DEFAULT_REGION");
+    }
+
+    #[test]
+    fn test_explicit_region_wins() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_REGION", "us-west-2");
+        let region = RegionChain::new("default").region("ap-south-1").no_imds().resolve();
+        clear();
+        assert_eq!(region.unwrap(), "ap-south-1")
+    }
+
+    #[test]
+    fn test_env_region() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_DEFAULT_REGION", "us-east-2");
+        let fallback = env_region();
+        env::set_var("AWS_REGION", "us-west-2");
+        let preferred = env_region();
+        env::set_var("AWS_REGION", "");
+        let empty = env_region();
+        clear();
+        assert_eq!(fallback.unwrap(), "us-east-2");
+        assert_eq!(preferred.unwrap(), "us-west-2");
+        assert_eq!(empty.unwrap(), "us-east-2")
+    }
+
+    #[test]
+    fn test_profile_region() {
+        let _g = LOCK.write().unwrap();
+        clear();
+        env::set_var("AWS_CONFIG_FILE", "fixtures/config.ini");
+        env::set_var("AWS_SHARED_CREDENTIALS_FILE", "fixtures/credentials.ini");
+        let default = RegionChain::new("default").no_imds().resolve();
+        let missing = RegionChain::new("web").no_imds().resolve();
+        env::remove_var("AWS_CONFIG_FILE");
+        env::remove_var("AWS_SHARED_CREDENTIALS_FILE");
+        assert_eq!(default.unwrap(), "eu-west-1");
+        assert!(missing.is_none())
+    }
+
+    #[test]
+    fn test_imds_region() {
+        let imds = Imds { requests: Mutex::new(Vec::new()) };
+        assert_eq!(imds_region_with(&imds).unwrap(), "eu-central-1");
+        assert_eq!(*imds.requests.lock().unwrap(),
+                   vec!["PUT http://169.254.169.254/latest/api/token",
+                        "GET http://169.254.169.254/latest/meta-data/placement/region"])
+    }
+
+    #[test]
+    fn test_imds_disabled() {
+        let _g = LOCK.write().unwrap();
+        env::set_var("AWS_EC2_METADATA_DISABLED", "TRUE");
+        let region = imds_region();
+        env::remove_var("AWS_EC2_METADATA_DISABLED");
+        assert!(region.is_none())
+    }
+}
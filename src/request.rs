@@ -1,50 +1,429 @@
-use hyper::Client;
-use hyper::client::Response;
-use hyper::Result as HyperResult;
+use std::fmt;
+use std::sync::Arc;
+use openssl::crypto::hash::hash;
+use openssl::crypto::hash::Type::SHA256;
+use serialize::Decodable;
+use serialize::hex::ToHex;
+use serialize::json::{decode as decode_json, Json};
+use hyper::header::{Headers, UserAgent};
+use hyper::method::Method;
+use time::now_utc;
 use signers::sigv4::SigV4;
+use config::{Config, ConfigError};
 use credentials::Credentials;
+use endpoints::{self, Endpoint, Variant};
+use error::AwsError;
+use json::JsonRequest;
+use paginate::Paginator;
+use providers::SharedProvider;
+use query::QueryRequest;
+use rest::RestRequest;
+use retry::{RetryMode, RetryStrategy};
+use transport::{HttpRequest, HttpResponse, HyperClient, SharedClient, TransportOptions};
+use xml::{self, decode_response, QueryResponse};
 
-#[derive(Debug)]
+const FORM_CONTENT_TYPE: &'static str = "application/x-www-form-urlencoded; charset=utf-8";
+
+#[derive(Clone)]
 pub struct ApiClient {
     signer: SigV4,
-    endpoint: String
+    // `None` for calls that go out unsigned
+    provider: Option<SharedProvider>,
+    endpoint: String,
+    path: String,
+    user_agent: Option<String>,
+    client: SharedClient,
+    retry: Arc<RetryStrategy>,
 }
 
 impl ApiClient {
     pub fn new(creds: Credentials, region: &str, service: &str) -> ApiClient{
-        let sig = SigV4::new();
-        let sig = sig.credentials(creds);
-        let sig = sig.region(region);
-        let sig = sig.service(service);
+        let endpoint = endpoints::resolve(service, region, Variant::default())
+            .ok().expect("the default endpoint variant always resolves");
+        ApiClient::with_endpoint(Some(Arc::new(creds)), &endpoint)
+    }
 
-        let host = format!("{}.{}.amazonaws.com", service, region);
-        let sig = sig.header(("Host", &host));
+    /// A client using the config's region, credentials provider, endpoint
+    /// overrides and HTTP client.
+    ///
+    /// Credentials are asked of the provider for every attempt, so a
+    /// long-lived client picks up refreshed or rotated ones.
+    ///
+    /// Requests to an overridden endpoint are still signed for the region and
+    /// service, with the `Host` header and path taken from the override URL.
+    pub fn from_config(config: &Config, service: &str) -> Result<ApiClient, ConfigError> {
+        let region = match config.region {
+            Some(ref region) => region,
+            None => return Err(ConfigError::new(
+                "no region configured, set AWS_REGION or the profile's region")),
+        };
+        let mut endpoint = try!(endpoints::resolve(service, region, config.endpoint_variant()));
+        if let Some(url) = config.endpoint_for(service) {
+            endpoint.url = if url.ends_with("/") { url } else { format!("{}/", url) };
+        }
+        let mut client = ApiClient::with_endpoint(Some(config.provider()), &endpoint);
+        client.user_agent = Some(config.user_agent.clone());
+        client.client = config.http_client();
+        client.retry = config.retry_strategy();
+        Ok(client)
+    }
+
+    /// A client for `endpoint` that sends through `client`, for the credential
+    /// providers that call STS without a `Config`.
+    ///
+    /// Without a provider the calls go out unsigned, as
+    /// `AssumeRoleWithWebIdentity` is, where a token is the proof of identity.
+    pub fn for_endpoint(provider: Option<SharedProvider>, endpoint: &Endpoint,
+                        client: SharedClient) -> ApiClient {
+        let mut api = ApiClient::with_endpoint(provider, endpoint);
+        api.client = client;
+        api
+    }
+
+    fn with_endpoint(provider: Option<SharedProvider>, endpoint: &Endpoint) -> ApiClient {
+        let sig = SigV4::new();
+        let sig = sig.region(&endpoint.signing_region);
+        let sig = sig.service(&endpoint.signing_service);
+        let sig = sig.header(("Host", &endpoint.host()));
 
         ApiClient {
             signer: sig,
-            endpoint: format!("https://{}/", host)
+            provider: provider,
+            endpoint: endpoint.url.clone(),
+            path: endpoint.path(),
+            user_agent: None,
+            client: Arc::new(HyperClient::new(TransportOptions::default())),
+            retry: Arc::new(RetryStrategy::new(RetryMode::Standard, 3)),
         }
     }
 
-    pub fn get(self, action: &str) -> HyperResult<Response>{
-        let sig = self.signer.clone();
+    /// Call `action`, turning error responses into an `AwsError`.
+    ///
+    /// Throttling and transient errors are retried, see `RetryStrategy`.
+    pub fn get(self, action: &str) -> Result<HttpResponse, AwsError> {
+        self.retry.call(|| self.send(action))
+    }
+
+    /// Make a Query protocol call, sending the parameters as a form encoded POST body.
+    pub fn call(&self, request: &QueryRequest) -> Result<HttpResponse, AwsError> {
+        let body = request.body();
+        self.retry.call(|| self.post(&body, &[("Content-Type", FORM_CONTENT_TYPE)]))
+    }
+
+    /// Make an AWS JSON protocol call, with the operation in `X-Amz-Target`.
+    pub fn call_json(&self, request: &JsonRequest) -> Result<HttpResponse, AwsError> {
+        let content_type = request.content_type();
+        let headers = [("Content-Type", &content_type[..]), ("X-Amz-Target", &request.target[..])];
+        self.retry.call(|| self.post(request.payload(), &headers))
+    }
+
+    /// Make an AWS JSON protocol call and decode the response into `T`.
+    pub fn call_json_decoded<T: Decodable>(&self, request: &JsonRequest) -> Result<T, AwsError> {
+        let res = try!(self.call_json(request));
+        Ok(try!(decode_json(&res.body_str())))
+    }
+
+    /// Make a Query protocol call and decode the response into `T`, see `XmlDecoder`.
+    pub fn call_decoded<T: Decodable>(&self, request: &QueryRequest) -> Result<QueryResponse<T>, AwsError> {
+        let res = try!(self.call(request));
+        Ok(try!(decode_response(&res.body_str())))
+    }
+
+    /// Make a REST-XML or REST-JSON call.
+    ///
+    /// The request's path goes under the endpoint's, so path style S3 buckets
+    /// work against an overridden endpoint too.
+    pub fn call_rest(&self, request: &RestRequest) -> Result<HttpResponse, AwsError> {
+        let path = try!(request.path());
+        let path = format!("{}{}", self.path.trim_right_matches('/'), path);
+        self.retry.call(|| self.send_rest(request, &path))
+    }
+
+    /// Make a REST call and decode the response body into `T`, from JSON or XML
+    /// depending on its content type.
+    pub fn call_rest_decoded<T: Decodable>(&self, request: &RestRequest) -> Result<T, AwsError> {
+        let res = try!(self.call_rest(request));
+        if is_json(&res) {
+            Ok(try!(decode_json(&res.body_str())))
+        } else {
+            Ok(try!(xml::decode(&try!(xml::parse(&res.body_str())))))
+        }
+    }
+
+    /// Every page of a Query or EC2 call, decoded into `T` like `call_decoded`.
+    ///
+    /// The `output_token` of each page, such as `NextToken` or EC2's
+    /// `nextToken`, is sent as the `input_token` parameter of the next call.
+    pub fn paginate<T: Decodable + 'static>(&self, request: &QueryRequest, input_token: &str,
+                                            output_token: &str) -> Paginator<T> {
+        let (client, request) = (self.clone(), request.clone());
+        let (input_token, output_token) = (input_token.to_string(), output_token.to_string());
+        Paginator::new(move |token: Option<&str>| -> Result<(T, Option<String>), AwsError> {
+            let request = match token {
+                Some(token) => request.clone().param(&input_token, token),
+                None => request.clone(),
+            };
+            let body = try!(client.call(&request)).body_str();
+            let page = try!(decode_response(&body)).result;
+            Ok((page, try!(xml_token(&body, &output_token))))
+        })
+    }
+
+    /// Every page of an AWS JSON call, with the `output_token` field of each
+    /// page sent as the `input_token` field of the next request.
+    pub fn paginate_json<T: Decodable + 'static>(&self, request: &JsonRequest, input_token: &str,
+                                                 output_token: &str) -> Paginator<T> {
+        let (client, request) = (self.clone(), request.clone());
+        let (input_token, output_token) = (input_token.to_string(), output_token.to_string());
+        Paginator::new(move |token: Option<&str>| -> Result<(T, Option<String>), AwsError> {
+            let request = match token {
+                Some(token) => {
+                    let mut body = Json::from_str(request.payload()).unwrap_or(Json::Object(Default::default()));
+                    if let Json::Object(ref mut fields) = body {
+                        fields.insert(input_token.clone(), Json::String(token.to_string()));
+                    }
+                    request.clone().body(body)
+                },
+                None => request.clone(),
+            };
+            let body = try!(client.call_json(&request)).body_str();
+            Ok((try!(decode_json(&body)), json_token(&body, &output_token)))
+        })
+    }
+
+    /// Every page of a REST call, decoded like `call_rest_decoded`. The
+    /// `output_token` of each page, such as S3's `NextContinuationToken`, is
+    /// sent as the `input_token` query parameter of the next call.
+    pub fn paginate_rest<T: Decodable + 'static>(&self, request: &RestRequest, input_token: &str,
+                                                 output_token: &str) -> Paginator<T> {
+        let (client, request) = (self.clone(), request.clone());
+        let (input_token, output_token) = (input_token.to_string(), output_token.to_string());
+        Paginator::new(move |token: Option<&str>| -> Result<(T, Option<String>), AwsError> {
+            let request = match token {
+                Some(token) => request.clone().query(&input_token, token),
+                None => request.clone(),
+            };
+            let res = try!(client.call_rest(&request));
+            let body = res.body_str();
+            if is_json(&res) {
+                Ok((try!(decode_json(&body)), json_token(&body, &output_token)))
+            } else {
+                let page = try!(xml::decode(&try!(xml::parse(&body))));
+                Ok((page, try!(xml_token(&body, &output_token))))
+            }
+        })
+    }
+
+    fn post(&self, body: &str, headers: &[(&str, &str)]) -> Result<HttpResponse, AwsError> {
+        let sig = try!(self.signer());
+        let sig = sig.method("POST");
+        let mut sig = sig.path(&self.path);
+        for &header in headers.iter() {
+            sig = sig.header(header);
+        }
+        let sig = sig.payload(body.as_bytes());
+
+        let request = HttpRequest {
+            method: Method::Post,
+            url: self.endpoint.clone(),
+            headers: self.headers(sig),
+            body: body.as_bytes().to_vec(),
+        };
+        self.dispatch(&request)
+    }
+
+    fn send(&self, action: &str) -> Result<HttpResponse, AwsError> {
+        let sig = try!(self.signer());
         let sig = sig.method("GET");
-        let sig = sig.path("/");
+        let sig = sig.path(&self.path);
         let query = format!("Action={}&Version=2015-04-15", action);
         let sig = sig.query(&query);
         let url = format!("{}?{}", self.endpoint, query);
 
-        let headers = sig.as_headers();
-        let mut client = Client::new();
-        let res = client.get(&url).headers(headers).send();
-        res
+        let request = HttpRequest {
+            method: Method::Get,
+            url: url,
+            headers: self.headers(sig),
+            body: Vec::new(),
+        };
+        self.dispatch(&request)
+    }
+
+    fn send_rest(&self, request: &RestRequest, path: &str) -> Result<HttpResponse, AwsError> {
+        let sig = try!(self.signer());
+        let sig = sig.method(&request.method.to_string());
+        let mut sig = sig.path(path);
+        // the signer encodes the query itself
+        let params = request.query_params();
+        if !params.is_empty() {
+            sig = sig.query_pairs(&params);
+        }
+        for &(ref name, ref value) in request.headers().iter() {
+            sig = sig.header((&name[..], &value[..]));
+        }
+        // S3 wants the payload hash in a header, other services ignore it
+        let hashed = hash(SHA256, request.payload()).to_hex();
+        let sig = sig.header(("x-amz-content-sha256", &hashed)).payload(request.payload());
+
+        let origin = &self.endpoint[..self.endpoint.len() - self.path.len()];
+        let mut url = format!("{}{}", origin, path);
+        if !params.is_empty() {
+            url.push('?');
+            url.push_str(&request.query_string());
+        }
+        let request = HttpRequest {
+            method: request.method.clone(),
+            url: url,
+            headers: self.headers(sig),
+            body: request.payload().to_vec(),
+        };
+        self.dispatch(&request)
+    }
+
+    // The signer for a single attempt, with credentials fetched and the date
+    // taken now, so retries don't go out with stale ones.
+    fn signer(&self) -> Result<SigV4, AwsError> {
+        let sig = self.signer.clone().timestamp(now_utc());
+        match self.provider {
+            Some(ref provider) => Ok(sig.credentials(try!(provider.credentials()))),
+            None => Ok(sig),
+        }
+    }
+
+    fn headers(&self, sig: SigV4) -> Headers {
+        let mut headers = sig.as_headers();
+        if let Some(ref agent) = self.user_agent {
+            headers.set(UserAgent(agent.clone()));
+        }
+        headers
+    }
+
+    // Send through the transport, turning error responses into an `AwsError`.
+    fn dispatch(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError> {
+        let res = try!(self.client.send(request));
+        if !res.is_success() {
+            return Err(AwsError::from_response(res.status, &res.headers, &res.body_str()))
+        }
+        Ok(res)
+    }
+}
+
+fn is_json(res: &HttpResponse) -> bool {
+    res.header("Content-Type").map_or(false, |v| v.contains("json"))
+}
+
+// The token for the next page, directly under the root as EC2 and REST-XML
+// have it, or in the `*Result` element of a Query response.
+fn xml_token(body: &str, name: &str) -> Result<Option<String>, AwsError> {
+    let root = try!(xml::parse(body));
+    let token = root.child(name).or_else(|| xml::result_element(&root).child(name));
+    Ok(token.map(|t| t.text.clone()))
+}
+
+fn json_token(body: &str, name: &str) -> Option<String> {
+    Json::from_str(body).ok()
+        .and_then(|json| json.find(name).and_then(|t| t.as_string()).map(|t| t.to_string()))
+}
+
+impl fmt::Debug for ApiClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ApiClient")
+            .field("signer", &self.signer)
+            .field("endpoint", &self.endpoint)
+            .field("user_agent", &self.user_agent)
+            .field("max_attempts", &self.retry.max_attempts)
+            .finish()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::ApiClient;
-    use credentials::Credentials;
+    use std::sync::{Arc, Mutex};
+    use hyper::header::Headers;
+    use hyper::method::Method;
+    use serialize::json::Json;
+    use config::{test_config, Config};
+    use credentials::{Credentials, Secret};
+    use error::AwsError;
+    use json::JsonRequest;
+    use providers::{CredentialsError, ProvideCredentials};
+    use query::QueryRequest;
+    use rest::RestRequest;
+    use transport::{HttpClient, HttpRequest, HttpResponse};
+    use ENV_LOCK as LOCK;
+
+    struct Canned {
+        status: u16,
+        body: &'static str,
+        sent: Arc<Mutex<Vec<HttpRequest>>>,
+    }
+
+    impl HttpClient for Canned {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError> {
+            self.sent.lock().unwrap().push(request.clone());
+            Ok(HttpResponse {
+                status: self.status,
+                headers: Headers::new(),
+                body: self.body.as_bytes().to_vec(),
+            })
+        }
+    }
+
+    fn canned_config(status: u16, body: &'static str) -> (Config, Arc<Mutex<Vec<HttpRequest>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let config = test_config("eu-west-1")
+            .max_attempts(2)
+            .transport(Canned { status: status, body: body, sent: sent.clone() })
+            .load();
+        (config, sent)
+    }
+
+    // Answers with each of `bodies` in turn, one per page.
+    struct Pages {
+        bodies: Vec<&'static str>,
+        content_type: &'static str,
+        sent: Arc<Mutex<Vec<HttpRequest>>>,
+    }
+
+    impl HttpClient for Pages {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError> {
+            let mut sent = self.sent.lock().unwrap();
+            sent.push(request.clone());
+            let mut headers = Headers::new();
+            headers.set_raw("Content-Type", vec![self.content_type.as_bytes().to_vec()]);
+            Ok(HttpResponse {
+                status: 200,
+                headers: headers,
+                body: self.bodies[sent.len() - 1].as_bytes().to_vec(),
+            })
+        }
+    }
+
+    // Hands out a new access key on every call, like a provider that refreshed.
+    struct Rotating {
+        calls: Mutex<u32>,
+    }
+
+    impl ProvideCredentials for Rotating {
+        fn credentials(&self) -> Result<Credentials, CredentialsError> {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+            let mut creds = Credentials::new();
+            creds.key = Some(format!("AKID{}", *calls));
+            creds.secret = Some(Secret::new("secret"));
+            Ok(creds)
+        }
+    }
+
+    fn paged_client(service: &str, content_type: &'static str, bodies: Vec<&'static str>)
+                    -> (ApiClient, Arc<Mutex<Vec<HttpRequest>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let config = test_config("eu-west-1")
+            .transport(Pages { bodies: bodies, content_type: content_type, sent: sent.clone() })
+            .load();
+        (ApiClient::from_config(&config, service).unwrap(), sent)
+    }
 
     #[test]
     fn test_new_apiclient() {
@@ -55,4 +434,223 @@ mod tests {
         let client = ApiClient::new(cred, region, service);
         assert_eq!(client.endpoint, "https://ec2.eu-west-1.amazonaws.com/")
     }
+
+    #[test]
+    fn test_from_config() {
+        let _g = LOCK.read().unwrap();
+        let config = test_config("us-west-2")
+            .endpoint_url("sqs", "http://localhost:4566/sqs");
+        let client = ApiClient::from_config(&config, "ec2").unwrap();
+        let local = ApiClient::from_config(&config, "sqs").unwrap();
+        assert_eq!(client.endpoint, "https://ec2.us-west-2.amazonaws.com/");
+        assert_eq!(client.user_agent, Some(config.user_agent.clone()));
+        assert_eq!(local.endpoint, "http://localhost:4566/sqs/");
+        assert_eq!(local.path, "/sqs/");
+        assert!(format!("{:?}", local.signer).contains("localhost:4566"))
+    }
+
+    #[test]
+    fn test_global_service() {
+        let _g = LOCK.read().unwrap();
+        let cred = Credentials::new().path("fixtures/credentials.ini").load();
+        let client = ApiClient::new(cred, "eu-west-1", "iam");
+        assert_eq!(client.endpoint, "https://iam.amazonaws.com/");
+        assert!(format!("{:?}", client.signer).contains("us-east-1"))
+    }
+
+    #[test]
+    fn test_from_config_without_region() {
+        let _g = LOCK.read().unwrap();
+        let config = Config::new().no_imds();
+        assert!(ApiClient::from_config(&config, "ec2").is_err())
+    }
+
+    #[test]
+    fn test_transport() {
+        let _g = LOCK.read().unwrap();
+        let (config, sent) = canned_config(200, "<DescribeInstancesResponse/>");
+        let client = ApiClient::from_config(&config, "ec2").unwrap();
+        let res = client.call(&QueryRequest::new("DescribeInstances", "2016-11-15")).unwrap();
+        assert_eq!(res.body_str(), "<DescribeInstancesResponse/>");
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].url, "https://ec2.eu-west-1.amazonaws.com/");
+        assert_eq!(sent[0].body, b"Action=DescribeInstances&Version=2016-11-15".to_vec());
+        assert!(sent[0].headers.get_raw("Authorization").is_some())
+    }
+
+    #[test]
+    fn test_transport_errors() {
+        let _g = LOCK.read().unwrap();
+        let (config, sent) = canned_config(503, "<Response><Errors><Error><Code>Unavailable</Code>\
+                                                 <Message>try later</Message></Error></Errors></Response>");
+        let client = ApiClient::from_config(&config, "ec2").unwrap();
+        let err = client.call(&QueryRequest::new("DescribeInstances", "2016-11-15")).unwrap_err();
+        assert_eq!(err.code, "Unavailable");
+        assert_eq!(sent.lock().unwrap().len(), 2)
+    }
+
+    #[test]
+    fn test_credentials_per_attempt() {
+        let _g = LOCK.read().unwrap();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let config = test_config("eu-west-1")
+            .max_attempts(2)
+            .credentials_provider(Rotating { calls: Mutex::new(0) })
+            .transport(Canned { status: 503, body: "", sent: sent.clone() })
+            .load();
+        let client = ApiClient::from_config(&config, "ec2").unwrap();
+        assert!(client.call(&QueryRequest::new("DescribeInstances", "2016-11-15")).is_err());
+
+        let sent = sent.lock().unwrap();
+        let key = |i: usize| {
+            let header = String::from_utf8(sent[i].headers.get_raw("Authorization").unwrap()[0].clone());
+            header.unwrap().contains(&format!("Credential=AKID{}/", i + 1))
+        };
+        assert_eq!(sent.len(), 2);
+        assert!(key(0) && key(1))
+    }
+
+    #[derive(RustcDecodable)]
+    struct Region {
+        region_name: String,
+    }
+
+    #[derive(RustcDecodable)]
+    struct DescribeRegions {
+        region_info: Vec<Region>,
+    }
+
+    #[test]
+    fn test_call_decoded() {
+        let _g = LOCK.read().unwrap();
+        let (config, _) = canned_config(200, "<DescribeRegionsResponse><requestId>r-1</requestId>\
+                                              <regionInfo><item><regionName>eu-west-1</regionName></item>\
+                                              </regionInfo></DescribeRegionsResponse>");
+        let client = ApiClient::from_config(&config, "ec2").unwrap();
+        let request = QueryRequest::new("DescribeRegions", "2016-11-15");
+        let res = client.call_decoded::<DescribeRegions>(&request).unwrap();
+        assert_eq!(res.request_id.unwrap(), "r-1");
+        assert_eq!(res.result.region_info[0].region_name, "eu-west-1")
+    }
+
+    #[allow(non_snake_case)]
+    #[derive(RustcDecodable)]
+    struct ListTables {
+        TableNames: Vec<String>,
+    }
+
+    #[test]
+    fn test_call_json() {
+        let _g = LOCK.read().unwrap();
+        let (config, sent) = canned_config(200, r#"{"TableNames":["jobs","users"]}"#);
+        let client = ApiClient::from_config(&config, "dynamodb").unwrap();
+        let request = JsonRequest::new("DynamoDB_20120810", "ListTables").json_version("1.0");
+        let tables = client.call_json_decoded::<ListTables>(&request).unwrap();
+        assert_eq!(tables.TableNames, vec!["jobs".to_string(), "users".to_string()]);
+
+        let sent = sent.lock().unwrap();
+        let header = |name: &str| sent[0].headers.get_raw(name).map(|v| v[0].clone());
+        assert_eq!(header("X-Amz-Target"), Some(b"DynamoDB_20120810.ListTables".to_vec()));
+        assert_eq!(header("Content-Type"), Some(b"application/x-amz-json-1.0".to_vec()));
+        let authorization = String::from_utf8(header("Authorization").unwrap()).unwrap();
+        assert!(authorization.contains("content-type;") && authorization.contains("x-amz-target"));
+        assert_eq!(sent[0].body, b"{}".to_vec())
+    }
+
+    #[test]
+    fn test_call_json_error() {
+        let _g = LOCK.read().unwrap();
+        let (config, _) = canned_config(400, r#"{"__type":"com.amazonaws.kms#NotFoundException","message":"Key not found"}"#);
+        let client = ApiClient::from_config(&config, "kms").unwrap();
+        let err = client.call_json(&JsonRequest::new("TrentService", "DescribeKey")).unwrap_err();
+        assert_eq!(err.code, "NotFoundException");
+        assert_eq!(err.message, "Key not found")
+    }
+
+    #[allow(non_snake_case)]
+    #[derive(RustcDecodable)]
+    struct Key {
+        KeyId: String,
+    }
+
+    #[allow(non_snake_case)]
+    #[derive(RustcDecodable)]
+    struct ListKeys {
+        Keys: Vec<Key>,
+    }
+
+    #[test]
+    fn test_paginate_json() {
+        let _g = LOCK.read().unwrap();
+        let (client, sent) = paged_client("kms", "application/x-amz-json-1.1", vec![
+            r#"{"Keys":[{"KeyId":"k1"},{"KeyId":"k2"}],"NextMarker":"m/1=","Truncated":true}"#,
+            r#"{"Keys":[{"KeyId":"k3"}],"Truncated":false}"#]);
+        let request = JsonRequest::new("TrentService", "ListKeys").body(Json::from_str(r#"{"Limit":2}"#).unwrap());
+        let pages: Vec<ListKeys> = client.paginate_json(&request, "Marker", "NextMarker").map(|p| p.unwrap()).collect();
+        let keys: Vec<&str> = pages.iter().flat_map(|p| p.Keys.iter().map(|k| &k.KeyId[..])).collect();
+        assert_eq!(keys, vec!["k1", "k2", "k3"]);
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].body, br#"{"Limit":2}"#.to_vec());
+        assert_eq!(sent[1].body, br#"{"Limit":2,"Marker":"m/1="}"#.to_vec())
+    }
+
+    #[derive(RustcDecodable)]
+    struct Object {
+        key: String,
+    }
+
+    #[derive(RustcDecodable)]
+    struct ListObjects {
+        contents: Vec<Object>,
+    }
+
+    #[test]
+    fn test_paginate_rest() {
+        let _g = LOCK.read().unwrap();
+        let (client, sent) = paged_client("s3", "application/xml", vec![
+            "<ListBucketResult><Contents><Key>a</Key></Contents><Contents><Key>b</Key></Contents>\
+             <NextContinuationToken>1/a=b</NextContinuationToken></ListBucketResult>",
+            "<ListBucketResult><Contents><Key>c</Key></Contents></ListBucketResult>"]);
+        let request = RestRequest::new(Method::Get, "/{Bucket}?list-type=2").label("Bucket", "logs");
+        let pages = client.paginate_rest::<ListObjects>(&request, "continuation-token", "NextContinuationToken");
+        let keys: Vec<String> = pages.items(|p| p.contents).map(|o| o.unwrap().key).collect();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].url.ends_with("/logs?list-type=2"));
+        assert!(sent[1].url.ends_with("/logs?list-type=2&continuation-token=1%2Fa%3Db"))
+    }
+
+    #[allow(non_snake_case)]
+    #[derive(RustcDecodable)]
+    struct Function {
+        FunctionName: String,
+    }
+
+    #[allow(non_snake_case)]
+    #[derive(RustcDecodable)]
+    struct ListFunctions {
+        Functions: Vec<Function>,
+    }
+
+    #[test]
+    fn test_paginate_rest_json() {
+        let _g = LOCK.read().unwrap();
+        let (client, sent) = paged_client("lambda", "application/json", vec![
+            r#"{"Functions":[{"FunctionName":"build"}],"NextMarker":"m1"}"#,
+            r#"{"Functions":[{"FunctionName":"deploy"}],"NextMarker":null}"#]);
+        let request = RestRequest::new(Method::Get, "/2015-03-31/functions/");
+        let pages = client.paginate_rest::<ListFunctions>(&request, "Marker", "NextMarker");
+        let names: Vec<String> = pages.items(|p| p.Functions).map(|f| f.unwrap().FunctionName).collect();
+        assert_eq!(names, vec!["build", "deploy"]);
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[1].url.ends_with("/2015-03-31/functions/?Marker=m1"))
+    }
 }
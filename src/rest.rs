@@ -0,0 +1,172 @@
+use hyper::method::Method;
+use serialize::json::Json;
+
+use encoding::{query_string, uri_encode};
+use error::{AwsError, ErrorKind};
+
+/// A call to a REST-XML or REST-JSON service such as S3, Route 53 or Lambda.
+///
+/// The URI is a template from the service model, such as `/{Bucket}/{Key+}` or
+/// `/2015-03-31/functions/{FunctionName}/invocations`, optionally with fixed
+/// query parameters like `/{Bucket}?uploads`. Labels are percent encoded as a
+/// single path segment, except greedy `{Name+}` labels, which keep their `/`.
+#[derive(Clone,Debug)]
+pub struct RestRequest {
+    pub method: Method,
+    uri: String,
+    labels: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl RestRequest {
+    pub fn new(method: Method, uri: &str) -> RestRequest {
+        RestRequest {
+            method: method,
+            uri: uri.to_string(),
+            labels: Vec::new(),
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn label(mut self, name: &str, value: &str) -> RestRequest {
+        self.labels.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Add a query parameter, repeat it for list members.
+    pub fn query(mut self, name: &str, value: &str) -> RestRequest {
+        self.query.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Add a header, which is signed along with the rest of the request.
+    pub fn header(mut self, name: &str, value: &str) -> RestRequest {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Send `body` as it is, such as the contents of an S3 object.
+    pub fn body(mut self, body: &[u8], content_type: &str) -> RestRequest {
+        self.body = body.to_vec();
+        self.header("Content-Type", content_type)
+    }
+
+    pub fn xml_body(self, body: &str) -> RestRequest {
+        self.body(body.as_bytes(), "application/xml")
+    }
+
+    pub fn json_body(self, body: &Json) -> RestRequest {
+        self.body(body.to_string().as_bytes(), "application/json")
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The URI with its labels filled in and percent encoded.
+    pub fn path(&self) -> Result<String, AwsError> {
+        let template = self.uri.split('?').next().unwrap();
+        let mut path = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            path.push_str(&rest[..start]);
+            let end = try!(rest[start..].find('}').ok_or(invalid(&format!("unclosed label in {}", self.uri))));
+            let label = &rest[start + 1..start + end];
+            let greedy = label.ends_with('+');
+            let name = label.trim_right_matches('+');
+            let value = match self.labels.iter().find(|&&(ref n, _)| n == name) {
+                Some(&(_, ref value)) if !value.is_empty() => value,
+                _ => return Err(invalid(&format!("{} needs a value for {}", self.uri, name))),
+            };
+            if greedy {
+                let segments: Vec<String> = value.split('/').map(uri_encode).collect();
+                path.push_str(&segments.join("/"));
+            } else {
+                path.push_str(&uri_encode(value));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        path.push_str(rest);
+        Ok(path)
+    }
+
+    /// The query parameters, those fixed in the URI first, without any encoding.
+    pub fn query_params(&self) -> Vec<(String, String)> {
+        let mut params: Vec<(String, String)> = match self.uri.find('?') {
+            Some(i) => self.uri[i + 1..].split('&').filter(|p| !p.is_empty()).map(|p| {
+                let mut parts = p.splitn(2, '=');
+                (parts.next().unwrap().to_string(), parts.next().unwrap_or("").to_string())
+            }).collect(),
+            None => Vec::new(),
+        };
+        params.extend(self.query.iter().cloned());
+        params
+    }
+
+    /// The query string as sent, percent encoded.
+    pub fn query_string(&self) -> String {
+        query_string(&self.query_params())
+    }
+}
+
+fn invalid(message: &str) -> AwsError {
+    let mut err = AwsError::new("InvalidRequest", message, None, None);
+    err.kind = ErrorKind::Client;
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RestRequest;
+    use hyper::method::Method;
+
+    fn pair(k: &str, v: &str) -> (String, String) {
+        (k.to_string(), v.to_string())
+    }
+
+    #[test]
+    fn test_labels() {
+        let request = RestRequest::new(Method::Get, "/{Bucket}/{Key+}")
+            .label("Bucket", "my-bucket")
+            .label("Key", "photos/2016/a b+c.jpg");
+        assert_eq!(request.path().unwrap(), "/my-bucket/photos/2016/a%20b%2Bc.jpg");
+
+        let request = RestRequest::new(Method::Get, "/2015-03-31/functions/{FunctionName}/configuration")
+            .label("FunctionName", "arn:aws:lambda:us-east-1:123456789012:function:my/fn");
+        assert_eq!(request.path().unwrap(),
+                   "/2015-03-31/functions/arn%3Aaws%3Alambda%3Aus-east-1%3A123456789012%3Afunction%3Amy%2Ffn/configuration")
+    }
+
+    #[test]
+    fn test_missing_label() {
+        let request = RestRequest::new(Method::Delete, "/2013-04-01/hostedzone/{Id}");
+        assert!(request.path().is_err());
+        assert!(request.label("Id", "").path().is_err())
+    }
+
+    #[test]
+    fn test_query() {
+        let request = RestRequest::new(Method::Get, "/{Bucket}?list-type=2")
+            .label("Bucket", "logs")
+            .query("prefix", "2016/01 01")
+            .query("continuation-token", "a:b");
+        assert_eq!(request.path().unwrap(), "/logs");
+        assert_eq!(request.query_params(), vec![pair("list-type", "2"),
+                                                pair("prefix", "2016/01 01"),
+                                                pair("continuation-token", "a:b")]);
+        assert_eq!(request.query_string(), "list-type=2&prefix=2016%2F01%2001&continuation-token=a%3Ab");
+
+        let request = RestRequest::new(Method::Post, "/{Bucket}/{Key+}?uploads")
+            .label("Bucket", "b")
+            .label("Key", "k");
+        assert_eq!(request.query_params(), vec![pair("uploads", "")])
+    }
+}
@@ -0,0 +1,298 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use rand::{thread_rng, Rng};
+use time::precise_time_s;
+
+use error::AwsError;
+
+// Retry quota, as in the standard retry mode of the other SDKs
+const INITIAL_RETRY_TOKENS: u32 = 500;
+const RETRY_COST: u32 = 5;
+const TIMEOUT_RETRY_COST: u32 = 10;
+const NO_RETRY_INCREMENT: u32 = 1;
+
+// Client side rate limiting in adaptive mode, in requests per second
+const MIN_FILL_RATE: f64 = 0.5;
+const MAX_FILL_RATE: f64 = 500.0;
+const THROTTLE_BETA: f64 = 0.7;
+const SUCCESS_INCREMENT: f64 = 0.5;
+
+/// How failed requests are retried.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum RetryMode {
+    /// Retry throttling and transient errors with exponential backoff, limited
+    /// by a retry quota so an outage doesn't turn into a retry storm.
+    Standard,
+    /// Standard retries, plus slowing down the rate requests are sent at
+    /// whenever the service starts throttling.
+    Adaptive,
+}
+
+impl RetryMode {
+    pub fn parse(mode: &str) -> Option<RetryMode> {
+        match mode {
+            "standard" | "legacy" => Some(RetryMode::Standard),
+            "adaptive" => Some(RetryMode::Adaptive),
+            _ => None,
+        }
+    }
+}
+
+struct State {
+    // retry quota tokens left
+    tokens: u32,
+    // adaptive mode only: enabled once we get throttled
+    limiting: bool,
+    fill_rate: f64,
+    capacity: f64,
+    last_fill: f64,
+    // requests sent in the current second, to start limiting from the rate we were at
+    window_start: f64,
+    window_count: u32,
+}
+
+/// Decides whether and when failed calls are tried again.
+///
+/// Delays use full jitter: a random duration between zero and `base_delay`
+/// doubled for every attempt, capped at `max_backoff`. One strategy is meant to
+/// be shared by every client built from a `Config`, so they draw from the same
+/// retry quota.
+pub struct RetryStrategy {
+    pub mode: RetryMode,
+    pub max_attempts: u32,
+    base_delay: Duration,
+    max_backoff: Duration,
+    state: Mutex<State>,
+}
+
+impl RetryStrategy {
+    pub fn new(mode: RetryMode, max_attempts: u32) -> RetryStrategy {
+        RetryStrategy {
+            mode: mode,
+            max_attempts: if max_attempts == 0 { 1 } else { max_attempts },
+            base_delay: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(20),
+            state: Mutex::new(State {
+                tokens: INITIAL_RETRY_TOKENS,
+                limiting: false,
+                fill_rate: MAX_FILL_RATE,
+                capacity: 0.0,
+                last_fill: precise_time_s(),
+                window_start: precise_time_s(),
+                window_count: 0,
+            }),
+        }
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> RetryStrategy {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn max_backoff(mut self, backoff: Duration) -> RetryStrategy {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Run `attempt` until it succeeds, fails for good or runs out of attempts.
+    ///
+    /// `attempt` is called again for every try, so it should sign its request
+    /// afresh each time.
+    pub fn call<T, F>(&self, mut attempt: F) -> Result<T, AwsError>
+        where F: FnMut() -> Result<T, AwsError> {
+        let mut attempts = 0;
+        let mut last_cost = 0;
+        loop {
+            attempts += 1;
+            self.wait_for_send();
+            match attempt() {
+                Ok(result) => {
+                    self.succeeded(last_cost);
+                    return Ok(result)
+                },
+                Err(err) => {
+                    self.failed(&err);
+                    let cost = match self.retry_cost(&err, attempts) {
+                        Some(cost) => cost,
+                        None => return Err(err),
+                    };
+                    last_cost = cost;
+                    let delay = self.delay(attempts);
+                    debug!("Attempt {} failed with {}, retrying in {:?}", attempts, err.code, delay);
+                    thread::sleep(delay);
+                }
+            }
+        }
+    }
+
+    // Takes the cost of a retry from the quota, `None` if we shouldn't retry.
+    fn retry_cost(&self, err: &AwsError, attempts: u32) -> Option<u32> {
+        if attempts >= self.max_attempts || !(err.is_throttling() || err.is_transient()) {
+            return None
+        }
+        let cost = if err.status.is_none() { TIMEOUT_RETRY_COST } else { RETRY_COST };
+        let mut state = self.state.lock().unwrap();
+        if state.tokens < cost {
+            warn!("Retry quota exhausted, not retrying {}", err.code);
+            return None
+        }
+        state.tokens -= cost;
+        Some(cost)
+    }
+
+    fn delay(&self, attempts: u32) -> Duration {
+        let base = millis(self.base_delay) as f64 * 2f64.powi(attempts as i32 - 1);
+        let cap = base.min(millis(self.max_backoff) as f64);
+        Duration::from_millis((thread_rng().gen::<f64>() * cap) as u64)
+    }
+
+    // Only the cost of the retry that worked goes back, as in the other SDKs,
+    // so intermittent failures still drain the quota.
+    fn succeeded(&self, last_cost: u32) {
+        let mut state = self.state.lock().unwrap();
+        let refund = if last_cost == 0 { NO_RETRY_INCREMENT } else { last_cost };
+        state.tokens = (state.tokens + refund).min(INITIAL_RETRY_TOKENS);
+        if state.limiting {
+            state.fill_rate = (state.fill_rate + SUCCESS_INCREMENT).min(MAX_FILL_RATE);
+        }
+    }
+
+    fn failed(&self, err: &AwsError) {
+        if self.mode != RetryMode::Adaptive || !err.is_throttling() {
+            return
+        }
+        let mut state = self.state.lock().unwrap();
+        let rate = if state.limiting {
+            state.fill_rate
+        } else {
+            state.window_count as f64
+        };
+        state.limiting = true;
+        state.fill_rate = (rate * THROTTLE_BETA).max(MIN_FILL_RATE);
+        state.capacity = state.capacity.min(state.fill_rate);
+    }
+
+    // In adaptive mode, once throttled, hold requests back to the fill rate of
+    // a token bucket which shrinks on throttling and grows back on success.
+    fn wait_for_send(&self) {
+        if self.mode != RetryMode::Adaptive {
+            return
+        }
+        let mut state = self.state.lock().unwrap();
+        let now = precise_time_s();
+        if now - state.window_start >= 1.0 {
+            state.window_start = now;
+            state.window_count = 0;
+        }
+        state.window_count += 1;
+        if !state.limiting {
+            return
+        }
+
+        let elapsed = now - state.last_fill;
+        state.capacity = (state.capacity + elapsed * state.fill_rate).min(state.fill_rate.max(1.0));
+        state.last_fill = now;
+        if state.capacity < 1.0 {
+            let wait = (1.0 - state.capacity) / state.fill_rate;
+            state.capacity = 0.0;
+            state.last_fill = now + wait;
+            drop(state);
+            thread::sleep(Duration::from_millis((wait * 1000.0) as u64));
+        } else {
+            state.capacity -= 1.0;
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RetryMode, RetryStrategy, INITIAL_RETRY_TOKENS};
+    use std::cell::Cell;
+    use std::time::Duration;
+    use error::AwsError;
+
+    fn strategy(mode: RetryMode, attempts: u32) -> RetryStrategy {
+        RetryStrategy::new(mode, attempts).base_delay(Duration::from_millis(0))
+    }
+
+    fn error(code: &str, status: u16) -> AwsError {
+        AwsError::new(code, "", None, Some(status))
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let retry = strategy(RetryMode::Standard, 3);
+        let calls = Cell::new(0);
+        let result = retry.call(|| {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 { Err(error("Throttling", 400)) } else { Ok(calls.get()) }
+        });
+        assert_eq!(result.unwrap(), 3);
+        // only the retry that succeeded is refunded
+        assert_eq!(retry.state.lock().unwrap().tokens, INITIAL_RETRY_TOKENS - 5)
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let retry = strategy(RetryMode::Standard, 2);
+        let calls = Cell::new(0);
+        let result: Result<(), AwsError> = retry.call(|| {
+            calls.set(calls.get() + 1);
+            Err(error("InternalError", 500))
+        });
+        assert_eq!(result.unwrap_err().code, "InternalError");
+        assert_eq!(calls.get(), 2)
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        let retry = strategy(RetryMode::Standard, 3);
+        let calls = Cell::new(0);
+        let result: Result<(), AwsError> = retry.call(|| {
+            calls.set(calls.get() + 1);
+            Err(error("InvalidParameterValue", 400))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1)
+    }
+
+    #[test]
+    fn test_quota_runs_out() {
+        let retry = strategy(RetryMode::Standard, 3);
+        retry.state.lock().unwrap().tokens = 7;
+        let calls = Cell::new(0);
+        let result: Result<(), AwsError> = retry.call(|| {
+            calls.set(calls.get() + 1);
+            Err(error("ServiceUnavailable", 503))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 2);
+        assert_eq!(retry.state.lock().unwrap().tokens, 2)
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let retry = RetryStrategy::new(RetryMode::Standard, 10)
+            .base_delay(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300));
+        for attempt in 1..10 {
+            assert!(retry.delay(attempt) <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn test_adaptive_slows_down() {
+        let retry = strategy(RetryMode::Adaptive, 3);
+        retry.wait_for_send();
+        retry.failed(&error("Throttling", 400));
+        let throttled = retry.state.lock().unwrap().fill_rate;
+        assert!(retry.state.lock().unwrap().limiting);
+        retry.succeeded(0);
+        assert!(retry.state.lock().unwrap().fill_rate > throttled)
+    }
+}
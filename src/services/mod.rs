@@ -0,0 +1,174 @@
+//! Typed clients generated by `build.rs` from the service models in `models/`.
+//!
+//! Each service has a `Client` with a method per operation, taking and
+//! returning the structs of the model and failing with an error enum that has
+//! a variant for each error the model lists. Operations with a paginator also
+//! get a `*_pages` method returning a `Paginator` over every page, and an
+//! `*_items` one over the items of every page when there's a single list of
+//! results.
+
+pub mod ec2 {
+    include!(concat!(env!("OUT_DIR"), "/ec2.rs"));
+}
+
+pub mod s3 {
+    include!(concat!(env!("OUT_DIR"), "/s3.rs"));
+}
+
+pub mod sqs {
+    include!(concat!(env!("OUT_DIR"), "/sqs.rs"));
+}
+
+pub mod sts {
+    include!(concat!(env!("OUT_DIR"), "/sts.rs"));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use mock::{MockServer, ACCOUNT_ID};
+    use query::QueryRequest;
+    use super::{ec2, s3, sqs, sts};
+    use ENV_LOCK as LOCK;
+
+    #[test]
+    fn test_ec2_input() {
+        let input = ec2::DescribeInstancesRequest {
+            filters: vec![ec2::Filter {
+                name: Some("instance-state-name".to_string()),
+                values: vec!["running".to_string()],
+            }],
+            instance_ids: vec!["i-1".to_string()],
+            max_results: Some(5),
+            ..Default::default()
+        };
+        let request = QueryRequest::new("DescribeInstances", ec2::API_VERSION).input(&input);
+        let params: Vec<String> = request.flatten().iter().map(|&(ref k, ref v)| format!("{}={}", k, v)).collect();
+        assert_eq!(params, vec!["Action=DescribeInstances", "Version=2016-11-15",
+                                "Filter.1.Name=instance-state-name", "Filter.1.Value.1=running",
+                                "InstanceId.1=i-1", "MaxResults=5"])
+    }
+
+    #[test]
+    fn test_sts() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let client = sts::Client::from_config(&server.config()).unwrap();
+        let identity = client.get_caller_identity().unwrap();
+        assert_eq!(identity.account.unwrap(), ACCOUNT_ID);
+
+        let input = sts::AssumeRoleRequest {
+            role_arn: "arn:aws:iam::123456789012:role/deploy".to_string(),
+            role_session_name: "ci".to_string(),
+            ..Default::default()
+        };
+        let assumed = client.assume_role(&input).unwrap();
+        assert!(assumed.credentials.unwrap().access_key_id.unwrap().starts_with("ASIA"));
+        assert_eq!(assumed.assumed_role_user.unwrap().arn.unwrap(),
+                   "arn:aws:sts::123456789012:assumed-role/deploy/ci")
+    }
+
+    #[test]
+    fn test_sqs() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let client = sqs::Client::from_config(&server.config()).unwrap();
+
+        let mut attributes = BTreeMap::new();
+        attributes.insert("VisibilityTimeout".to_string(), "60".to_string());
+        let created = client.create_queue(&sqs::CreateQueueRequest {
+            queue_name: "jobs".to_string(),
+            attributes: attributes,
+        }).unwrap();
+        let url = created.queue_url.unwrap();
+
+        client.send_message(&sqs::SendMessageRequest {
+            queue_url: url.clone(),
+            message_body: "build 42".to_string(),
+            ..Default::default()
+        }).unwrap();
+        let received = client.receive_message(&sqs::ReceiveMessageRequest {
+            queue_url: url.clone(),
+            max_number_of_messages: Some(10),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(received.messages.len(), 1);
+        assert_eq!(received.messages[0].body, Some("build 42".to_string()));
+
+        let pages: Vec<sqs::ListQueuesResult> = client.list_queues_pages(&Default::default())
+            .map(|page| page.unwrap())
+            .collect();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].queue_urls, vec![url.clone()]);
+
+        let other = client.create_queue(&sqs::CreateQueueRequest { queue_name: "reports".to_string(), ..Default::default() })
+            .unwrap().queue_url.unwrap();
+        let one_by_one = sqs::ListQueuesRequest { max_results: Some(1), ..Default::default() };
+        assert_eq!(client.list_queues_pages(&one_by_one).count(), 2);
+        let urls: Vec<String> = client.list_queues_items(&one_by_one).map(|url| url.unwrap()).collect();
+        assert_eq!(urls, vec![url.clone(), other]);
+        assert_eq!(client.list_queues_items(&one_by_one).max_items(1).count(), 1);
+
+        match client.get_queue_url(&sqs::GetQueueUrlRequest { queue_name: "missing".to_string(), ..Default::default() }) {
+            Err(sqs::GetQueueUrlError::QueueDoesNotExist(err)) => assert_eq!(err.status, Some(400)),
+            other => panic!("expected QueueDoesNotExist, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_s3() {
+        let _g = LOCK.read().unwrap();
+        let server = MockServer::start().unwrap();
+        let client = s3::Client::from_config(&server.config()).unwrap();
+
+        client.create_bucket(&s3::CreateBucketRequest { bucket: "builds".to_string(), ..Default::default() }).unwrap();
+        let located = |location: &str| s3::CreateBucketRequest {
+            bucket: "archive".to_string(),
+            create_bucket_configuration: Some(s3::CreateBucketConfiguration {
+                location_constraint: Some(location.to_string()),
+            }),
+            ..Default::default()
+        };
+        match client.create_bucket(&located("us-east-1")) {
+            Err(s3::CreateBucketError::Other(err)) => assert_eq!(err.code, "InvalidLocationConstraint"),
+            other => panic!("expected InvalidLocationConstraint, got {:?}", other),
+        }
+        client.create_bucket(&located("eu-west-1")).unwrap();
+
+        let put = client.put_object(&s3::PutObjectRequest {
+            bucket: "builds".to_string(),
+            key: "logs/run 1.txt".to_string(),
+            body: Some(b"passed\xff\x00".to_vec()),
+            content_type: Some("application/octet-stream".to_string()),
+            ..Default::default()
+        }).unwrap();
+        assert!(put.e_tag.is_some());
+
+        let object = client.get_object(&s3::GetObjectRequest {
+            bucket: "builds".to_string(),
+            key: "logs/run 1.txt".to_string(),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(object.body.unwrap(), b"passed\xff\x00".to_vec());
+        assert_eq!(object.e_tag, put.e_tag);
+
+        let listed = client.list_objects_v2(&s3::ListObjectsV2Request {
+            bucket: "builds".to_string(),
+            prefix: Some("logs/".to_string()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(listed.contents.len(), 1);
+        assert_eq!(listed.contents[0].key, Some("logs/run 1.txt".to_string()));
+        assert_eq!(listed.contents[0].size, Some(8));
+
+        let buckets = client.list_buckets().unwrap().buckets;
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].name, Some("archive".to_string()));
+        assert_eq!(buckets[1].name, Some("builds".to_string()));
+
+        match client.get_object(&s3::GetObjectRequest { bucket: "builds".to_string(), key: "nope".to_string(), ..Default::default() }) {
+            Err(s3::GetObjectError::NoSuchKey(err)) => assert_eq!(err.status, Some(404)),
+            other => panic!("expected NoSuchKey, got {:?}", other),
+        }
+    }
+}
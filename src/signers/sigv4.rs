@@ -7,11 +7,11 @@ use openssl::crypto::hash::Type::SHA256;
 use serialize::hex::ToHex;
 use std::collections::BTreeMap;
 use std::collections::btree_map::Entry;
-use url::percent_encoding::{percent_encode_to, FORM_URLENCODED_ENCODE_SET};
+use encoding::{sorted_query_string, uri_encode};
 use hyper::header::Headers;
 use std::str;
 
-use credentials::Credentials;
+use credentials::{Credentials, Secret};
 
 #[derive(Clone,Debug)]
 pub struct SigV4 {
@@ -20,8 +20,8 @@ pub struct SigV4 {
     headers: BTreeMap<String, Vec<Vec<u8>>>,
     method: Option<String>,
     path: Option<String>,
-    payload: Option<String>,
-    query: Option<String>,
+    payload: Option<Vec<u8>>,
+    query: Option<Vec<(String, String)>>,
     region: Option<String>,
     service: Option<String>,
 }
@@ -65,14 +65,24 @@ impl<'a> SigV4 {
     }
 
     pub fn query(mut self, query: &str) -> SigV4 {
-        let query = String::from(query);
-        self.query = Some(query);
+        let pairs = query.split('&').map(|q| {
+            let mut parts = q.splitn(2, '=');
+            (parts.next().unwrap().to_string(), parts.next().unwrap_or("").to_string())
+        }).collect();
+        self.query = Some(pairs);
         self
     }
 
-    pub fn payload(mut self, payload: &str) -> SigV4 {
-        let payload = String::from(payload);
-        self.payload = Some(payload);
+    /// The query as unencoded name and value pairs, for values that may hold
+    /// a `&` or `=` of their own.
+    pub fn query_pairs(mut self, pairs: &[(String, String)]) -> SigV4 {
+        self.query = Some(pairs.to_vec());
+        self
+    }
+
+    /// The request body, as bytes so binary uploads sign as they are sent.
+    pub fn payload(mut self, payload: &[u8]) -> SigV4 {
+        self.payload = Some(payload.to_vec());
         self
     }
 
@@ -88,10 +98,17 @@ impl<'a> SigV4 {
         self
     }
 
+    /// Sign as of `date` rather than when the signer was created, such as for
+    /// a retry of an earlier request.
+    pub fn timestamp(mut self, date: Tm) -> SigV4 {
+        self.date = date;
+        self
+    }
+
     fn token(mut self) -> SigV4 {
         match self.credentials.clone().unwrap().token {
             Some(token) => {
-                append_header(&mut self.headers, "x-amz-security-token", token.as_ref());
+                append_header(&mut self.headers, "x-amz-security-token", token.expose());
                 self
             },
             None => self
@@ -113,13 +130,17 @@ impl<'a> SigV4 {
                self.clone().credentials.unwrap().key.unwrap(),
                cs, h, s);
 
+        // only the access key id and the signature end up in here, never the secret
         debug!("Authorization header: {:?}", auth);
         append_header(&mut self.headers, "authorization", &auth);
         self
     }
 
+    /// The headers to send. Without credentials the request goes out
+    /// unsigned, with only the date added to the headers given.
     pub fn as_headers(self) -> Headers {
-        let fin = self.date().token().authorization();
+        let sig = self.date();
+        let fin = if sig.credentials.is_some() { sig.token().authorization() } else { sig };
         let mut headers = Headers::new();
 
         for h in fin.headers {
@@ -135,8 +156,9 @@ impl<'a> SigV4 {
 
     #[allow(non_snake_case)]
     fn derived_signing_key(&self) -> Vec<u8> {
-        let kSecret = self.clone().credentials.unwrap().secret.unwrap();
-        let kDate = hmac(SHA256, format!("AWS4{}", kSecret).as_bytes(),
+        let creds = self.credentials.as_ref().unwrap();
+        let kSecret = Secret::new(format!("AWS4{}", creds.secret.as_ref().unwrap().expose()));
+        let kDate = hmac(SHA256, kSecret.expose().as_bytes(),
                 self.date.strftime("%Y%m%d").unwrap().to_string().as_bytes());
         let kRegion = hmac(SHA256, &kDate, expand_string(&self.region).as_bytes());
         let kService = hmac(SHA256, &kRegion, expand_string(&self.service).as_bytes());
@@ -163,10 +185,10 @@ impl<'a> SigV4 {
 
     fn hashed_payload(&self) -> String {
         let val = match self.payload {
-            Some(ref x) => x.to_string(),
-            None => "".to_string(),
+            Some(ref x) => &x[..],
+            None => &[][..],
         };
-        to_hexdigest(val)
+        hash(SHA256, val).to_hex()
     }
 
     fn signed_headers(&self) -> String {
@@ -199,24 +221,24 @@ impl<'a> SigV4 {
     fn canonical_query_string(&self) -> String {
         match self.query {
             None => String::new(),
-            Some(ref x) => {
-                let mut h: Vec<(&str, &str)> = Vec::new();
-                for q in x.split('&') {
-                    if q.contains('=') {
-                        let n: Vec<&str> = q.splitn(2, '=').collect();
-                        h.push((n[0], n[1]))
-                    } else {
-                        h.push((q, ""))
-                    }
-                };
-                sort_query_string(h)
-            }
+            Some(ref pairs) => sorted_query_string(pairs),
+        }
+    }
+
+    // The path comes in encoded as it is sent. S3 signs it like that, every
+    // other service encodes each segment a second time.
+    fn canonical_path(&self) -> String {
+        let path = expand_string(&self.path);
+        if self.service.as_ref().map_or(false, |s| s == "s3") {
+            return path
         }
+        let segments: Vec<String> = path.split('/').map(uri_encode).collect();
+        segments.join("/")
     }
 
     fn canonical_request(&self) -> String {
         format!("{}\n{}\n{}\n{}\n{}\n{}", expand_string(&self.method),
-                expand_string(&self.path),
+                self.canonical_path(),
                 self.canonical_query_string(),
                 self.canonical_headers(),
                 self.signed_headers(),
@@ -226,33 +248,6 @@ impl<'a> SigV4 {
 
 }
 
-fn sort_query_string(mut query: Vec<(&str, &str)>) -> String {
-    #[inline]
-    fn byte_serialize(input: &str, output: &mut String) {
-        for &byte in input.as_bytes().iter() {
-            percent_encode_to(&[byte], FORM_URLENCODED_ENCODE_SET, output)
-        }
-    }
-
-    let mut output = String::new();
-
-    query.sort_by( |a, b| a.0.cmp(b.0));
-    for item in query.iter() {
-        if output.len() > 0 {
-            output.push_str("&");
-        }
-        byte_serialize(item.0, &mut output);
-        output.push_str("=");
-        byte_serialize(item.1, &mut output);
-    }
-
-    output
-    // it would be marvelous to use the below, but the AWS SigV4 spec says space must be %20, and
-    // rust-url gives me back a +. So, roll our own for now.
-    // let qs: Vec<(String, String)> = query.iter().map(|n| (n.k.to_string(), n.v.to_string())).collect();
-    // form_urlencoded::serialize_owned(qs.as_slice())
-}
-
 fn append_header(map: &mut BTreeMap<String, Vec<Vec<u8>>>, key: &str, value: &str) {
     let k = key.to_ascii_lowercase().to_string();
 
@@ -378,6 +373,27 @@ mod tests {
         assert_eq!(sig.canonical_query_string(), "q=xyz&q=mno&q.options=abc")
     }
 
+    #[test]
+    fn test_canonical_query_pairs() {
+        let pairs = vec![("prefix".to_string(), "a&b=c".to_string()), ("list-type".to_string(), "2".to_string())];
+        let sig = SigV4::new().query_pairs(&pairs);
+        assert_eq!(sig.canonical_query_string(), "list-type=2&prefix=a%26b%3Dc")
+    }
+
+    #[test]
+    fn test_canonical_path() {
+        let path = "/2015-03-31/functions/arn%3Aaws%3Alambda%3Aus-east-1%3A123456789012%3Afunction%3Afn/invocations";
+        let sig = SigV4::new().service("lambda").path(path);
+        assert_eq!(sig.canonical_path(),
+                   "/2015-03-31/functions/arn%253Aaws%253Alambda%253Aus-east-1%253A123456789012%253Afunction%253Afn/invocations");
+
+        let sig = SigV4::new().service("s3").path("/my-bucket/a%20b%2Bc.jpg");
+        assert_eq!(sig.canonical_path(), "/my-bucket/a%20b%2Bc.jpg");
+
+        let sig = SigV4::new().service("iam").path("/");
+        assert_eq!(sig.canonical_path(), "/")
+    }
+
     #[test]
     fn test_signing_string() {
         let h = ("Content-Type", "application/x-www-form-urlencoded; charset=utf-8");
@@ -389,7 +405,7 @@ mod tests {
             path: Some("/".to_string()),
             method: Some("POST".to_string()),
             query: None,
-            payload: Some("Action=ListUsers&Version=2010-05-08".to_string()),
+            payload: Some(b"Action=ListUsers&Version=2010-05-08".to_vec()),
             date: strptime("20110909T233600Z", "%Y%m%dT%H%M%SZ").unwrap(),
             region: Some("us-east-1".to_string()),
             service: Some("iam".to_string()),
@@ -411,7 +427,7 @@ mod tests {
             path: Some("/".to_string()),
             method: Some("POST".to_string()),
             query: None,
-            payload: Some("Action=ListUsers&Version=2010-05-08".to_string()),
+            payload: Some(b"Action=ListUsers&Version=2010-05-08".to_vec()),
             credentials: None,
             date: strptime("20110909T233600Z", "%Y%m%dT%H%M%SZ").unwrap(),
             region: None,
@@ -434,7 +450,7 @@ mod tests {
     #[test]
     fn test_hashed_payload() {
         let sig = SigV4::new().
-            payload("Action=ListUsers&Version=2010-05-08");
+            payload(b"Action=ListUsers&Version=2010-05-08");
         assert_eq!(sig.hashed_payload(),
         "b6359072c78d70ebee1e81adcbab4f01bf2c23245fa365ef83fe8f1f955085e2")
     }
@@ -486,6 +502,13 @@ mod tests {
         assert_eq!(sig.headers.get("x-amz-date"), wrap_header!("20110909T233600Z"))
     }
 
+    #[test]
+    fn test_timestamp() {
+        let date = strptime("20110909T233600Z", "%Y%m%dT%H%M%SZ").unwrap();
+        let sig = SigV4::new().timestamp(date).date();
+        assert_eq!(sig.headers.get("x-amz-date"), wrap_header!("20110909T233600Z"))
+    }
+
     #[test]
     fn test_credential_scope() {
         let sig = SigV4 {
@@ -518,7 +541,7 @@ mod tests {
             path: Some("/".to_string()),
             method: Some("POST".to_string()),
             query: None,
-            payload: Some("Action=ListUsers&Version=2010-05-08".to_string()),
+            payload: Some(b"Action=ListUsers&Version=2010-05-08".to_vec()),
             credentials: None,
             date: strptime("20110909T233600Z", "%Y%m%dT%H%M%SZ").unwrap(),
             region: None,
@@ -547,7 +570,7 @@ b6359072c78d70ebee1e81adcbab4f01bf2c23245fa365ef83fe8f1f955085e2")
             path: Some("/".to_string()),
             method: Some("POST".to_string()),
             query: None,
-            payload: Some("Action=ListUsers&Version=2010-05-08".to_string()),
+            payload: Some(b"Action=ListUsers&Version=2010-05-08".to_vec()),
             date: strptime("20110909T233600Z", "%Y%m%dT%H%M%SZ").unwrap(),
             region: Some("us-east-1".to_string()),
             service: Some("iam".to_string()),
@@ -570,7 +593,7 @@ b6359072c78d70ebee1e81adcbab4f01bf2c23245fa365ef83fe8f1f955085e2")
             path: Some("/".to_string()),
             method: Some("POST".to_string()),
             query: None,
-            payload: Some("Action=ListUsers&Version=2010-05-08".to_string()),
+            payload: Some(b"Action=ListUsers&Version=2010-05-08".to_vec()),
             date: strptime("20110909T233600Z", "%Y%m%dT%H%M%SZ").unwrap(),
             region: Some("us-east-1".to_string()),
             service: Some("iam".to_string()),
@@ -592,7 +615,7 @@ b6359072c78d70ebee1e81adcbab4f01bf2c23245fa365ef83fe8f1f955085e2")
             path: Some("/".to_string()),
             method: Some("POST".to_string()),
             query: None,
-            payload: Some("Action=ListUsers&Version=2010-05-08".to_string()),
+            payload: Some(b"Action=ListUsers&Version=2010-05-08".to_vec()),
             date: strptime("20110909T233600Z", "%Y%m%dT%H%M%SZ").unwrap(),
             region: Some("us-east-1".to_string()),
             service: Some("iam".to_string()),
@@ -601,6 +624,13 @@ b6359072c78d70ebee1e81adcbab4f01bf2c23245fa365ef83fe8f1f955085e2")
         assert_eq!(sig.headers.get("authorization"), wrap_header!("AWS4-HMAC-SHA256 Credential=akid/20110909/us-east-1/iam/aws4_request, SignedHeaders=content-type;host;x-amz-date, Signature=ced6826de92d2bdeed8f846f0bf508e8559e98e4b0199114b84c54174deb456c"))
     }
 
+    #[test]
+    fn test_debug_redacts_secret() {
+        let cred = Credentials::new().path("fixtures/credentials.ini").profile("aws").load();
+        let sig = SigV4::new().credentials(cred).region("us-east-1").service("iam");
+        assert!(!format!("{:?}", sig).contains("wJalrXUtnFEMI"))
+    }
+
     #[test]
     fn test_as_headers() {
         let h = ("Content-Type", "application/x-www-form-urlencoded; charset=utf-8");
@@ -614,7 +644,7 @@ b6359072c78d70ebee1e81adcbab4f01bf2c23245fa365ef83fe8f1f955085e2")
             path: Some("/".to_string()),
             method: Some("POST".to_string()),
             query: None,
-            payload: Some("Action=ListUsers&Version=2010-05-08".to_string()),
+            payload: Some(b"Action=ListUsers&Version=2010-05-08".to_vec()),
             date: strptime("20110909T233600Z", "%Y%m%dT%H%M%SZ").unwrap(),
             region: Some("us-east-1".to_string()),
             service: Some("iam".to_string()),
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use hyper::header::Headers;
+use serialize::base64::{FromBase64, ToBase64, STANDARD};
+use serialize::json;
+
+use error::{AwsError, ErrorKind};
+use profile::write_atomic;
+use super::{HttpClient, HttpRequest, HttpResponse, SharedClient};
+
+// Presigned URLs carry the date and signature in the query instead of headers.
+const IGNORED_PARAMS: [&'static str; 4] = [
+    "X-Amz-Date", "X-Amz-Signature", "X-Amz-Credential", "X-Amz-Security-Token"];
+
+/// A request and the response it got, as saved in a cassette file.
+///
+/// Only what's matched on is kept of the request, so the file holds no
+/// signatures or session tokens. The request and response bodies are base64,
+/// so binary objects survive the round trip.
+#[derive(Clone,Debug,PartialEq,RustcDecodable,RustcEncodable)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub body: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub response: String,
+}
+
+impl Interaction {
+    fn new(request: &HttpRequest, res: &HttpResponse) -> Interaction {
+        let (path, query) = split_url(&request.url);
+        Interaction {
+            method: request.method.to_string(),
+            path: path,
+            query: query,
+            body: request.body.to_base64(STANDARD),
+            status: res.status,
+            headers: res.headers.iter().map(|h| (h.name().to_string(), h.value_string())).collect(),
+            response: res.body.to_base64(STANDARD),
+        }
+    }
+
+    fn matches(&self, request: &HttpRequest) -> bool {
+        let (path, query) = split_url(&request.url);
+        self.method == request.method.to_string() &&
+            self.path == path &&
+            self.query == query &&
+            self.body.from_base64().ok().map_or(false, |body| body == request.body)
+    }
+
+    fn to_response(&self) -> HttpResponse {
+        let mut values: BTreeMap<&str, Vec<Vec<u8>>> = BTreeMap::new();
+        for &(ref name, ref value) in self.headers.iter() {
+            values.entry(name).or_insert(Vec::new()).push(value.as_bytes().to_vec());
+        }
+        let mut headers = Headers::new();
+        for (name, value) in values.into_iter() {
+            headers.set_raw(name.to_string(), value);
+        }
+        HttpResponse {
+            status: self.status,
+            headers: headers,
+            body: self.response.from_base64().unwrap_or(Vec::new()),
+        }
+    }
+}
+
+/// A transport that records requests and responses to a file, or replays them.
+///
+/// Replayed requests match a recorded one on method, path, query string and
+/// body, so the date and signature, which change on every run, don't matter.
+/// Each recording is played back once, in order, so a cassette can hold the
+/// same call with different answers, such as an instance starting up.
+///
+/// ```ignore
+/// // once, with credentials and network
+/// let live = Config::new().http_client();
+/// let config = Config::new()
+///     .transport(Cassette::record("fixtures/cassettes/ec2.json", live))
+///     .load();
+/// // in CI
+/// let config = Config::new()
+///     .transport(try!(Cassette::replay("fixtures/cassettes/ec2.json")))
+///     .load();
+/// ```
+pub struct Cassette {
+    path: PathBuf,
+    recorder: Option<SharedClient>,
+    interactions: Mutex<Vec<(Interaction, bool)>>,
+}
+
+impl Cassette {
+    /// Send requests with `client`, saving every response to `path`.
+    pub fn record(path: &str, client: SharedClient) -> Cassette {
+        Cassette {
+            path: PathBuf::from(path),
+            recorder: Some(client),
+            interactions: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Answer requests from the cassette at `path`, without any network.
+    pub fn replay(path: &str) -> io::Result<Cassette> {
+        let mut body = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut body));
+        let bad = |e: String| io::Error::new(io::ErrorKind::InvalidData,
+                                             format!("bad cassette {}: {}", path, e));
+        let interactions: Vec<Interaction> = try!(json::decode(&body).map_err(|e| bad(e.to_string())));
+        for interaction in interactions.iter() {
+            try!(interaction.body.from_base64().map_err(|e| bad(e.to_string())));
+            try!(interaction.response.from_base64().map_err(|e| bad(e.to_string())));
+        }
+        Ok(Cassette {
+            path: PathBuf::from(path),
+            recorder: None,
+            interactions: Mutex::new(interactions.into_iter().map(|i| (i, false)).collect()),
+        })
+    }
+
+    /// Recordings that haven't been played back yet.
+    pub fn unplayed(&self) -> Vec<Interaction> {
+        let interactions = self.interactions.lock().unwrap();
+        interactions.iter().filter(|&&(_, played)| !played).map(|&(ref i, _)| i.clone()).collect()
+    }
+
+    // Rewritten through a temporary file, so an interrupted run can't leave a
+    // truncated cassette behind.
+    fn save(&self, interactions: &[(Interaction, bool)]) -> io::Result<()> {
+        let recorded: Vec<&Interaction> = interactions.iter().map(|&(ref i, _)| i).collect();
+        let body = format!("{}\n", json::as_pretty_json(&recorded));
+        write_atomic(&self.path, body.as_bytes())
+    }
+}
+
+impl HttpClient for Cassette {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError> {
+        if let Some(ref client) = self.recorder {
+            // the lock is only taken once the response is in, so recorded
+            // calls can still run in parallel
+            let res = try!(client.send(request));
+            let mut interactions = self.interactions.lock().unwrap();
+            interactions.push((Interaction::new(request, &res), true));
+            try!(self.save(&interactions));
+            return Ok(res)
+        }
+
+        let mut interactions = self.interactions.lock().unwrap();
+        match interactions.iter_mut().find(|entry| !entry.1 && entry.0.matches(request)) {
+            Some(entry) => {
+                entry.1 = true;
+                Ok(entry.0.to_response())
+            },
+            None => {
+                let (path, query) = split_url(&request.url);
+                let mut err = AwsError::new(
+                    "CassetteMismatch",
+                    &format!("no recording left in {} for {} {}?{}",
+                             self.path.display(), request.method, path, query),
+                    None, None);
+                err.kind = ErrorKind::Client;
+                Err(err)
+            },
+        }
+    }
+}
+
+// The path and the query string, sorted and without the parameters that
+// change between runs.
+fn split_url(url: &str) -> (String, String) {
+    let rest = url.splitn(2, "://").last().unwrap();
+    let rest = match rest.find('/') {
+        Some(i) => &rest[i..],
+        None => "/",
+    };
+    let mut parts = rest.splitn(2, '?');
+    let path = parts.next().unwrap().to_string();
+    let mut params: Vec<&str> = parts.next().unwrap_or("").split('&')
+        .filter(|p| !p.is_empty())
+        .filter(|p| !IGNORED_PARAMS.contains(&p.split('=').next().unwrap()))
+        .collect();
+    params.sort();
+    (path, params.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_url, Cassette};
+    use std::env;
+    use std::fs;
+    use std::sync::Arc;
+    use hyper::header::Headers;
+    use hyper::method::Method;
+    use rand::{thread_rng, Rng};
+    use config::test_config;
+    use error::{AwsError, ErrorKind};
+    use query::QueryRequest;
+    use request::ApiClient;
+    use transport::{HttpClient, HttpRequest, HttpResponse};
+    use ENV_LOCK as LOCK;
+
+    struct Fixed(&'static [u8]);
+
+    impl HttpClient for Fixed {
+        fn send(&self, _: &HttpRequest) -> Result<HttpResponse, AwsError> {
+            let mut headers = Headers::new();
+            headers.set_raw("x-amzn-RequestId", vec![b"abc-123".to_vec()]);
+            Ok(HttpResponse {
+                status: 200,
+                headers: headers,
+                body: self.0.to_vec(),
+            })
+        }
+    }
+
+    // A cassette file no other test run is using.
+    fn temp_path() -> String {
+        let name = format!("aws-rs-cassette-{:08x}.json", thread_rng().gen::<u32>());
+        env::temp_dir().join(name).to_str().unwrap().to_string()
+    }
+
+    fn client(cassette: Cassette) -> ApiClient {
+        let config = test_config("eu-west-1").transport(cassette).load();
+        ApiClient::from_config(&config, "ec2").unwrap()
+    }
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(split_url("https://s3.amazonaws.com/bucket/key?X-Amz-Date=1&b=2&a=1&X-Amz-Signature=f"),
+                   ("/bucket/key".to_string(), "a=1&b=2".to_string()));
+        assert_eq!(split_url("https://ec2.eu-west-1.amazonaws.com"), ("/".to_string(), String::new()))
+    }
+
+    #[test]
+    fn test_replay() {
+        let _g = LOCK.read().unwrap();
+        let cassette = Cassette::replay("fixtures/cassettes/describe_instances.json").unwrap();
+        let client = client(cassette);
+        let request = QueryRequest::new("DescribeInstances", "2016-11-15").list("InstanceId", &["i-1"]);
+        let res = client.call(&request).unwrap();
+        assert!(res.body_str().contains("<instanceId>i-1</instanceId>"));
+
+        // the cassette holds a single answer, so asking again fails without retrying
+        let err = client.call(&request).unwrap_err();
+        assert_eq!(err.code, "CassetteMismatch");
+        assert_eq!(err.kind, ErrorKind::Client)
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let _g = LOCK.read().unwrap();
+        let path = temp_path();
+        let request = QueryRequest::new("DescribeRegions", "2016-11-15");
+
+        let live = Arc::new(Fixed(b"<DescribeRegionsResponse/>"));
+        let recorded = client(Cassette::record(&path, live)).call(&request).unwrap();
+        let replayed = client(Cassette::replay(&path).unwrap()).call(&request).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replayed.status, recorded.status);
+        assert_eq!(replayed.body, recorded.body);
+        assert_eq!(replayed.headers.get_raw("x-amzn-RequestId").unwrap()[0], b"abc-123".to_vec())
+    }
+
+    #[test]
+    fn test_binary_body() {
+        let path = temp_path();
+        let body: &'static [u8] = b"\x89PNG\r\n\x1a\n\xff\x00";
+        let request = HttpRequest {
+            method: Method::Put,
+            url: "https://bucket.s3.amazonaws.com/image.png".to_string(),
+            headers: Headers::new(),
+            body: body.to_vec(),
+        };
+
+        let recorded = Cassette::record(&path, Arc::new(Fixed(body))).send(&request).unwrap();
+        let replayed = Cassette::replay(&path).unwrap().send(&request).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recorded.body, body.to_vec());
+        assert_eq!(replayed.body, body.to_vec())
+    }
+}
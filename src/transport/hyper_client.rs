@@ -0,0 +1,190 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use hyper::{self, Client};
+use hyper::client::pool::{Config as PoolConfig, Pool};
+use hyper::net::{NetworkConnector, NetworkStream};
+use openssl::ssl::{Ssl, SslContext, SslMethod, SslStream, SSL_VERIFY_PEER};
+
+use error::AwsError;
+use super::{HttpClient, HttpRequest, HttpResponse, TransportOptions};
+use super::proxy::Proxy;
+
+/// The default transport: hyper with pooled keep-alive connections.
+///
+/// Connections go through the proxy from `HTTPS_PROXY` or `HTTP_PROXY`, by way
+/// of a `CONNECT` tunnel, unless the host is listed in `NO_PROXY`. hyper's
+/// client can be shared between threads, so requests from clones of a `Config`
+/// go out in parallel.
+pub struct HyperClient {
+    client: Client,
+}
+
+impl HyperClient {
+    pub fn new(options: TransportOptions) -> HyperClient {
+        let connector = Connector { options: options };
+        let pool = Pool::with_connector(PoolConfig::default(), connector);
+        HyperClient { client: Client::with_connector(pool) }
+    }
+}
+
+impl HttpClient for HyperClient {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError> {
+        let mut res = try!(self.client.request(request.method.clone(), &request.url[..])
+                                .headers(request.headers.clone())
+                                .body(&request.body[..])
+                                .send());
+        let mut body = Vec::new();
+        try!(res.read_to_end(&mut body));
+        Ok(HttpResponse {
+            status: res.status.to_u16(),
+            headers: res.headers.clone(),
+            body: body,
+        })
+    }
+}
+
+struct Connector {
+    options: TransportOptions,
+}
+
+impl Connector {
+    fn tcp(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let stream = match self.options.connect_timeout {
+            Some(timeout) => {
+                let mut last = Err(io::Error::new(io::ErrorKind::Other,
+                                                  format!("no addresses found for {}", host)));
+                for addr in try!((host, port).to_socket_addrs()) {
+                    last = TcpStream::connect_timeout(&addr, timeout);
+                    if last.is_ok() {
+                        break
+                    }
+                }
+                try!(last)
+            },
+            None => try!(TcpStream::connect((host, port))),
+        };
+        try!(stream.set_read_timeout(self.options.read_timeout));
+        try!(stream.set_write_timeout(self.options.read_timeout));
+        Ok(stream)
+    }
+
+    // Built for each connection so a bad AWS_CA_BUNDLE shows up as a request error.
+    fn ssl_context(&self) -> io::Result<SslContext> {
+        let mut ctx = try!(SslContext::new(SslMethod::Sslv23).map_err(ssl_error));
+        ctx.set_verify(SSL_VERIFY_PEER, None);
+        match self.options.ca_bundle {
+            Some(ref path) => try!(ctx.set_CA_file(path).map_err(ssl_error)),
+            None => try!(ctx.set_default_verify_paths().map_err(ssl_error)),
+        }
+        Ok(ctx)
+    }
+}
+
+impl NetworkConnector for Connector {
+    type Stream = Stream;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<Stream> {
+        let proxy = if self.options.no_proxy { None } else { Proxy::from_env(scheme, host) };
+        let tcp = match proxy {
+            Some(ref proxy) => {
+                let mut tcp = try!(self.tcp(&proxy.host, proxy.port));
+                try!(tunnel(&mut tcp, host, port, proxy));
+                tcp
+            },
+            None => try!(self.tcp(host, port)),
+        };
+        if scheme != "https" {
+            return Ok(Stream::Plain(tcp))
+        }
+        let ctx = try!(self.ssl_context());
+        let mut ssl = try!(Ssl::new(&ctx).map_err(ssl_error));
+        try!(ssl.set_hostname(host).map_err(ssl_error));
+        let tls = try!(SslStream::connect(ssl, tcp).map_err(ssl_error));
+        Ok(Stream::Tls(tls))
+    }
+}
+
+fn ssl_error<E: ::std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+// Ask the proxy for a tunnel to host:port, reading its response up to the blank line.
+fn tunnel(stream: &mut TcpStream, host: &str, port: u16, proxy: &Proxy) -> io::Result<()> {
+    let mut connect = format!("CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n", host, port);
+    if let Some(ref authorization) = proxy.authorization {
+        connect.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    connect.push_str("\r\n");
+    try!(stream.write_all(connect.as_bytes()));
+
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if try!(stream.read(&mut byte)) == 0 {
+            return Err(io::Error::new(io::ErrorKind::ConnectionAborted,
+                                      "proxy closed the connection"))
+        }
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or("");
+    if status.split(' ').nth(1) != Some("200") {
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  format!("proxy refused tunnel to {}:{}: {}", host, port, status)))
+    }
+    Ok(())
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(SslStream<TcpStream>),
+}
+
+impl Stream {
+    fn tcp(&self) -> &TcpStream {
+        match *self {
+            Stream::Plain(ref s) => s,
+            Stream::Tls(ref s) => s.get_ref(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Plain(ref mut s) => s.read(buf),
+            Stream::Tls(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Plain(ref mut s) => s.write(buf),
+            Stream::Tls(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Plain(ref mut s) => s.flush(),
+            Stream::Tls(ref mut s) => s.flush(),
+        }
+    }
+}
+
+impl NetworkStream for Stream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.tcp().peer_addr()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_write_timeout(timeout)
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use hyper::header::Headers;
+use hyper::method::Method;
+
+use error::AwsError;
+
+pub mod cassette;
+pub mod hyper_client;
+pub mod proxy;
+
+pub use self::cassette::Cassette;
+pub use self::hyper_client::HyperClient;
+
+/// A request as it goes over the wire, already signed.
+#[derive(Clone,Debug)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+/// A response read in full.
+#[derive(Clone,Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn body_str(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers.get_raw(name)
+            .and_then(|v| v.first())
+            .map(|v| String::from_utf8_lossy(v).into_owned())
+    }
+
+    /// The headers starting with `prefix`, such as S3's `x-amz-meta-`, keyed
+    /// by the rest of their lowercased name.
+    pub fn prefixed_headers(&self, prefix: &str) -> BTreeMap<String, String> {
+        let prefix = prefix.to_lowercase();
+        self.headers.iter()
+            .map(|h| (h.name().to_lowercase(), h.value_string()))
+            .filter(|&(ref name, _)| name.starts_with(&prefix) && name.len() > prefix.len())
+            .map(|(name, value)| (name[prefix.len()..].to_string(), value))
+            .collect()
+    }
+}
+
+/// Sends requests for the API clients.
+///
+/// `HyperClient` is used unless `Config` is given another one, such as a test
+/// double. Failing to get a response at all should be reported as an `AwsError`
+/// without a status. It is only retried when its kind is `Transient`, as the
+/// conversion from a refused connection or a timed out `io::Error` makes it.
+pub trait HttpClient: Send + Sync {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AwsError>;
+}
+
+impl fmt::Debug for HttpClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("HttpClient")
+    }
+}
+
+pub type SharedClient = Arc<HttpClient>;
+
+/// Settings for the default transport.
+#[derive(Clone,Debug,Default)]
+pub struct TransportOptions {
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    /// PEM file with the certificates to trust instead of the system ones.
+    pub ca_bundle: Option<PathBuf>,
+    /// Skip the `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` variables.
+    pub no_proxy: bool,
+}
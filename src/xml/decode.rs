@@ -0,0 +1,640 @@
+use std::marker::PhantomData;
+
+use serialize::{Decodable, Decoder};
+use serialize::base64::FromBase64;
+
+use super::parse::{parse, read_element, Element};
+use super::reader::{Event, Reader};
+use super::XmlError;
+
+/// A decoded Query or EC2 response and the id AWS gave the request.
+#[derive(Clone,Debug)]
+pub struct QueryResponse<T> {
+    pub request_id: Option<String>,
+    pub result: T,
+}
+
+/// Decode a Query protocol response body into `T`.
+///
+/// Query services wrap the data in `<ActionResult>` next to a
+/// `<ResponseMetadata>` holding the request id, EC2 puts it straight in the
+/// response element along with a `requestId`.
+pub fn decode_response<T: Decodable>(body: &str) -> Result<QueryResponse<T>, XmlError> {
+    let root = try!(parse(body));
+    let request_id = root.child("ResponseMetadata")
+        .and_then(|m| m.child("RequestId"))
+        .or_else(|| root.child("requestId"))
+        .map(|id| id.text.clone());
+    let result = result_element(&root);
+    Ok(QueryResponse {
+        request_id: request_id,
+        result: try!(decode(result)),
+    })
+}
+
+/// The element holding the data of a Query or EC2 response.
+///
+/// That's the `<ActionResult>` inside an `<ActionResponse>` for Query services.
+/// EC2 responses, told apart by their top level `requestId`, have no such
+/// wrapper, so a field of theirs that happens to end in `Result` is left alone.
+pub fn result_element(root: &Element) -> &Element {
+    if root.child("requestId").is_some() {
+        return root
+    }
+    let action = if root.name.ends_with("Response") {
+        &root.name[..root.name.len() - "Response".len()]
+    } else {
+        &root.name[..]
+    };
+    root.child(&format!("{}Result", action)).unwrap_or(root)
+}
+
+/// Read a blob, which XML carries in base64.
+///
+/// `Vec<u8>` would decode as a list of numbers, so the generated clients read
+/// their blob members through this instead.
+pub fn read_blob<D: Decoder>(d: &mut D) -> Result<Vec<u8>, D::Error> {
+    let text = try!(d.read_str());
+    text.from_base64().map_err(|e| d.error(&format!("invalid base64: {}", e)))
+}
+
+/// Decode `element` into `T`.
+pub fn decode<T: Decodable>(element: &Element) -> Result<T, XmlError> {
+    let mut decoder = XmlDecoder { stack: vec![("response".to_string(), vec![element])] };
+    T::decode(&mut decoder)
+}
+
+/// Decode every element at the end of `path` as it's read, without building
+/// the rest of the document.
+///
+/// `path` names the innermost elements, so `&["reservationSet", "item"]`
+/// yields each reservation of a DescribeInstances response. Only one record
+/// is held as elements at a time. A malformed document ends the iteration
+/// with an error after the records read before it.
+pub fn decode_each<'a, T: Decodable>(body: &'a str, path: &[&str]) -> DecodeEach<'a, T> {
+    DecodeEach {
+        reader: Reader::new(body),
+        path: path.iter().map(|p| p.to_string()).collect(),
+        marker: PhantomData,
+    }
+}
+
+/// The records of `decode_each`.
+pub struct DecodeEach<'a, T> {
+    reader: Reader<'a>,
+    path: Vec<String>,
+    marker: PhantomData<T>,
+}
+
+impl<'a, T: Decodable> Iterator for DecodeEach<'a, T> {
+    type Item = Result<T, XmlError>;
+
+    fn next(&mut self) -> Option<Result<T, XmlError>> {
+        loop {
+            let (name, attributes) = match self.reader.next() {
+                Some(Ok(Event::Start(name, attributes))) => (name, attributes),
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => return Some(Err(err)),
+                None => return None,
+            };
+            if self.reader.path().ends_with(&self.path) {
+                let element = read_element(&mut self.reader, name, attributes);
+                return Some(element.and_then(|e| decode(&e)))
+            }
+        }
+    }
+}
+
+/// Maps elements onto `RustcDecodable` types.
+///
+/// Struct fields match child elements regardless of case and underscores, so
+/// `instance_id` reads `instanceId` or `InstanceId`, and EC2's `xxxSet` lists
+/// can be named `xxx`. Lists are read from a wrapper element of `<item>` or
+/// `<member>` children, or from repeated elements when the list is flattened.
+/// Maps are read from `<entry>` elements, or flattened ones, holding a
+/// `key`/`Name` and a `value`/`Value`. Missing lists are empty.
+///
+/// A field named with a path, like `Buckets.Bucket`, reads the `Bucket`
+/// elements inside `Buckets`, for lists wrapped in other elements.
+pub struct XmlDecoder<'a> {
+    // the field being read and the elements it matched
+    stack: Vec<(String, Vec<&'a Element>)>,
+}
+
+impl<'a> XmlDecoder<'a> {
+    fn push<T, F>(&mut self, name: &str, elements: Vec<&'a Element>, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        self.stack.push((name.to_string(), elements));
+        let result = f(self);
+        self.stack.pop();
+        result
+    }
+
+    fn elements(&self) -> Vec<&'a Element> {
+        self.stack.last().map(|&(_, ref e)| e.clone()).unwrap_or(Vec::new())
+    }
+
+    fn element(&self) -> Result<&'a Element, XmlError> {
+        match self.stack.last() {
+            Some(&(_, ref elements)) if !elements.is_empty() => Ok(elements[0]),
+            Some(&(ref name, _)) => Err(XmlError::new(&format!("missing {}", name))),
+            None => Err(XmlError::new("nothing to decode")),
+        }
+    }
+
+    fn text(&self) -> Result<String, XmlError> {
+        self.element().map(|e| e.text.clone())
+    }
+
+    fn parse<T: ::std::str::FromStr>(&self, kind: &str) -> Result<T, XmlError> {
+        let text = try!(self.text());
+        text.trim().parse().map_err(|_| {
+            XmlError::new(&format!("{} is not a valid {}: {:?}", self.stack.last().unwrap().0, kind, text))
+        })
+    }
+
+    // The elements making up a list, wrapped or flattened.
+    fn items(&self, wrappers: &[&str]) -> Vec<&'a Element> {
+        let elements = self.elements();
+        if elements.len() == 1 {
+            let wrapper = elements[0];
+            let wrapped = !wrapper.children.is_empty() &&
+                wrapper.children.iter().all(|c| wrappers.contains(&&c.name[..]));
+            if wrapped {
+                return wrapper.children.iter().collect()
+            }
+            if wrapper.children.is_empty() && wrapper.text.trim().is_empty() {
+                return Vec::new()
+            }
+        }
+        elements
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| *c != '_' && *c != '-').flat_map(|c| c.to_lowercase()).collect()
+}
+
+// EC2 names lists `instancesSet` but also `tagSet`, so `tags` has to match both ways.
+fn matches(element: &str, field: &str) -> bool {
+    let (element, field) = (normalize(element), normalize(field));
+    element == field || element == format!("{}set", field) ||
+        (field.ends_with('s') && element == format!("{}set", &field[..field.len() - 1]))
+}
+
+fn child<'a>(element: &'a Element, names: &[&str]) -> Vec<&'a Element> {
+    element.children.iter().filter(|c| names.iter().any(|n| matches(&c.name, n))).collect()
+}
+
+fn path<'a>(element: &'a Element, name: &str) -> Vec<&'a Element> {
+    let mut parts: Vec<&str> = name.split('.').collect();
+    let last = parts.pop().unwrap();
+    let mut parent = element;
+    for part in parts {
+        match child(parent, &[part]).into_iter().next() {
+            Some(next) => parent = next,
+            None => return Vec::new(),
+        }
+    }
+    child(parent, &[last])
+}
+
+impl<'a> Decoder for XmlDecoder<'a> {
+    type Error = XmlError;
+
+    fn read_nil(&mut self) -> Result<(), XmlError> {
+        Ok(())
+    }
+
+    fn read_usize(&mut self) -> Result<usize, XmlError> { self.parse("number") }
+    fn read_u64(&mut self) -> Result<u64, XmlError> { self.parse("number") }
+    fn read_u32(&mut self) -> Result<u32, XmlError> { self.parse("number") }
+    fn read_u16(&mut self) -> Result<u16, XmlError> { self.parse("number") }
+    fn read_u8(&mut self) -> Result<u8, XmlError> { self.parse("number") }
+    fn read_isize(&mut self) -> Result<isize, XmlError> { self.parse("number") }
+    fn read_i64(&mut self) -> Result<i64, XmlError> { self.parse("number") }
+    fn read_i32(&mut self) -> Result<i32, XmlError> { self.parse("number") }
+    fn read_i16(&mut self) -> Result<i16, XmlError> { self.parse("number") }
+    fn read_i8(&mut self) -> Result<i8, XmlError> { self.parse("number") }
+    fn read_f64(&mut self) -> Result<f64, XmlError> { self.parse("number") }
+    fn read_f32(&mut self) -> Result<f32, XmlError> { self.parse("number") }
+    fn read_bool(&mut self) -> Result<bool, XmlError> { self.parse("boolean") }
+
+    fn read_char(&mut self) -> Result<char, XmlError> {
+        let text = try!(self.text());
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(XmlError::new(&format!("expected a single character, found {:?}", text))),
+        }
+    }
+
+    fn read_str(&mut self) -> Result<String, XmlError> {
+        self.text()
+    }
+
+    fn read_enum<T, F>(&mut self, _: &str, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        f(self)
+    }
+
+    // Only unit variants, named like the value: `running` reads `Running`.
+    fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> Result<T, XmlError>
+        where F: FnMut(&mut XmlDecoder<'a>, usize) -> Result<T, XmlError> {
+        let text = try!(self.text());
+        match names.iter().position(|n| normalize(n) == normalize(text.trim())) {
+            Some(i) => f(self, i),
+            None => Err(XmlError::new(&format!("unknown value {:?}, expected one of {:?}", text, names))),
+        }
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _: usize, _: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        Err(XmlError::new("enum variants with fields can't be read from XML"))
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> Result<T, XmlError>
+        where F: FnMut(&mut XmlDecoder<'a>, usize) -> Result<T, XmlError> {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self, _: &str, _: usize, _: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        Err(XmlError::new("enum variants with fields can't be read from XML"))
+    }
+
+    fn read_struct<T, F>(&mut self, _: &str, _: usize, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        try!(self.element());
+        f(self)
+    }
+
+    fn read_struct_field<T, F>(&mut self, name: &str, _: usize, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        let element = try!(self.element());
+        self.push(name, path(element, name), f)
+    }
+
+    fn read_tuple<T, F>(&mut self, _: usize, _: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        Err(XmlError::new("tuples can't be read from XML"))
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, _: usize, _: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        Err(XmlError::new("tuples can't be read from XML"))
+    }
+
+    // Newtypes read their one field from the element itself.
+    fn read_tuple_struct<T, F>(&mut self, name: &str, len: usize, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        if len != 1 {
+            return Err(XmlError::new(&format!("{} has more than one field", name)))
+        }
+        f(self)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self, _: usize, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        f(self)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> Result<T, XmlError>
+        where F: FnMut(&mut XmlDecoder<'a>, bool) -> Result<T, XmlError> {
+        let present = !self.elements().is_empty();
+        f(self, present)
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>, usize) -> Result<T, XmlError> {
+        let name = self.stack.last().map(|&(ref n, _)| n.clone()).unwrap_or(String::new());
+        let items = self.items(&["item", "member"]);
+        let len = items.len();
+        self.push(&name, items, |d| f(d, len))
+    }
+
+    fn read_seq_elt<T, F>(&mut self, idx: usize, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        let item = self.elements()[idx];
+        let name = format!("{}[{}]", self.stack.last().unwrap().0, idx);
+        self.push(&name, vec![item], f)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>, usize) -> Result<T, XmlError> {
+        let name = self.stack.last().map(|&(ref n, _)| n.clone()).unwrap_or(String::new());
+        let entries = self.items(&["entry"]);
+        let len = entries.len();
+        self.push(&name, entries, |d| f(d, len))
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, idx: usize, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        let entry = self.elements()[idx];
+        let name = format!("{}[{}].key", self.stack.last().unwrap().0, idx);
+        self.push(&name, child(entry, &["key", "name"]), f)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, idx: usize, f: F) -> Result<T, XmlError>
+        where F: FnOnce(&mut XmlDecoder<'a>) -> Result<T, XmlError> {
+        let entry = self.elements()[idx];
+        let name = format!("{}[{}].value", self.stack.last().unwrap().0, idx);
+        self.push(&name, child(entry, &["value"]), f)
+    }
+
+    fn error(&mut self, err: &str) -> XmlError {
+        XmlError::new(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, decode_each, decode_response, read_blob};
+    use std::collections::BTreeMap;
+    use serialize::{Decodable, Decoder};
+    use xml::parse;
+
+    #[derive(Debug,PartialEq,RustcDecodable)]
+    enum StateName {
+        Pending,
+        Running,
+        ShuttingDown,
+        Terminated,
+        Stopping,
+        Stopped,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct InstanceState {
+        code: u16,
+        name: StateName,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct Tag {
+        key: String,
+        value: String,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct Instance {
+        instance_id: String,
+        instance_state: InstanceState,
+        private_ip_address: Option<String>,
+        ebs_optimized: bool,
+        tags: Vec<Tag>,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct Reservation {
+        reservation_id: String,
+        instances: Vec<Instance>,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct DescribeInstances {
+        reservations: Vec<Reservation>,
+        next_token: Option<String>,
+    }
+
+    #[test]
+    fn test_ec2() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<DescribeInstancesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+  <requestId>8f7724cf-496f-496e-8fe3-example</requestId>
+  <reservationSet>
+    <item>
+      <reservationId>r-1234567890abcdef0</reservationId>
+      <instancesSet>
+        <item>
+          <instanceId>i-1234567890abcdef0</instanceId>
+          <instanceState><code>16</code><name>running</name></instanceState>
+          <privateIpAddress>10.0.0.12</privateIpAddress>
+          <ebsOptimized>false</ebsOptimized>
+          <tagSet>
+            <item><key>Name</key><value>web &amp; api</value></item>
+          </tagSet>
+        </item>
+        <item>
+          <instanceId>i-0598c7d356eba48d7</instanceId>
+          <instanceState><code>80</code><name>stopped</name></instanceState>
+          <ebsOptimized>true</ebsOptimized>
+          <tagSet/>
+        </item>
+      </instancesSet>
+    </item>
+  </reservationSet>
+</DescribeInstancesResponse>"#;
+        let response = decode_response::<DescribeInstances>(body).unwrap();
+        assert_eq!(response.request_id.unwrap(), "8f7724cf-496f-496e-8fe3-example");
+        let reservation = &response.result.reservations[0];
+        assert_eq!(reservation.reservation_id, "r-1234567890abcdef0");
+        let (web, stopped) = (&reservation.instances[0], &reservation.instances[1]);
+        assert_eq!(web.instance_state.name, StateName::Running);
+        assert_eq!(web.instance_state.code, 16);
+        assert_eq!(web.private_ip_address, Some("10.0.0.12".to_string()));
+        assert_eq!(web.tags[0].value, "web & api");
+        assert_eq!(stopped.instance_state.name, StateName::Stopped);
+        assert!(stopped.ebs_optimized);
+        assert!(stopped.private_ip_address.is_none());
+        assert!(stopped.tags.is_empty());
+        assert!(response.result.next_token.is_none())
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct Message {
+        message_id: String,
+        body: String,
+        attribute: BTreeMap<String, String>,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct ReceiveMessage {
+        message: Vec<Message>,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct ListQueues {
+        queue_url: Vec<String>,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct ListUsers {
+        users: Vec<User>,
+        is_truncated: bool,
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct User {
+        user_name: String,
+    }
+
+    #[test]
+    fn test_query() {
+        let body = r#"<ReceiveMessageResponse>
+  <ReceiveMessageResult>
+    <Message>
+      <MessageId>5fea7756-0ea4-451a-a703-a558b933e274</MessageId>
+      <Body>first</Body>
+      <Attribute><Name>SenderId</Name><Value>195004372649</Value></Attribute>
+      <Attribute><Name>ApproximateReceiveCount</Name><Value>1</Value></Attribute>
+    </Message>
+    <Message>
+      <MessageId>8bd29e27-e67e-4d5f-8b2b-7a2f4c4aa1d9</MessageId>
+      <Body>second</Body>
+    </Message>
+  </ReceiveMessageResult>
+  <ResponseMetadata><RequestId>b6633655-283d-45b4-aee4-4e84e0ae6afa</RequestId></ResponseMetadata>
+</ReceiveMessageResponse>"#;
+        let response = decode_response::<ReceiveMessage>(body).unwrap();
+        assert_eq!(response.request_id.unwrap(), "b6633655-283d-45b4-aee4-4e84e0ae6afa");
+        let messages = response.result.message;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].body, "first");
+        assert_eq!(messages[0].attribute.get("SenderId").unwrap(), "195004372649");
+        assert!(messages[1].attribute.is_empty());
+
+        // one flattened element is still a list
+        let body = "<ListQueuesResponse><ListQueuesResult><QueueUrl>https://q/1/a</QueueUrl>\
+                    </ListQueuesResult></ListQueuesResponse>";
+        let queues = decode_response::<ListQueues>(body).unwrap().result;
+        assert_eq!(queues.queue_url, vec!["https://q/1/a".to_string()]);
+
+        let body = "<ListUsersResponse><ListUsersResult><Users><member><UserName>alice</UserName></member>\
+                    <member><UserName>bob</UserName></member></Users><IsTruncated>false</IsTruncated>\
+                    </ListUsersResult></ListUsersResponse>";
+        let users = decode_response::<ListUsers>(body).unwrap().result;
+        assert_eq!(users.users.len(), 2);
+        assert_eq!(users.users[1].user_name, "bob");
+        assert!(!users.is_truncated)
+    }
+
+    #[test]
+    fn test_errors() {
+        let body = "<R><RResult><Users></Users></RResult></R>";
+        let err = decode_response::<ListUsers>(body).unwrap_err();
+        assert!(err.to_string().contains("is_truncated"));
+
+        let body = "<R><RResult><IsTruncated>maybe</IsTruncated></RResult></R>";
+        assert!(decode_response::<ListUsers>(body).is_err())
+    }
+
+    struct BlobMessage {
+        body: Vec<u8>,
+        attribute: Option<Vec<u8>>,
+    }
+
+    impl Decodable for BlobMessage {
+        fn decode<D: Decoder>(d: &mut D) -> Result<BlobMessage, D::Error> {
+            d.read_struct("BlobMessage", 2, |d| {
+                Ok(BlobMessage {
+                    body: try!(d.read_struct_field("Body", 0, |d| read_blob(d))),
+                    attribute: try!(d.read_struct_field("Attribute", 1, |d| {
+                        d.read_option(|d, present| if present { read_blob(d).map(Some) } else { Ok(None) })
+                    })),
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_blob() {
+        let root = parse("<Message><Body>AP8=</Body></Message>").unwrap();
+        let message: BlobMessage = decode(&root).unwrap();
+        assert_eq!(message.body, vec![0, 255]);
+        assert_eq!(message.attribute, None);
+
+        let root = parse("<Message><Body>AP8=</Body><Attribute>not base64!</Attribute></Message>").unwrap();
+        assert!(decode::<BlobMessage>(&root).is_err())
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct ValidateTemplate {
+        validation_result: String,
+        status: String,
+    }
+
+    #[test]
+    fn test_ec2_result_field() {
+        // an EC2 field ending in Result isn't mistaken for a Query wrapper
+        let body = "<ValidateTemplateResponse><requestId>abc</requestId>\
+                    <validationResult>ok</validationResult><status>done</status>\
+                    </ValidateTemplateResponse>";
+        let response = decode_response::<ValidateTemplate>(body).unwrap();
+        assert_eq!(response.request_id.unwrap(), "abc");
+        assert_eq!(response.result.validation_result, "ok");
+        assert_eq!(response.result.status, "done")
+    }
+
+    struct ListBuckets {
+        buckets: Vec<String>,
+    }
+
+    impl Decodable for ListBuckets {
+        fn decode<D: Decoder>(d: &mut D) -> Result<ListBuckets, D::Error> {
+            d.read_struct("ListBuckets", 1, |d| {
+                Ok(ListBuckets { buckets: try!(d.read_struct_field("Buckets.Bucket", 0, |d| Decodable::decode(d))) })
+            })
+        }
+    }
+
+    #[test]
+    fn test_path() {
+        let root = parse("<ListAllMyBucketsResult><Buckets><Bucket>logs</Bucket></Buckets></ListAllMyBucketsResult>").unwrap();
+        assert_eq!(decode::<ListBuckets>(&root).unwrap().buckets, vec!["logs".to_string()]);
+        let root = parse("<ListAllMyBucketsResult><Buckets/></ListAllMyBucketsResult>").unwrap();
+        assert!(decode::<ListBuckets>(&root).unwrap().buckets.is_empty());
+        let root = parse("<ListAllMyBucketsResult/>").unwrap();
+        assert!(decode::<ListBuckets>(&root).unwrap().buckets.is_empty())
+    }
+
+    #[test]
+    fn test_decode_each() {
+        let body = r#"<DescribeInstancesResponse>
+  <requestId>8f7724cf</requestId>
+  <reservationSet>
+    <item>
+      <reservationId>r-1</reservationId>
+      <instancesSet>
+        <item>
+          <instanceId>i-1</instanceId>
+          <instanceState><code>16</code><name>running</name></instanceState>
+          <ebsOptimized>false</ebsOptimized>
+        </item>
+      </instancesSet>
+    </item>
+    <item>
+      <reservationId>r-2</reservationId>
+      <instancesSet/>
+    </item>
+  </reservationSet>
+</DescribeInstancesResponse>"#;
+        let reservations: Vec<Reservation> = decode_each(body, &["reservationSet", "item"])
+            .map(|r| r.unwrap()).collect();
+        assert_eq!(reservations.len(), 2);
+        assert_eq!(reservations[0].instances[0].instance_id, "i-1");
+        assert_eq!(reservations[1].reservation_id, "r-2");
+        assert!(reservations[1].instances.is_empty());
+
+        let body = "<ListBucketResult><Contents><Key>a</Key></Contents>\
+                    <Contents><Key>b</Key></Contents></ListBucketResult>";
+        let keys: Vec<String> = decode_each::<Object>(body, &["Contents"])
+            .map(|o| o.unwrap().key).collect();
+        assert_eq!(keys, vec!["a".to_string(), "b".to_string()])
+    }
+
+    #[derive(Debug,RustcDecodable)]
+    struct Object {
+        key: String,
+    }
+
+    #[test]
+    fn test_decode_each_malformed() {
+        // records ahead of the mistake come through before the error
+        let body = "<ListBucketResult><Contents><Key>a</Key></Contents><Contents><Key>b</Contents>";
+        let mut objects = decode_each::<Object>(body, &["Contents"]);
+        assert_eq!(objects.next().unwrap().unwrap().key, "a");
+        assert!(objects.next().unwrap().is_err());
+        assert!(objects.next().is_none())
+    }
+}
@@ -0,0 +1,114 @@
+use serialize::base64::{ToBase64, STANDARD};
+
+use super::escape;
+
+/// Writing a value as an element of a REST-XML request body, implemented by
+/// the input types of the generated clients in `aws::services`.
+///
+/// Each value writes its own tags so that structures can put their namespace
+/// and attributes in the opening one.
+pub trait ToXml {
+    fn to_xml(&self, name: &str, out: &mut String);
+}
+
+impl ToXml for String {
+    fn to_xml(&self, name: &str, out: &mut String) {
+        element(name, self, out)
+    }
+}
+
+impl ToXml for bool {
+    fn to_xml(&self, name: &str, out: &mut String) {
+        element(name, &self.to_string(), out)
+    }
+}
+
+impl ToXml for i32 {
+    fn to_xml(&self, name: &str, out: &mut String) {
+        element(name, &self.to_string(), out)
+    }
+}
+
+impl ToXml for i64 {
+    fn to_xml(&self, name: &str, out: &mut String) {
+        element(name, &self.to_string(), out)
+    }
+}
+
+impl ToXml for f64 {
+    fn to_xml(&self, name: &str, out: &mut String) {
+        element(name, &self.to_string(), out)
+    }
+}
+
+/// Blobs go in base64.
+impl ToXml for Vec<u8> {
+    fn to_xml(&self, name: &str, out: &mut String) {
+        element(name, &self.to_base64(STANDARD), out)
+    }
+}
+
+/// A leaf element holding `text`.
+pub fn element(name: &str, text: &str, out: &mut String) {
+    out.push_str(&format!("<{}>{}</{}>", name, escape(text), name));
+}
+
+/// A whole request body with `value` as its root, in the default namespace
+/// `namespace` if the model gives one.
+pub fn document<T: ToXml>(value: &T, name: &str, namespace: Option<&str>) -> String {
+    let mut body = String::new();
+    value.to_xml(name, &mut body);
+    match namespace {
+        Some(uri) => format!("<{} xmlns=\"{}\"{}", name, escape(uri), &body[1 + name.len()..]),
+        None => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{document, element, ToXml};
+
+    struct Configuration {
+        location: Option<String>,
+        tags: Vec<String>,
+    }
+
+    impl ToXml for Configuration {
+        fn to_xml(&self, name: &str, out: &mut String) {
+            out.push_str(&format!("<{}>", name));
+            if let Some(ref v) = self.location {
+                v.to_xml("LocationConstraint", out);
+            }
+            out.push_str("<Tags>");
+            for v in self.tags.iter() {
+                v.to_xml("Tag", out);
+            }
+            out.push_str("</Tags>");
+            out.push_str(&format!("</{}>", name));
+        }
+    }
+
+    #[test]
+    fn test_element() {
+        let mut out = String::new();
+        element("Key", "a<b & c", &mut out);
+        true.to_xml("Quiet", &mut out);
+        b"\x00\xff".to_vec().to_xml("Body", &mut out);
+        assert_eq!(out, "<Key>a&lt;b &amp; c</Key><Quiet>true</Quiet><Body>AP8=</Body>")
+    }
+
+    #[test]
+    fn test_document() {
+        let config = Configuration {
+            location: Some("eu-west-1".to_string()),
+            tags: vec!["a".to_string(), "b".to_string()],
+        };
+        assert_eq!(document(&config, "CreateBucketConfiguration", Some("http://s3.amazonaws.com/doc/2006-03-01/")),
+                   "<CreateBucketConfiguration xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+                    <LocationConstraint>eu-west-1</LocationConstraint><Tags><Tag>a</Tag><Tag>b</Tag></Tags>\
+                    </CreateBucketConfiguration>");
+
+        let empty = Configuration { location: None, tags: Vec::new() };
+        assert_eq!(document(&empty, "C", None), "<C><Tags></Tags></C>")
+    }
+}
@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::fmt;
+
+mod decode;
+mod encode;
+mod parse;
+mod reader;
+
+pub use self::decode::{decode, decode_each, decode_response, read_blob, result_element, DecodeEach,
+                       QueryResponse, XmlDecoder};
+pub use self::encode::{document, element, ToXml};
+pub use self::parse::{parse, read_element, Element};
+pub use self::reader::{Event, Reader};
+
+/// Text of the first element named `tag`, with the predefined entities decoded.
+///
+/// STS responses and error bodies are small and flat enough that finding the
+/// first element with a given name is all we need.
+pub fn find(body: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = match body.find(&open) {
+        Some(i) => i + open.len(),
+        None => return None,
+    };
+    body[start..].find(&close).map(|end| unescape(&body[start..start + end]))
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+        .replace("'", "&apos;")
+}
+
+pub fn unescape(text: &str) -> String {
+    let text = text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'");
+    unescape_numeric(&text).replace("&amp;", "&")
+}
+
+// Character references such as `&#13;` or `&#x2F;`, which EC2 uses for newlines in user data.
+fn unescape_numeric(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("&#") {
+        out.push_str(&rest[..start]);
+        let reference = &rest[start + 2..];
+        let decoded = reference.find(';').and_then(|end| {
+            let code = &reference[..end];
+            let value = if code.starts_with('x') || code.starts_with('X') {
+                u32::from_str_radix(&code[1..], 16).ok()
+            } else {
+                code.parse().ok()
+            };
+            value.and_then(::std::char::from_u32).map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &reference[end + 1..];
+            },
+            None => {
+                out.push_str("&#");
+                rest = reference;
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A document that isn't well formed, or doesn't fit the type it's decoded into.
+#[derive(Clone,Debug)]
+pub struct XmlError {
+    message: String,
+}
+
+impl XmlError {
+    pub fn new(message: &str) -> XmlError {
+        XmlError { message: message.to_string() }
+    }
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for XmlError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, find, unescape};
+
+    #[test]
+    fn test_find() {
+        let body = "<Error><Code>A</Code><Message>a &lt;b&gt; &amp;amp;</Message></Error>";
+        assert_eq!(find(body, "Code").unwrap(), "A");
+        assert_eq!(find(body, "Message").unwrap(), "a <b> &amp;");
+        assert!(find(body, "RequestId").is_none())
+    }
+
+    #[test]
+    fn test_escape() {
+        let text = "<a href=\"x\">Tom & Jerry's</a>";
+        assert_eq!(escape(text), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;");
+        assert_eq!(unescape(&escape(text)), text);
+        assert_eq!(unescape("a&#13;&#10;b &#x2F; &amp;#38; &#bad;"), "a\r\nb / &#38; &#bad;")
+    }
+}
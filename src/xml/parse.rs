@@ -0,0 +1,97 @@
+use super::XmlError;
+use super::reader::{Event, Reader};
+
+/// An element of a parsed document, with its namespace prefix dropped.
+///
+/// `text` is all the character data directly inside the element, which is
+/// only meaningful for leaves.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    /// The first child named `name`.
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref v)| &v[..])
+    }
+}
+
+/// Parse a whole document into its root element, built from the events of
+/// a `Reader`.
+pub fn parse(body: &str) -> Result<Element, XmlError> {
+    let mut reader = Reader::new(body);
+    // The reader hands out nothing but errors ahead of the root.
+    let root = match reader.next() {
+        Some(Ok(Event::Start(name, attributes))) => try!(read_element(&mut reader, name, attributes)),
+        Some(Err(err)) => return Err(err),
+        _ => return Err(XmlError::new("no root element")),
+    };
+    // Anything after the root is only read to catch a malformed tail.
+    for event in reader {
+        try!(event);
+    }
+    Ok(root)
+}
+
+/// Read the rest of an element whose `Start` the reader has just handed
+/// out, up to and including its `End`.
+pub fn read_element(reader: &mut Reader, name: String,
+                    attributes: Vec<(String, String)>) -> Result<Element, XmlError> {
+    let mut element = Element {
+        name: name,
+        attributes: attributes,
+        children: Vec::new(),
+        text: String::new(),
+    };
+    loop {
+        match reader.next() {
+            Some(Ok(Event::Start(name, attributes))) => {
+                let child = try!(read_element(reader, name, attributes));
+                element.children.push(child);
+            },
+            Some(Ok(Event::Text(text))) => element.text.push_str(&text),
+            Some(Ok(Event::End(_))) => return Ok(element),
+            Some(Err(err)) => return Err(err),
+            None => return Err(XmlError::new(&format!("<{}> is never closed", element.name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn test_parse() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- comment -->
+<ns:Root xmlns:ns="urn:x" kind='a > b'>
+  <Name>Tom &amp; Jerry</Name>
+  <Empty/>
+  <Script><![CDATA[<b>bold</b>]]></Script>
+</ns:Root>"#;
+        let root = parse(body).unwrap();
+        assert_eq!(root.name, "Root");
+        assert_eq!(root.attribute("kind"), Some("a > b"));
+        assert_eq!(root.children.len(), 3);
+        assert_eq!(root.child("Name").unwrap().text, "Tom & Jerry");
+        assert_eq!(root.child("Empty").unwrap().text, "");
+        assert_eq!(root.child("Script").unwrap().text, "<b>bold</b>")
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(parse("<a><b></a>").is_err());
+        assert!(parse("<a>").is_err());
+        assert!(parse("<a/><b/>").is_err());
+        assert!(parse("").is_err())
+    }
+}
@@ -0,0 +1,245 @@
+use super::{unescape, XmlError};
+
+/// What a `Reader` hands out, one per step through a document.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Event {
+    /// An opening tag and its attributes, named without the namespace prefix.
+    /// A self-closing tag is followed by its `End` straight away.
+    Start(String, Vec<(String, String)>),
+    /// Character data with the entities decoded, or a CDATA section as it is.
+    /// Whitespace between elements comes through as well.
+    Text(String),
+    End(String),
+}
+
+/// A pull parser: every call to `next` reads just far enough into the body
+/// to produce the next event.
+///
+/// This covers what AWS sends: elements, attributes, entities and CDATA.
+/// Declarations, comments and doctypes are skipped. Tags are checked to be
+/// balanced under a single root as they're read, and the reader stops after
+/// the first error.
+pub struct Reader<'a> {
+    rest: &'a str,
+    // the elements open at this point, outermost first
+    open: Vec<String>,
+    // the end of a self-closing tag, still to be handed out
+    closing: Option<String>,
+    root_seen: bool,
+    failed: bool,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(body: &'a str) -> Reader<'a> {
+        Reader {
+            rest: body,
+            open: Vec::new(),
+            closing: None,
+            root_seen: false,
+            failed: false,
+        }
+    }
+
+    /// Names of the elements the reader is inside, outermost first. Right
+    /// after a `Start` that includes the element just opened.
+    pub fn path(&self) -> &[String] {
+        &self.open
+    }
+
+    fn read(&mut self) -> Result<Option<Event>, XmlError> {
+        if let Some(name) = self.closing.take() {
+            self.open.pop();
+            return Ok(Some(Event::End(name)))
+        }
+        loop {
+            let rest = self.rest;
+            if rest.is_empty() {
+                if let Some(open) = self.open.last() {
+                    return Err(XmlError::new(&format!("<{}> is never closed", open)))
+                }
+                if !self.root_seen {
+                    return Err(XmlError::new("no root element"))
+                }
+                return Ok(None)
+            }
+
+            if rest.starts_with("<?") {
+                self.rest = try!(skip_past(rest, "?>"));
+            } else if rest.starts_with("<!--") {
+                self.rest = try!(skip_past(rest, "-->"));
+            } else if rest.starts_with("<![CDATA[") {
+                let end = try!(rest.find("]]>").ok_or(XmlError::new("unterminated CDATA section")));
+                if self.open.is_empty() {
+                    return Err(XmlError::new("CDATA outside of the root element"))
+                }
+                self.rest = &rest[end + 3..];
+                return Ok(Some(Event::Text(rest[9..end].to_string())))
+            } else if rest.starts_with("<!") {
+                self.rest = try!(skip_past(rest, ">"));
+            } else if rest.starts_with("</") {
+                let end = try!(rest.find('>').ok_or(XmlError::new("unterminated closing tag")));
+                let name = local_name(rest[2..end].trim()).to_string();
+                match self.open.pop() {
+                    Some(ref open) if *open == name => (),
+                    Some(open) => return Err(XmlError::new(
+                        &format!("expected </{}>, found </{}>", open, name))),
+                    None => return Err(XmlError::new(&format!("unexpected </{}>", name))),
+                }
+                self.rest = &rest[end + 1..];
+                return Ok(Some(Event::End(name)))
+            } else if rest.starts_with('<') {
+                let end = try!(tag_end(rest).ok_or(XmlError::new("unterminated tag")));
+                let tag = &rest[1..end];
+                let (name, attributes) = try!(open_tag(tag.trim_right_matches('/')));
+                if self.open.is_empty() {
+                    if self.root_seen {
+                        return Err(XmlError::new("more than one root element"))
+                    }
+                    self.root_seen = true;
+                }
+                self.open.push(name.clone());
+                if tag.ends_with('/') {
+                    self.closing = Some(name.clone());
+                }
+                self.rest = &rest[end + 1..];
+                return Ok(Some(Event::Start(name, attributes)))
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let text = &rest[..end];
+                self.rest = &rest[end..];
+                if !self.open.is_empty() {
+                    return Ok(Some(Event::Text(unescape(text))))
+                }
+                if !text.trim().is_empty() {
+                    return Err(XmlError::new("text outside of the root element"))
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<Event, XmlError>;
+
+    fn next(&mut self) -> Option<Result<Event, XmlError>> {
+        if self.failed {
+            return None
+        }
+        match self.read() {
+            Ok(event) => event.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            },
+        }
+    }
+}
+
+fn skip_past<'a>(rest: &'a str, end: &str) -> Result<&'a str, XmlError> {
+    match rest.find(end) {
+        Some(i) => Ok(&rest[i + end.len()..]),
+        None => Err(XmlError::new(&format!("missing {}", end))),
+    }
+}
+
+// The index of the `>` ending the tag, skipping any inside attribute values.
+fn tag_end(rest: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn open_tag(tag: &str) -> Result<(String, Vec<(String, String)>), XmlError> {
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = local_name(&tag[..name_end]).to_string();
+    if name.is_empty() {
+        return Err(XmlError::new("tag without a name"))
+    }
+
+    let mut attributes = Vec::new();
+    let mut rest = tag[name_end..].trim_left();
+    while !rest.is_empty() {
+        let eq = try!(rest.find('=').ok_or(XmlError::new(&format!("bad attribute in <{}>", tag))));
+        let attribute = rest[..eq].trim().to_string();
+        let value = rest[eq + 1..].trim_left();
+        let quote = try!(value.chars().next().ok_or(XmlError::new("attribute without a value")));
+        if quote != '"' && quote != '\'' {
+            return Err(XmlError::new(&format!("unquoted attribute {} in <{}>", attribute, name)))
+        }
+        let close = try!(value[1..].find(quote).ok_or(XmlError::new("unterminated attribute value")));
+        attributes.push((attribute, unescape(&value[1..close + 1])));
+        rest = value[close + 2..].trim_left();
+    }
+    Ok((name, attributes))
+}
+
+fn local_name(name: &str) -> &str {
+    match name.rfind(':') {
+        Some(i) => &name[i + 1..],
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Reader};
+
+    fn start(name: &str) -> Event {
+        Event::Start(name.to_string(), Vec::new())
+    }
+
+    fn text(text: &str) -> Event {
+        Event::Text(text.to_string())
+    }
+
+    fn end(name: &str) -> Event {
+        Event::End(name.to_string())
+    }
+
+    #[test]
+    fn test_events() {
+        let body = r#"<?xml version="1.0"?><ns:Root xmlns:ns="urn:x"><A>x &amp; y</A><B/><![CDATA[<c>]]></ns:Root>"#;
+        let events: Vec<Event> = Reader::new(body).map(|e| e.unwrap()).collect();
+        assert_eq!(events, vec![
+            Event::Start("Root".to_string(), vec![("xmlns:ns".to_string(), "urn:x".to_string())]),
+            start("A"), text("x & y"), end("A"),
+            start("B"), end("B"),
+            text("<c>"),
+            end("Root")])
+    }
+
+    #[test]
+    fn test_path() {
+        let mut reader = Reader::new("<a><b><c/></b></a>");
+        reader.next();
+        reader.next();
+        reader.next();
+        assert_eq!(reader.path(), &["a".to_string(), "b".to_string(), "c".to_string()]);
+        reader.next();
+        assert_eq!(reader.path(), &["a".to_string(), "b".to_string()])
+    }
+
+    #[test]
+    fn test_errors_as_read() {
+        // the events before the mistake still come through
+        let mut reader = Reader::new("<a><b></a>");
+        assert_eq!(reader.next().unwrap().unwrap(), start("a"));
+        assert_eq!(reader.next().unwrap().unwrap(), start("b"));
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+
+        let last = |body: &str| Reader::new(body).last().unwrap();
+        assert!(last("<a/><b/>").is_err());
+        assert!(last("<a>").is_err());
+        assert!(last("text<a/>").is_err());
+        assert!(Reader::new("").next().unwrap().is_err())
+    }
+}